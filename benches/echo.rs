@@ -0,0 +1,41 @@
+//! How long a script printing a line at a time takes with stdout
+//! buffered and without. Run with `cargo bench`.
+
+use std::env;
+use std::fs;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const LINES: usize = 100_000;
+const RUNS: usize = 5;
+
+/// The best of `RUNS` runs of an echo loop, after `setup`, with stdout
+/// going to a pipe that's read as it fills.
+fn time(setup: &str) -> Duration {
+    let path = env::temp_dir().join(format!("shellob-bench-{}.sh", std::process::id()));
+    fs::write(&path, format!("{}\n{}", setup, "echo a line of output\n".repeat(LINES))).unwrap();
+    let best = (0..RUNS)
+        .map(|_| {
+            let started = Instant::now();
+            let output = Command::new(env!("CARGO_BIN_EXE_shellob"))
+                .arg(&path)
+                .stdout(Stdio::piped())
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            started.elapsed()
+        })
+        .min()
+        .unwrap();
+    let _ = fs::remove_file(&path);
+    best
+}
+
+fn main() {
+    let unbuffered = time("set +o bufferoutput");
+    let buffered = time("set -o bufferoutput");
+    let rate = |elapsed: Duration| LINES as f64 / elapsed.as_secs_f64();
+    println!("{} echos, best of {}:", LINES, RUNS);
+    println!("  unbuffered  {:>10.2?}  {:>10.0} lines/s", unbuffered, rate(unbuffered));
+    println!("  buffered    {:>10.2?}  {:>10.0} lines/s", buffered, rate(buffered));
+}
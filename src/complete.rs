@@ -0,0 +1,301 @@
+//! Tab completion for the line editor: what the word before the cursor
+//! could be, given where it is on the line.
+//!
+//! The first word of a command completes to a builtin or to a program in
+//! one of PATH's directories. Reading every directory of a long PATH on
+//! each Tab would be slow, so their listings are cached and only read
+//! again once a directory has changed. Other words, and first words with
+//! a `/` in them, complete to paths, unless the command is one whose
+//! arguments are something else: `cd` and `pushd` take directories,
+//! `export` variables, `fg` and `bg` job specs, `kill` the pids of jobs,
+//! `type` and `hash` command names, and `set -o` options.
+//!
+//! Words match what starts with them, or with `set -o nocasecomplete`
+//! ignoring case, and with `set -o substringcomplete` what has them
+//! anywhere in it.
+//!
+//! When none of that turns anything up, history is searched, latest
+//! first: first for words of earlier lines that the word could be, then
+//! for whole lines that the line so far is the start of.
+//!
+//! Words are taken apart the way the shell would read them, so a word
+//! typed with quotes or backslashes completes too, and what's put in its
+//! place has its special characters escaped.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::lexer::{self, Token};
+use crate::{Options, Shell};
+
+/// Characters that end a word, besides blanks.
+const OPERATORS: &str = ";&|()<>";
+
+/// Characters that need a backslash to be taken literally in a word.
+const SPECIAL: &str = " \t\n\\'\"$`;&|()<>*?[]#!{}";
+
+/// The word before the cursor and what it could be completed to.
+pub struct Completion {
+    /// Where the word starts on the line, in characters
+    pub start: usize,
+    /// The word as the shell reads it, without quotes or backslashes
+    pub word: String,
+    /// What the whole word could be, sorted, or latest first if they
+    /// come from history. Directories end in `/`.
+    pub candidates: Vec<String>,
+    /// How much of each candidate, in bytes, is the directory it's in,
+    /// which is left out when they are listed
+    pub dir_len: usize,
+    /// Whether the candidates are text from history, to be put in as it
+    /// is rather than escaped
+    pub literal: bool,
+}
+
+impl Completion {
+    /// What a candidate is listed as.
+    pub fn shown<'a>(&self, candidate: &'a str) -> &'a str {
+        &candidate[self.dir_len..]
+    }
+}
+
+/// The programs in each directory of PATH, with when the directory was
+/// last changed as of reading it.
+#[derive(Default)]
+pub struct CommandCache {
+    dirs: HashMap<PathBuf, (SystemTime, Vec<String>)>,
+}
+
+/// Matches names against the word being completed.
+struct Matcher {
+    /// The word, in lower case if case is ignored
+    word: String,
+    ignore_case: bool,
+    substring: bool,
+}
+
+impl Matcher {
+    fn new(word: &str, options: &Options) -> Matcher {
+        let ignore_case = options.nocasecomplete;
+        let word = if ignore_case { word.to_lowercase() } else { word.to_string() };
+        Matcher { word, ignore_case, substring: options.substringcomplete }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let lower;
+        let name = match self.ignore_case {
+            true => {
+                lower = name.to_lowercase();
+                &lower
+            }
+            false => name,
+        };
+        if self.substring { name.contains(&self.word) } else { name.starts_with(&self.word) }
+    }
+}
+
+impl CommandCache {
+    /// The programs in `dir` whose names match.
+    fn matching(&mut self, dir: PathBuf, matcher: &Matcher) -> Vec<String> {
+        let Ok(modified) = fs::metadata(&dir).and_then(|meta| meta.modified()) else {
+            return Vec::new();
+        };
+        let (read, names) = self.dirs.entry(dir.clone()).or_insert_with(|| (SystemTime::UNIX_EPOCH, Vec::new()));
+        if *read != modified {
+            *names = programs(&dir);
+            *read = modified;
+        }
+        names.iter().filter(|name| matcher.matches(name)).cloned().collect()
+    }
+}
+
+/// The names of the executable files in `dir`.
+fn programs(dir: &PathBuf) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            // Following symlinks, as running the name would
+            fs::metadata(entry.path()).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        })
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+/// Completes the word that ends at `cursor` in `line`.
+pub fn complete(line: &[char], cursor: usize, shell: &Shell, cache: &mut CommandCache) -> Completion {
+    let (start, word) = current_word(&line[..cursor]);
+    let before: String = line[..start].iter().collect();
+    // The words of the command before this one
+    let words: Vec<&str> = before.rsplit(|c| ";&|(".contains(c)).next().unwrap_or("").split_whitespace().collect();
+
+    let matcher = Matcher::new(&word, &shell.options);
+    let mut candidates = BTreeSet::new();
+    let mut names = |names: &mut dyn Iterator<Item = String>| {
+        candidates.extend(names.filter(|name| matcher.matches(name)));
+    };
+    let mut dir_len = 0;
+    match words.as_slice() {
+        [] | ["type" | "hash", ..] if !word.contains('/') => {
+            names(&mut shell.commands.keys().cloned());
+            for dir in shell.parameter("PATH").unwrap_or_default().split(':').filter(|dir| !dir.is_empty()) {
+                candidates.extend(cache.matching(PathBuf::from(dir), &matcher));
+            }
+        }
+        ["export", ..] => names(&mut shell.variables.keys().cloned()),
+        ["fg" | "bg", ..] => names(&mut shell.jobs.iter().map(|job| format!("%{}", job.id))),
+        ["kill", ..] => names(&mut shell.jobs.iter().flat_map(|job| job.pids()).map(|pid| pid.to_string())),
+        ["set", .., "-o" | "+o"] => names(&mut Options::NAMES.iter().map(|name| name.to_string())),
+        words => {
+            dir_len = word.rfind('/').map_or(0, |slash| slash + 1);
+            let kind = match words {
+                [] => Kind::Program,
+                ["cd" | "pushd", ..] => Kind::Directory,
+                _ => Kind::Any,
+            };
+            let matcher = Matcher::new(&word[dir_len..], &shell.options);
+            candidates.extend(paths(shell, &word[..dir_len], &matcher, kind));
+        }
+    }
+    if candidates.is_empty() {
+        return from_history(line, cursor, start, shell.history.entries());
+    }
+    Completion { start, word, candidates: candidates.into_iter().collect(), dir_len, literal: false }
+}
+
+/// Completes the word that ends at `cursor` in `line`, which starts at
+/// `start`, to a word of an earlier line, or failing that completes the
+/// whole line to an earlier one.
+fn from_history(line: &[char], cursor: usize, start: usize, history: &[String]) -> Completion {
+    let extends = |typed: &str, text: &str| text.len() > typed.len() && text.starts_with(typed);
+    let typed: String = line[start..cursor].iter().collect();
+    if !typed.is_empty() {
+        let words = history.iter().rev().flat_map(|entry| lexer::lex(entry)).filter_map(|token| match token {
+            Token::Word(text) if extends(&typed, &text) => Some(text),
+            _ => None,
+        });
+        let candidates = unique(words);
+        if !candidates.is_empty() {
+            return Completion { start, word: typed, candidates, dir_len: 0, literal: true };
+        }
+    }
+
+    let typed: String = line[..cursor].iter().collect();
+    let candidates = match typed.trim().is_empty() {
+        true => Vec::new(),
+        false => unique(history.iter().rev().filter(|entry| extends(&typed, entry)).cloned()),
+    };
+    Completion { start: 0, word: typed, candidates, dir_len: 0, literal: true }
+}
+
+/// `items` with only the first of each.
+fn unique(items: impl Iterator<Item = String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    items.filter(|item| seen.insert(item.clone())).collect()
+}
+
+/// Which paths a word can complete to.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Any,
+    /// Executables, and directories that may have some
+    Program,
+    Directory,
+}
+
+/// Finds where the word that `line` ends with starts, skipping over
+/// quoted and escaped blanks, and returns that with the word unquoted.
+fn current_word(line: &[char]) -> (usize, String) {
+    let mut start = 0;
+    let mut quote = None;
+    let mut i = 0;
+    while i < line.len() {
+        match (quote, line[i]) {
+            (None | Some('"'), '\\') => i += 1,
+            (None, c @ ('\'' | '"')) => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, c) if c.is_whitespace() || OPERATORS.contains(c) => start = i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = line[start..].iter().copied();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None | Some('"'), '\\') => word.extend(chars.next()),
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (_, c) => word.push(c),
+        }
+    }
+    (start, word)
+}
+
+/// The paths of `kind` in `dir`, as typed, whose names match.
+fn paths(shell: &Shell, dir: &str, matcher: &Matcher, kind: Kind) -> Vec<String> {
+    let lookup = match dir.strip_prefix('~') {
+        Some(rest) => {
+            let mut chars = rest.chars().peekable();
+            let home = shell.expand_tilde(&mut chars);
+            format!("{}{}", home, chars.collect::<String>())
+        }
+        None => dir.to_string(),
+    };
+    let lookup = shell.cwd.join(if lookup.is_empty() { "." } else { &lookup });
+    let Ok(entries) = fs::read_dir(&lookup) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only if asked for
+            if !matcher.matches(&name) || (name.starts_with('.') && !matcher.word.starts_with('.')) {
+                return None;
+            }
+            let meta = fs::metadata(entry.path()).ok()?;
+            match meta.is_dir() {
+                true => Some(format!("{}{}/", dir, name)),
+                false if kind == Kind::Directory => None,
+                false if kind == Kind::Program && meta.permissions().mode() & 0o111 == 0 => None,
+                false => Some(format!("{}{}", dir, name)),
+            }
+        })
+        .collect()
+}
+
+/// `word` with backslashes before the characters the shell would take
+/// as special. A `~` starting it is left to be expanded.
+pub fn escape(word: &str) -> String {
+    let mut escaped = String::new();
+    for c in word.chars() {
+        if SPECIAL.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The longest prefix all of `candidates` share.
+pub fn common_prefix(candidates: &[String]) -> String {
+    let Some(first) = candidates.first() else {
+        return String::new();
+    };
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first.bytes().zip(candidate.bytes()).take(len).take_while(|(a, b)| a == b).count();
+    }
+    // Back off to a character boundary
+    while !first.is_char_boundary(len) {
+        len -= 1;
+    }
+    first[..len].to_string()
+}
@@ -0,0 +1,79 @@
+//! The directory stack kept by `pushd`, `popd` and `dirs`.
+
+use std::path::{Path, PathBuf};
+
+/// The directories below the working directory on the stack, top first.
+/// The working directory itself is always entry 0 of the stack as the
+/// builtins show it, so it isn't stored here.
+#[derive(Debug, Default, Clone)]
+pub struct DirStack {
+    dirs: Vec<PathBuf>,
+}
+
+impl DirStack {
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    pub fn top(&self) -> Option<&Path> {
+        self.dirs.first().map(PathBuf::as_path)
+    }
+
+    /// Pushes the directory that was left for a new one.
+    pub fn push(&mut self, dir: PathBuf) {
+        self.dirs.insert(0, dir);
+    }
+
+    /// Replaces the top entry, for `pushd` swapping it with the working
+    /// directory.
+    pub fn replace_top(&mut self, dir: PathBuf) {
+        if let Some(top) = self.dirs.first_mut() {
+            *top = dir;
+        }
+    }
+
+    /// Removes entry `index` of the whole stack, which can't be 0.
+    pub fn remove(&mut self, index: usize) -> PathBuf {
+        self.dirs.remove(index - 1)
+    }
+
+    pub fn clear(&mut self) {
+        self.dirs.clear();
+    }
+
+    /// Resolves `+N` (counting from the top, the working directory being
+    /// `+0`) or `-N` (counting from the bottom) to an index into the whole
+    /// stack. `None` if `arg` isn't an index or is out of range.
+    pub fn index(&self, arg: &str) -> Option<usize> {
+        let len = self.dirs.len() + 1;
+        let n: usize = arg.get(1..)?.parse().ok()?;
+        match arg.chars().next()? {
+            '+' if n < len => Some(n),
+            '-' if n < len => Some(len - 1 - n),
+            _ => None,
+        }
+    }
+
+    /// The whole stack with the working directory `cwd` on top.
+    pub fn entries<'a>(&'a self, cwd: &'a Path) -> impl Iterator<Item = &'a Path> {
+        std::iter::once(cwd).chain(self.dirs.iter().map(PathBuf::as_path))
+    }
+
+    /// Rotates the whole stack so entry `index` is on top, and returns
+    /// that entry, the new working directory. The rest stays stored.
+    pub fn rotate(&mut self, cwd: &Path, index: usize) -> PathBuf {
+        let mut all: Vec<PathBuf> = self.entries(cwd).map(Path::to_path_buf).collect();
+        all.rotate_left(index);
+        self.dirs = all.split_off(1);
+        all.remove(0)
+    }
+}
+
+/// `dir` as `dirs` shows it, with the home directory abbreviated to `~`.
+pub fn abbreviate(dir: &Path, home: Option<&str>) -> String {
+    match home.filter(|home| !home.is_empty()).and_then(|home| dir.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => dir.display().to_string(),
+    }
+}
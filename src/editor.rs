@@ -0,0 +1,1423 @@
+//! The line editor used when stdin is a terminal. The terminal is put in
+//! raw mode while a line is typed, so the shell sees every key and
+//! redraws the line itself, letting it be edited anywhere rather than
+//! only erased from the end. Keys work as in Emacs by default, or as in
+//! vi with `set -o vi`.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use crate::complete::{self, CommandCache};
+use crate::highlight;
+use crate::input::{self, Line};
+use crate::keymap::{Binding, Keymap};
+use crate::prompt;
+use crate::signals;
+use crate::sys::{self, Termios};
+use crate::unicode;
+use crate::Shell;
+
+/// How long to wait after an Escape for the rest of a key's sequence
+/// before taking it as the Escape key itself, in milliseconds
+const ESCAPE_TIMEOUT: i32 = 50;
+
+const BRACKETED_PASTE_ON: &[u8] = b"\x1b[?2004h";
+const BRACKETED_PASTE_OFF: &[u8] = b"\x1b[?2004l";
+/// What the terminal sends after pasted text
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Moves the cursor to the top left and clears the screen below it
+pub const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+
+/// The most rows of completions shown at once
+const MENU_ROWS: usize = 10;
+
+/// A key as read from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    /// A control character, named by the key typed with Ctrl
+    Ctrl(char),
+    /// A key typed with Alt, or after Escape
+    Alt(char),
+    AltBackspace,
+    Escape,
+    Enter,
+    Tab,
+    /// Shift-Tab
+    BackTab,
+    /// The start of pasted text, which `read_paste` reads
+    Paste,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    /// An escape sequence the editor doesn't know
+    Unknown,
+}
+
+/// What a key does, named as in readline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    AcceptLine,
+    BeginningOfLine,
+    EndOfLine,
+    ForwardChar,
+    BackwardChar,
+    ForwardWord,
+    BackwardWord,
+    /// Deletes the character under the cursor, or ends the input if the
+    /// line is empty
+    DeleteCharOrEof,
+    DeleteChar,
+    BackwardDeleteChar,
+    KillLine,
+    UnixLineDiscard,
+    UnixWordRubout,
+    KillWord,
+    BackwardKillWord,
+    Yank,
+    PreviousHistory,
+    NextHistory,
+    /// Searches back through history as the search string is typed
+    ReverseSearchHistory,
+    /// Leaves vi's insert mode for its command mode
+    ViCommandMode,
+    /// Completes the word before the cursor
+    Complete,
+    /// Clears the screen and draws the line again at the top
+    ClearScreen,
+    /// Puts the line back as it was before the last change
+    Undo,
+    /// Makes the last change undone again
+    Redo,
+}
+
+/// Keeps the terminal in raw mode, without echo or line buffering, until
+/// dropped. Signals stay on, so Ctrl-C still interrupts. Bracketed paste
+/// is on too, so pasted text comes marked and can be told from typing.
+struct RawMode {
+    saved: Termios,
+    raw: Termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let saved = sys::tcgetattr(0)?;
+        let mut raw = saved;
+        raw.c_lflag &= !(sys::ICANON | sys::ECHO | sys::IEXTEN);
+        raw.c_iflag &= !sys::IXON;
+        raw.c_cc[sys::VMIN] = 1;
+        raw.c_cc[sys::VTIME] = 0;
+        let mode = RawMode { saved, raw };
+        mode.resume()?;
+        Ok(mode)
+    }
+
+    /// Puts the terminal back in raw mode after `suspend`.
+    fn resume(&self) -> io::Result<()> {
+        sys::tcsetattr(0, &self.raw)?;
+        let mut out = io::stdout();
+        out.write_all(BRACKETED_PASTE_ON)?;
+        out.flush()
+    }
+
+    /// Puts the terminal back as it was for a while, for a command to
+    /// run.
+    fn suspend(&self) -> io::Result<()> {
+        let mut out = io::stdout();
+        out.write_all(BRACKETED_PASTE_OFF)?;
+        out.flush()?;
+        sys::tcsetattr(0, &self.saved)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = self.suspend();
+    }
+}
+
+/// Reads one byte from the terminal. Ctrl-C is an `Interrupted` error,
+/// and the terminal going away an `UnexpectedEof` one.
+fn read_byte() -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    loop {
+        match sys::read(0, &mut byte) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => return Ok(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                if signals::take_interrupt() {
+                    return Err(e);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The key `bytes` are, if they are exactly one, for `bind`.
+pub fn parse_key(bytes: &[u8]) -> Option<Key> {
+    let mut bytes = bytes.iter();
+    match decode_key(&mut bytes) {
+        Ok(Key::Unknown | Key::Paste) | Err(_) => None,
+        Ok(key) => bytes.as_slice().is_empty().then_some(key),
+    }
+}
+
+/// Where the bytes keys are made of come from.
+trait Bytes {
+    fn next_byte(&mut self) -> io::Result<u8>;
+    /// Whether another byte follows straight away, which tells the
+    /// Escape key from the start of a longer sequence
+    fn pending(&mut self) -> io::Result<bool>;
+}
+
+/// The terminal, read as keys are typed.
+struct Terminal;
+
+impl Bytes for Terminal {
+    fn next_byte(&mut self) -> io::Result<u8> {
+        read_byte()
+    }
+
+    fn pending(&mut self) -> io::Result<bool> {
+        input_pending(ESCAPE_TIMEOUT)
+    }
+}
+
+/// A key sequence given to `bind`.
+impl Bytes for std::slice::Iter<'_, u8> {
+    fn next_byte(&mut self) -> io::Result<u8> {
+        self.next().copied().ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+
+    fn pending(&mut self) -> io::Result<bool> {
+        Ok(!self.as_slice().is_empty())
+    }
+}
+
+/// Reads the next key from `bytes`.
+fn decode_key(bytes: &mut dyn Bytes) -> io::Result<Key> {
+    let byte = bytes.next_byte()?;
+    Ok(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x08 | 0x7f => Key::Backspace,
+        0x1b => read_escape(bytes)?,
+        // Ctrl with a letter is named by the letter, in lower case
+        0..=0x1a => Key::Ctrl((byte + b'`') as char),
+        0x1c..=0x1f => Key::Ctrl((byte ^ 0x40) as char),
+        0x20..=0x7e => Key::Char(byte as char),
+        _ => read_utf8(bytes, byte)?.map_or(Key::Unknown, Key::Char),
+    })
+}
+
+/// Reads the rest of a character whose first byte was `first`.
+fn read_utf8(bytes: &mut dyn Bytes, first: u8) -> io::Result<Option<char>> {
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Ok(None),
+    };
+    let mut encoded = vec![first];
+    for _ in 1..len {
+        encoded.push(bytes.next_byte()?);
+    }
+    Ok(std::str::from_utf8(&encoded).ok().and_then(|s| s.chars().next()))
+}
+
+/// Reads pasted text up to the mark the terminal ends it with. Line
+/// breaks come as carriage returns, as if Enter was typed, and are turned
+/// into newlines.
+fn read_paste() -> io::Result<String> {
+    let mut bytes = Vec::new();
+    while !bytes.ends_with(PASTE_END) {
+        bytes.push(read_byte()?);
+    }
+    bytes.truncate(bytes.len() - PASTE_END.len());
+    Ok(String::from_utf8_lossy(&bytes).replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+/// Whether more input arrives within `timeout` milliseconds.
+fn input_pending(timeout: i32) -> io::Result<bool> {
+    loop {
+        match sys::poll_input(0, timeout) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                if signals::take_interrupt() {
+                    return Err(e);
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Reads what follows an Escape: a key typed with Alt, or the sequence a
+/// cursor or editing key sends. An Escape with nothing right after it is
+/// the Escape key.
+fn read_escape(bytes: &mut dyn Bytes) -> io::Result<Key> {
+    if !bytes.pending()? {
+        return Ok(Key::Escape);
+    }
+    let byte = bytes.next_byte()?;
+    Ok(match byte {
+        b'[' => {
+            let mut params = String::new();
+            let last = loop {
+                match bytes.next_byte()? {
+                    byte @ 0x40..=0x7e => break byte,
+                    byte => params.push(byte as char),
+                }
+            };
+            match (params.as_str(), last) {
+                (_, b'A') => Key::Up,
+                (_, b'B') => Key::Down,
+                (_, b'C') => Key::Right,
+                (_, b'D') => Key::Left,
+                (_, b'H') | ("1" | "7", b'~') => Key::Home,
+                (_, b'F') | ("4" | "8", b'~') => Key::End,
+                ("3", b'~') => Key::Delete,
+                (_, b'Z') => Key::BackTab,
+                ("200", b'~') => Key::Paste,
+                _ => Key::Unknown,
+            }
+        }
+        b'O' => match bytes.next_byte()? {
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            _ => Key::Unknown,
+        },
+        0x08 | 0x7f => Key::AltBackspace,
+        0x1b => Key::Escape,
+        0x20..=0x7e => Key::Alt(byte as char),
+        _ => Key::Unknown,
+    })
+}
+
+/// The line being typed and where it was drawn.
+struct Edit<'a> {
+    shell: &'a Shell,
+    /// Left for commands bound with `bind -x` to run. Without one there
+    /// is nothing to draw the line on.
+    terminal: Option<&'a RawMode>,
+    /// Where keys are read from
+    keys: &'a mut dyn Bytes,
+    prompt: &'a str,
+    /// What's shown at the right end of the prompt's last row
+    right_prompt: &'a str,
+    /// What's shown instead of the prompt during a history search
+    search_prompt: Option<String>,
+    /// The lines that can be recalled, oldest first
+    history: Cow<'a, [String]>,
+    /// How many lines other shells have run since the prompt was shown
+    /// have been added to `history`
+    unread: usize,
+    /// The history entry being edited; the length of the history for
+    /// the new line
+    entry: usize,
+    /// The new line, put aside while history entries are shown
+    new_line: Vec<char>,
+    buffer: Vec<char>,
+    cursor: usize,
+    /// The row the cursor was left on, counting from the prompt's
+    row: usize,
+    /// Whether keys are typed in, rather than being vi commands
+    insert: bool,
+    /// The completions shown below the line
+    menu: Option<Menu>,
+    /// Whether to suggest the rest of the line from history
+    suggest: bool,
+    /// The line and cursor as they were before each change, the latest
+    /// last
+    undo: Vec<(Vec<char>, usize)>,
+    /// The line and cursor as they were before each change undone
+    redo: Vec<(Vec<char>, usize)>,
+    /// The line and cursor as of the last key, to tell whether the next
+    /// one changes it
+    saved: (Vec<char>, usize),
+    /// Whether the last change was a character typed in, which the next
+    /// one typed joins
+    typing: bool,
+}
+
+/// Completions shown below the line for one to be picked, in rows of as
+/// many as fit across.
+struct Menu {
+    items: Vec<String>,
+    selected: Option<usize>,
+}
+
+impl Menu {
+    /// The rows of the menu as drawn, the picked item in reverse video.
+    /// If there are too many to show, only the rows around the picked one
+    /// are, with a line saying which.
+    fn rows(&self, columns: usize) -> Vec<String> {
+        let width = (self.items.iter().map(|item| unicode::text_width(item)).max().unwrap_or(0) + 2).min(columns);
+        let per_row = (columns / width).max(1);
+        let rows = self.items.len().div_ceil(per_row);
+        let selected_row = self.selected.map_or(0, |selected| selected / per_row);
+        let top = selected_row.saturating_sub(MENU_ROWS - 1);
+        let mut lines: Vec<String> = (top..rows.min(top + MENU_ROWS))
+            .map(|row| {
+                let mut line = String::new();
+                for (i, item) in self.items.iter().enumerate().skip(row * per_row).take(per_row) {
+                    let mut shown = 0;
+                    let item: String = item.chars().take_while(|&c| {
+                        shown += unicode::width(c);
+                        shown < width
+                    }).collect();
+                    let padding = " ".repeat(width - unicode::text_width(&item));
+                    match Some(i) == self.selected {
+                        true => line.push_str(&format!("\x1b[7m{}\x1b[0m{}", item, padding)),
+                        false => line.push_str(&format!("{}{}", item, padding)),
+                    }
+                }
+                line.trim_end().to_string()
+            })
+            .collect();
+        if rows > MENU_ROWS {
+            lines.push(format!("rows {} to {} of {}", top + 1, top + lines.len(), rows));
+        }
+        lines
+    }
+}
+
+impl<'a> Edit<'a> {
+    /// A new line to be typed after `prompt`, with the shell's history to
+    /// recall and vi's insert mode to start in.
+    fn new(shell: &'a Shell, terminal: Option<&'a RawMode>, keys: &'a mut dyn Bytes, prompt: &'a str, right_prompt: &'a str) -> Edit<'a> {
+        let history = shell.history.entries();
+        Edit {
+            shell,
+            terminal,
+            keys,
+            prompt,
+            right_prompt,
+            search_prompt: None,
+            history: Cow::Borrowed(history),
+            unread: 0,
+            entry: history.len(),
+            new_line: Vec::new(),
+            buffer: Vec::new(),
+            cursor: 0,
+            row: 0,
+            insert: true,
+            menu: None,
+            suggest: shell.options.autosuggest,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            saved: (Vec::new(), 0),
+            typing: false,
+        }
+    }
+
+    fn read_key(&mut self) -> io::Result<Key> {
+        decode_key(self.keys)
+    }
+
+    fn bell(&self) -> io::Result<()> {
+        if self.terminal.is_none() {
+            return Ok(());
+        }
+        let mut out = io::stdout();
+        out.write_all(b"\x07")?;
+        out.flush()
+    }
+
+    /// Redraws the prompt and the line, which may wrap or hold newlines,
+    /// and puts the cursor back in place.
+    fn refresh(&mut self) -> io::Result<()> {
+        if self.terminal.is_none() {
+            return Ok(());
+        }
+        let columns = sys::columns(1).unwrap_or(80);
+        let mut text = String::new();
+        if self.row > 0 {
+            text.push_str(&format!("\x1b[{}A", self.row));
+        }
+        text.push_str("\r\x1b[J");
+        let prompt = self.search_prompt.as_deref().unwrap_or(self.prompt);
+        text.push_str(&prompt::printed(prompt));
+
+        // The row and column each character leaves the cursor at. A wide
+        // character that doesn't fit at the end of a row goes on the next.
+        let advance = |(row, column): (usize, usize), c: char| match (c, unicode::width(c)) {
+            ('\n', _) => (row + 1, 0),
+            (_, width) if column + width > columns => (row + 1, width),
+            (_, width) if column + width == columns => (row + 1, 0),
+            (_, width) => (row, column + width),
+        };
+        let prompt = prompt::visible(prompt);
+        let start = prompt.chars().fold((0, 0), advance);
+        // Only the first line of a suggestion is shown, dimmed
+        let suggestion: Vec<char> = self.suggestion().map_or(Vec::new(), |rest| rest.lines().next().unwrap_or("").chars().collect());
+
+        // The right prompt goes at the end of the prompt's last row, as
+        // long as what's typed on that row stays clear of it
+        if self.search_prompt.is_none() && !self.right_prompt.is_empty() {
+            let width = unicode::text_width(&prompt::visible(self.right_prompt));
+            let (row, column) = self.buffer.iter().chain(&suggestion).copied().take_while(|&c| c != '\n').fold(start, advance);
+            if row == start.0 && column + 1 + width < columns {
+                text.push_str(&format!("\x1b[{}G{}\x1b[{}G", columns - width, prompt::printed(self.right_prompt), start.1 + 1));
+            }
+        }
+
+        let colors = match self.shell.options.highlight {
+            true => highlight::colors(&self.buffer, self.shell),
+            false => Vec::new(),
+        };
+        let mut current = None;
+        for (i, &c) in self.buffer.iter().enumerate() {
+            let color = colors.get(i).copied().flatten();
+            if color != current {
+                text.push_str(&format!("\x1b[{}m", color.unwrap_or("0")));
+                current = color;
+            }
+            text.push(c);
+        }
+        if current.is_some() {
+            text.push_str("\x1b[0m");
+        }
+        if !suggestion.is_empty() {
+            text.push_str("\x1b[2m");
+            text.extend(&suggestion);
+            text.push_str("\x1b[0m");
+        }
+
+        let (row, column) = self.buffer[..self.cursor].iter().copied().fold(start, advance);
+        let end = self.buffer[self.cursor..].iter().chain(&suggestion).copied().fold((row, column), advance);
+
+        // A line that fills its last row exactly leaves the terminal
+        // waiting to wrap, so the cursor is moved to the next row by hand
+        let last = suggestion.last().or(self.buffer.last()).copied().or(prompt.chars().last());
+        if end.0 > 0 && end.1 == 0 && last != Some('\n') {
+            text.push_str("\r\n");
+        }
+        let mut bottom = end.0;
+        if let Some(menu) = &self.menu {
+            if !(end.0 > 0 && end.1 == 0) {
+                text.push_str("\r\n");
+                bottom += 1;
+            }
+            let rows = menu.rows(columns);
+            text.push_str(&rows.join("\r\n"));
+            bottom += rows.len() - 1;
+        }
+        if bottom > row {
+            text.push_str(&format!("\x1b[{}A", bottom - row));
+        }
+        text.push('\r');
+        if column > 0 {
+            text.push_str(&format!("\x1b[{}C", column));
+        }
+        self.row = row;
+
+        let mut out = io::stdout();
+        out.write_all(text.as_bytes())?;
+        out.flush()
+    }
+
+    /// The rest of the latest history entry that starts with the new line
+    /// being typed, when the cursor is at its end.
+    fn suggestion(&self) -> Option<&str> {
+        if !self.suggest || self.buffer.is_empty() || self.cursor < self.buffer.len() || self.entry < self.history.len() {
+            return None;
+        }
+        if self.search_prompt.is_some() || self.menu.is_some() {
+            return None;
+        }
+        let typed: String = self.buffer.iter().collect();
+        let entry = self.history.iter().rev().find(|entry| entry.len() > typed.len() && entry.starts_with(&typed))?;
+        Some(&entry[typed.len()..])
+    }
+
+    /// Runs a command bound with `bind -x` below the line, with the line
+    /// in READLINE_LINE and the cursor's position in READLINE_POINT, and
+    /// takes them back as the command leaves them. The shell is busy
+    /// reading the line, so the command runs in a copy of it, as a
+    /// command substitution does.
+    fn run_command(&mut self, command: &str) -> io::Result<()> {
+        let (cursor, suggest) = (self.cursor, self.suggest);
+        self.finish("\r\n")?;
+        self.suggest = suggest;
+        let line: String = self.buffer.iter().collect();
+        let mut shell = self.shell.subshell();
+        shell.set_variable("READLINE_LINE", &line);
+        shell.set_variable("READLINE_POINT", &cursor.to_string());
+        let terminal = self.terminal.ok_or(io::ErrorKind::Unsupported)?;
+        terminal.suspend()?;
+        shell.eval(command);
+        // A Ctrl-C meant for the command isn't one for the line
+        signals::take_interrupt();
+        terminal.resume()?;
+        self.buffer = shell.parameter("READLINE_LINE").unwrap_or(line).chars().collect();
+        let point = shell.parameter("READLINE_POINT").and_then(|point| point.parse().ok());
+        self.cursor = point.unwrap_or(cursor).min(self.buffer.len());
+        // The line is drawn again below what the command wrote
+        self.row = 0;
+        Ok(())
+    }
+
+    /// Moves the cursor past the end of the line and writes `last` there.
+    fn finish(&mut self, last: &str) -> io::Result<()> {
+        self.menu = None;
+        self.suggest = false;
+        self.cursor = self.buffer.len();
+        self.refresh()?;
+        let mut out = io::stdout();
+        write!(out, "{}", last)?;
+        out.flush()
+    }
+
+    /// Where the character `count` characters after `pos` starts,
+    /// taking a character as what the user sees as one: a grapheme
+    /// cluster, with any accents or joined emoji.
+    fn forward_chars(&self, pos: usize, count: usize) -> usize {
+        (0..count).fold(pos, |pos, _| unicode::next_boundary(&self.buffer, pos))
+    }
+
+    /// Where the character `count` characters before `pos` starts.
+    fn backward_chars(&self, pos: usize, count: usize) -> usize {
+        (0..count).fold(pos, |pos, _| unicode::previous_boundary(&self.buffer, pos))
+    }
+
+    /// Whether `c` is part of a word for Emacs's word commands: letters
+    /// and digits, and the accents on them.
+    fn word_char(c: char) -> bool {
+        c.is_alphanumeric() || unicode::combining(c)
+    }
+
+    /// Where the word under or before the cursor starts, with words
+    /// made of letters and digits.
+    fn word_start(&self) -> usize {
+        let mut pos = self.cursor;
+        while pos > 0 && !Edit::word_char(self.buffer[pos - 1]) {
+            pos -= 1;
+        }
+        while pos > 0 && Edit::word_char(self.buffer[pos - 1]) {
+            pos -= 1;
+        }
+        pos
+    }
+
+    /// Where the word under or after the cursor ends.
+    fn word_end(&self) -> usize {
+        let mut pos = self.cursor;
+        while pos < self.buffer.len() && !Edit::word_char(self.buffer[pos]) {
+            pos += 1;
+        }
+        while pos < self.buffer.len() && Edit::word_char(self.buffer[pos]) {
+            pos += 1;
+        }
+        pos
+    }
+
+    /// Where the whitespace-separated word before the cursor starts.
+    fn big_word_start(&self) -> usize {
+        let mut pos = self.cursor;
+        while pos > 0 && self.buffer[pos - 1].is_whitespace() {
+            pos -= 1;
+        }
+        while pos > 0 && !self.buffer[pos - 1].is_whitespace() {
+            pos -= 1;
+        }
+        pos
+    }
+
+    /// The class of a character for vi's word motions: blanks, word
+    /// characters and punctuation, or with `big` only blanks and the rest.
+    fn class(c: char, big: bool) -> u8 {
+        match c {
+            c if c.is_whitespace() => 0,
+            _ if big => 1,
+            c if Edit::word_char(c) || c == '_' => 1,
+            _ => 2,
+        }
+    }
+
+    /// Where the next vi word after `pos` starts, for `w` and `W`.
+    fn next_word(&self, mut pos: usize, big: bool) -> usize {
+        let len = self.buffer.len();
+        if pos < len {
+            let class = Edit::class(self.buffer[pos], big);
+            while pos < len && class != 0 && Edit::class(self.buffer[pos], big) == class {
+                pos += 1;
+            }
+        }
+        while pos < len && self.buffer[pos].is_whitespace() {
+            pos += 1;
+        }
+        pos
+    }
+
+    /// Where the vi word before `pos` starts, for `b` and `B`.
+    fn previous_word(&self, mut pos: usize, big: bool) -> usize {
+        while pos > 0 && self.buffer[pos - 1].is_whitespace() {
+            pos -= 1;
+        }
+        if pos > 0 {
+            let class = Edit::class(self.buffer[pos - 1], big);
+            while pos > 0 && Edit::class(self.buffer[pos - 1], big) == class {
+                pos -= 1;
+            }
+        }
+        pos
+    }
+
+    /// The last character of the vi word after `pos`, for `e` and `E`.
+    fn word_last(&self, mut pos: usize, big: bool) -> usize {
+        let len = self.buffer.len();
+        pos += 1;
+        while pos < len && self.buffer[pos].is_whitespace() {
+            pos += 1;
+        }
+        if pos >= len {
+            return self.backward_chars(len, 1);
+        }
+        let class = Edit::class(self.buffer[pos], big);
+        while pos + 1 < len && Edit::class(self.buffer[pos + 1], big) == class {
+            pos += 1;
+        }
+        self.backward_chars(pos + 1, 1)
+    }
+
+    /// Where a vi motion repeated `count` times leaves the cursor, if
+    /// `key` is one.
+    fn vi_motion(&self, key: Key, count: usize) -> Option<usize> {
+        let repeat = |step: &dyn Fn(usize) -> usize| (0..count).fold(self.cursor, |pos, _| step(pos));
+        Some(match key {
+            Key::Char('h') | Key::Left | Key::Backspace => self.backward_chars(self.cursor, count),
+            Key::Char('l' | ' ') | Key::Right => self.forward_chars(self.cursor, count),
+            Key::Char('0') | Key::Home => 0,
+            Key::Char('^') => self.buffer.iter().position(|c| !c.is_whitespace()).unwrap_or(0),
+            Key::Char('$') | Key::End => self.buffer.len(),
+            Key::Char('w') => repeat(&|pos| self.next_word(pos, false)),
+            Key::Char('W') => repeat(&|pos| self.next_word(pos, true)),
+            Key::Char('b') => repeat(&|pos| self.previous_word(pos, false)),
+            Key::Char('B') => repeat(&|pos| self.previous_word(pos, true)),
+            Key::Char('e') => repeat(&|pos| self.word_last(pos, false)),
+            Key::Char('E') => repeat(&|pos| self.word_last(pos, true)),
+            _ => return None,
+        })
+    }
+
+    /// Adds the lines other shells have run since the prompt was shown,
+    /// if history is shared, so they can be recalled too.
+    fn catch_up(&mut self, shell: &Shell) {
+        let unread = shell.history.unread();
+        if unread.len() <= self.unread {
+            return;
+        }
+        let at_new_line = self.entry == self.history.len();
+        self.history.to_mut().extend_from_slice(&unread[self.unread..]);
+        self.unread = unread.len();
+        if at_new_line {
+            self.entry = self.history.len();
+        }
+    }
+
+    /// Replaces the line with history entry `entry`, or with the new
+    /// line past the end of the history.
+    fn recall(&mut self, entry: usize) {
+        if entry == self.entry {
+            return;
+        }
+        if self.entry == self.history.len() {
+            self.new_line = std::mem::take(&mut self.buffer);
+        }
+        self.buffer = match self.history.get(entry) {
+            Some(line) => line.chars().collect(),
+            None => std::mem::take(&mut self.new_line),
+        };
+        self.entry = entry;
+        // vi starts at the beginning of a recalled line, Emacs at the end
+        self.cursor = if self.insert { self.buffer.len() } else { 0 };
+        // Changes made to another line can't be undone on this one
+        self.undo.clear();
+        self.redo.clear();
+        self.saved = (self.buffer.clone(), self.cursor);
+        self.typing = false;
+    }
+
+    /// Takes note of the change `key` made to the line, if it made one,
+    /// for it to be undone. Characters typed one after another make one
+    /// change, until some other key comes between them.
+    fn record(&mut self, key: Key) {
+        if self.buffer == self.saved.0 {
+            self.saved.1 = self.cursor;
+            self.typing = false;
+            return;
+        }
+        let typed = matches!(key, Key::Char(_)) && self.insert && self.buffer.len() == self.saved.0.len() + 1;
+        let saved = std::mem::replace(&mut self.saved, (self.buffer.clone(), self.cursor));
+        if !(typed && self.typing) {
+            self.undo.push(saved);
+        }
+        self.redo.clear();
+        self.typing = typed;
+    }
+
+    /// Puts the line back as it was before the last change, or with
+    /// `redo` makes the last change undone again.
+    fn undo(&mut self, redo: bool) {
+        let (from, to) = match redo {
+            false => (&mut self.undo, &mut self.redo),
+            true => (&mut self.redo, &mut self.undo),
+        };
+        let Some(state) = from.pop() else {
+            return;
+        };
+        to.push(std::mem::replace(&mut self.saved, state.clone()));
+        (self.buffer, self.cursor) = state;
+        self.typing = false;
+    }
+
+    /// The nearest history entry before the one being edited, or with
+    /// `backward` false after it, that has `pattern` in it.
+    fn search_history(&self, pattern: &str, backward: bool) -> Option<usize> {
+        let matches = |line: &String| line.contains(pattern);
+        match backward {
+            true => self.history[..self.entry].iter().rposition(matches),
+            false => self.history.iter().skip(self.entry + 1).position(matches).map(|i| self.entry + 1 + i),
+        }
+    }
+
+    /// Removes the text between the cursor and `pos`, returning it.
+    fn kill_to(&mut self, pos: usize) -> String {
+        let (start, end) = (pos.min(self.cursor), pos.max(self.cursor));
+        self.cursor = start;
+        self.buffer.drain(start..end).collect()
+    }
+}
+
+/// The state kept between lines.
+#[derive(Default)]
+pub struct Editor {
+    /// The text last killed, which Ctrl-Y puts back
+    killed: String,
+    /// The programs on PATH, for completing command names
+    commands: CommandCache,
+    /// The pattern of the last `/` or `?` in vi's command mode, and
+    /// whether it searched back through history, for `n` and `N`
+    search: Option<(String, bool)>,
+}
+
+impl Editor {
+    /// Reads a line from the terminal, showing `prompt` before it.
+    pub fn read_line(&mut self, prompt: &str, right_prompt: &str, shell: &Shell) -> io::Result<Line> {
+        let Ok(terminal) = RawMode::enable() else {
+            print!("{}", prompt::printed(prompt));
+            io::stdout().flush()?;
+            return input::read_line();
+        };
+        let mut keys = Terminal;
+        let mut edit = Edit::new(shell, Some(&terminal), &mut keys, prompt, right_prompt);
+        edit.refresh()?;
+        loop {
+            match self.step(&mut edit, shell) {
+                Ok(None) => edit.refresh()?,
+                Ok(Some(line)) => {
+                    edit.finish("\r\n")?;
+                    return Ok(line);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    edit.finish("^C")?;
+                    return Ok(Line::Interrupted);
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    edit.finish("\r\n")?;
+                    return Ok(Line::Eof);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Reads a key and does what it's bound to, returning the line once
+    // it's done.
+    fn step(&mut self, edit: &mut Edit, shell: &Shell) -> io::Result<Option<Line>> {
+        let key = edit.read_key()?;
+        let line = self.dispatch(edit, shell, key)?;
+        edit.record(key);
+        Ok(line)
+    }
+
+    fn dispatch(&mut self, edit: &mut Edit, shell: &Shell, key: Key) -> io::Result<Option<Line>> {
+        // Pasted text goes in as it is, newlines and all, rather than
+        // being taken as keys
+        if key == Key::Paste {
+            for c in read_paste()?.chars() {
+                edit.buffer.insert(edit.cursor, c);
+                edit.cursor += 1;
+            }
+            return Ok(None);
+        }
+        let typing = edit.insert || !shell.options.vi;
+        let keymap = match (shell.options.vi, edit.insert) {
+            (false, _) => Keymap::Emacs,
+            (true, true) => Keymap::ViInsert,
+            (true, false) => Keymap::ViCommand,
+        };
+        let action = match shell.bindings.get(keymap, key) {
+            Some(Binding::Action(action)) => Some(*action),
+            Some(Binding::Command(command)) => {
+                edit.run_command(command)?;
+                return Ok(None);
+            }
+            Some(Binding::Unbound) => None,
+            None if keymap == Keymap::ViCommand => self.vi_command(edit, key)?,
+            None => keymap.default(key),
+        };
+        if let Some(Action::PreviousHistory | Action::ReverseSearchHistory) = action {
+            edit.catch_up(shell);
+        }
+        // Moving right from the end of the line takes the suggestion
+        if let (Some(Action::ForwardChar | Action::EndOfLine), Some(rest)) = (action, edit.suggestion()) {
+            let rest: Vec<char> = rest.chars().collect();
+            edit.buffer.extend(rest);
+            edit.cursor = edit.buffer.len();
+            return Ok(None);
+        }
+        match action {
+            Some(Action::AcceptLine) => return Ok(Some(Line::Text(edit.buffer.iter().collect()))),
+            Some(Action::DeleteCharOrEof) if edit.buffer.is_empty() => return Ok(Some(Line::Eof)),
+            Some(Action::ReverseSearchHistory) => {
+                if let Some(key) = self.reverse_search(edit)? {
+                    return self.dispatch(edit, shell, key);
+                }
+            }
+            Some(Action::Complete) => {
+                if let Some(key) = self.complete(edit, shell)? {
+                    return self.dispatch(edit, shell, key);
+                }
+            }
+            Some(Action::ClearScreen) => {
+                let mut out = io::stdout();
+                write!(out, "{}", CLEAR_SCREEN)?;
+                out.flush()?;
+                // The next refresh starts from the top
+                edit.row = 0;
+            }
+            Some(action) => self.apply(edit, action),
+            None => {
+                if let (Key::Char(c), true) = (key, typing) {
+                    edit.buffer.insert(edit.cursor, c);
+                    edit.cursor += 1;
+                }
+            }
+        }
+        // In vi's command mode the cursor stays on a character
+        if !edit.insert && edit.cursor > 0 && edit.cursor >= edit.buffer.len() {
+            edit.cursor = edit.buffer.len() - 1;
+        }
+        Ok(None)
+    }
+
+    // Completes the word before the cursor: to the one candidate there
+    // is, or as far as all of them agree, offering them in a menu if they
+    // agree on nothing more than what's typed. A word completed to a file
+    // gets a space after it, so the next can be typed; one completed to a
+    // directory doesn't, so what's in it can be. Returns a key that
+    // closed the menu, to be done as usual.
+    fn complete(&mut self, edit: &mut Edit, shell: &Shell) -> io::Result<Option<Key>> {
+        let completion = complete::complete(&edit.buffer, edit.cursor, shell, &mut self.commands);
+        let escape = |text: &str| match completion.literal {
+            true => text.to_string(),
+            false => complete::escape(text),
+        };
+        let replacement = match completion.candidates.as_slice() {
+            [] => {
+                edit.bell()?;
+                return Ok(None);
+            }
+            [only] if only.ends_with('/') => escape(only),
+            [only] => format!("{} ", escape(only)),
+            candidates => {
+                let prefix = complete::common_prefix(candidates);
+                if prefix.len() <= completion.word.len() {
+                    let replacements: Vec<String> = candidates.iter().map(|candidate| escape(candidate)).collect();
+                    let shown = candidates.iter().map(|candidate| completion.shown(candidate).to_string()).collect();
+                    edit.menu = Some(Menu { items: shown, selected: None });
+                    return self.pick(edit, completion.start, &replacements);
+                }
+                escape(&prefix)
+            }
+        };
+        edit.buffer.splice(completion.start..edit.cursor, replacement.chars());
+        edit.cursor = completion.start + replacement.chars().count();
+        Ok(None)
+    }
+
+    // Lets one of the completions in the menu be picked, putting each in
+    // place of the word at `start` as it is moved to. Tab and the arrows
+    // move through them, Enter keeps the one picked and Escape or Ctrl-G
+    // goes back to what was typed; any other key keeps it and is returned
+    // to be done as usual.
+    fn pick(&mut self, edit: &mut Edit, start: usize, replacements: &[String]) -> io::Result<Option<Key>> {
+        let typed: Vec<char> = edit.buffer[start..edit.cursor].to_vec();
+        let count = replacements.len();
+        loop {
+            edit.refresh()?;
+            let Some(selected) = edit.menu.as_ref().map(|menu| menu.selected) else {
+                return Ok(None);
+            };
+            let picked = match edit.read_key()? {
+                Key::Tab | Key::Down | Key::Right | Key::Ctrl('n') => selected.map_or(0, |i| (i + 1) % count),
+                Key::BackTab | Key::Up | Key::Left | Key::Ctrl('p') => selected.map_or(count - 1, |i| (i + count - 1) % count),
+                Key::Enter => {
+                    edit.menu = None;
+                    return Ok(None);
+                }
+                Key::Escape | Key::Ctrl('g') => {
+                    let end = std::mem::replace(&mut edit.cursor, start + typed.len());
+                    edit.buffer.splice(start..end, typed);
+                    edit.menu = None;
+                    return Ok(None);
+                }
+                key => {
+                    edit.menu = None;
+                    return Ok(Some(key));
+                }
+            };
+            if let Some(menu) = &mut edit.menu {
+                menu.selected = Some(picked);
+            }
+            edit.buffer.splice(start..edit.cursor, replacements[picked].chars());
+            edit.cursor = start + replacements[picked].chars().count();
+        }
+    }
+
+    // Searches back through history for the string typed after Ctrl-R,
+    // showing the latest entry that contains it. Ctrl-R again finds the
+    // one before, Escape leaves the entry found to be edited, and Ctrl-G
+    // goes back to the line as it was. Any other key ends the search and
+    // is returned, to be handled as usual.
+    fn reverse_search(&mut self, edit: &mut Edit) -> io::Result<Option<Key>> {
+        let original = (edit.buffer.clone(), edit.cursor, edit.entry);
+        let mut query = String::new();
+        let mut found = None;
+        let mut failed = false;
+        loop {
+            let failing = if failed { "failed " } else { "" };
+            edit.search_prompt = Some(format!("({}reverse-i-search)`{}': ", failing, query));
+            edit.refresh()?;
+            // The entry to search back from, exclusive
+            let from = match edit.read_key()? {
+                Key::Char(c) => {
+                    query.push(c);
+                    found.map_or(edit.history.len(), |entry| entry + 1)
+                }
+                Key::Backspace => {
+                    query.pop();
+                    edit.history.len()
+                }
+                Key::Ctrl('r') => found.unwrap_or(edit.history.len()),
+                Key::Ctrl('g') => {
+                    edit.recall(original.2);
+                    (edit.buffer, edit.cursor) = (original.0, original.1);
+                    edit.search_prompt = None;
+                    return Ok(None);
+                }
+                Key::Escape => {
+                    edit.search_prompt = None;
+                    return Ok(None);
+                }
+                key => {
+                    edit.search_prompt = None;
+                    return Ok(Some(key));
+                }
+            };
+            let matched = edit.history[..from]
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(entry, line)| Some((entry, line[..line.find(&query)?].chars().count())));
+            failed = matched.is_none();
+            if let Some((entry, pos)) = matched {
+                edit.recall(entry);
+                edit.cursor = pos;
+                found = Some(entry);
+            }
+        }
+    }
+
+    // Runs a command of vi's command mode, reading the rest of it (a
+    // count, or the motion after an operator) as needed. Returns the
+    // action for commands that do the same as a key in insert mode.
+    fn vi_command(&mut self, edit: &mut Edit, mut key: Key) -> io::Result<Option<Action>> {
+        let mut count = None;
+        while let Key::Char(digit @ ('1'..='9' | '0')) = key {
+            if digit == '0' && count.is_none() {
+                break;
+            }
+            count = Some(count.unwrap_or(0) * 10 + digit.to_digit(10).unwrap() as usize);
+            key = edit.read_key()?;
+        }
+        let count = count.unwrap_or(1);
+
+        match key {
+            Key::Enter => return Ok(Some(Action::AcceptLine)),
+            Key::Ctrl('d') => return Ok(Some(Action::DeleteCharOrEof)),
+            Key::Ctrl('l') => return Ok(Some(Action::ClearScreen)),
+            Key::Char('k' | '-') | Key::Up => return Ok(Some(Action::PreviousHistory)),
+            Key::Char('j' | '+') | Key::Down => return Ok(Some(Action::NextHistory)),
+            Key::Char('u') => return Ok(Some(Action::Undo)),
+            Key::Ctrl('r') => return Ok(Some(Action::Redo)),
+            // As in readline, `/` searches back through history and `?`
+            // forward; an empty pattern is the last one again
+            Key::Char(c @ ('/' | '?')) => {
+                let Some(pattern) = self.read_pattern(edit, c)? else {
+                    return Ok(None);
+                };
+                if !pattern.is_empty() {
+                    self.search = Some((pattern, c == '/'));
+                } else if let Some((_, backward)) = &mut self.search {
+                    *backward = c == '/';
+                }
+                self.search_again(edit, false)?;
+            }
+            Key::Char('n') => self.search_again(edit, false)?,
+            Key::Char('N') => self.search_again(edit, true)?,
+            Key::Char('i') => edit.insert = true,
+            Key::Char('a') => {
+                edit.cursor = edit.forward_chars(edit.cursor, 1);
+                edit.insert = true;
+            }
+            Key::Char('I') => {
+                edit.cursor = 0;
+                edit.insert = true;
+            }
+            Key::Char('A') => {
+                edit.cursor = edit.buffer.len();
+                edit.insert = true;
+            }
+            Key::Char('x') | Key::Delete => {
+                let end = edit.forward_chars(edit.cursor, count);
+                self.kill(edit, end);
+            }
+            Key::Char('X') => {
+                let start = edit.backward_chars(edit.cursor, count);
+                self.kill(edit, start);
+            }
+            Key::Char('D') => return Ok(Some(Action::KillLine)),
+            Key::Char('C') => {
+                edit.insert = true;
+                return Ok(Some(Action::KillLine));
+            }
+            Key::Char('S') => {
+                edit.cursor = 0;
+                edit.insert = true;
+                return Ok(Some(Action::KillLine));
+            }
+            Key::Char('p') => {
+                edit.cursor = edit.forward_chars(edit.cursor, 1);
+                self.apply(edit, Action::Yank);
+                edit.cursor = edit.backward_chars(edit.cursor, 1);
+            }
+            Key::Char('P') => {
+                self.apply(edit, Action::Yank);
+                edit.cursor = edit.backward_chars(edit.cursor, 1);
+            }
+            Key::Char(operator @ ('d' | 'c' | 'y')) => {
+                let motion = edit.read_key()?;
+                let range = if motion == key {
+                    // `dd`, `cc` and `yy` take the whole line
+                    Some((0, edit.buffer.len()))
+                } else {
+                    // Like vi, `cw` changes only to the end of the word
+                    let motion = match (operator, motion) {
+                        ('c', Key::Char('w')) => Key::Char('e'),
+                        ('c', Key::Char('W')) => Key::Char('E'),
+                        _ => motion,
+                    };
+                    let inclusive = matches!(motion, Key::Char('e' | 'E'));
+                    edit.vi_motion(motion, count).map(|pos| match pos < edit.cursor {
+                        true => (pos, edit.cursor),
+                        false => (edit.cursor, if inclusive { edit.forward_chars(pos, 1) } else { pos }),
+                    })
+                };
+                if let Some((start, end)) = range {
+                    if operator == 'y' {
+                        self.killed = edit.buffer[start..end].iter().collect();
+                        if motion != key {
+                            edit.cursor = start;
+                        }
+                    } else {
+                        edit.cursor = start;
+                        self.kill(edit, end);
+                        edit.insert = operator == 'c';
+                    }
+                }
+            }
+            motion => {
+                if let Some(pos) = edit.vi_motion(motion, count) {
+                    edit.cursor = pos;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Reads the pattern typed after `/` or `?`, shown in place of the
+    // line until Enter ends it. Escape, Ctrl-G or backspacing past the
+    // start gives up on the search and returns None.
+    fn read_pattern(&mut self, edit: &mut Edit, first: char) -> io::Result<Option<String>> {
+        let line = (std::mem::take(&mut edit.buffer), edit.cursor);
+        edit.cursor = 0;
+        let mut pattern = String::new();
+        let pattern = loop {
+            edit.search_prompt = Some(format!("{}{}", first, pattern));
+            edit.refresh()?;
+            match edit.read_key()? {
+                Key::Enter => break Some(pattern),
+                Key::Backspace if pattern.is_empty() => break None,
+                Key::Backspace => {
+                    pattern.pop();
+                }
+                Key::Escape | Key::Ctrl('g') => break None,
+                Key::Char(c) => pattern.push(c),
+                _ => {}
+            }
+        };
+        edit.search_prompt = None;
+        (edit.buffer, edit.cursor) = line;
+        Ok(pattern)
+    }
+
+    // Recalls the next history entry the last search's pattern is in,
+    // going the other way with `reverse`, or rings the bell if there's
+    // none.
+    fn search_again(&mut self, edit: &mut Edit, reverse: bool) -> io::Result<()> {
+        let shell = edit.shell;
+        edit.catch_up(shell);
+        let found = self.search.as_ref().and_then(|(pattern, backward)| edit.search_history(pattern, *backward != reverse));
+        match found {
+            Some(entry) => edit.recall(entry),
+            None => edit.bell()?,
+        }
+        Ok(())
+    }
+
+    // Removes the text between the cursor and `pos`, keeping it for
+    // Ctrl-Y unless there was none.
+    fn kill(&mut self, edit: &mut Edit, pos: usize) {
+        let killed = edit.kill_to(pos);
+        if !killed.is_empty() {
+            self.killed = killed;
+        }
+    }
+
+    fn apply(&mut self, edit: &mut Edit, action: Action) {
+        match action {
+            // Handled by the caller, which needs more than the line
+            Action::AcceptLine | Action::ReverseSearchHistory | Action::Complete | Action::ClearScreen => {}
+            Action::ViCommandMode => {
+                edit.insert = false;
+                edit.cursor = edit.backward_chars(edit.cursor, 1);
+            }
+            Action::BeginningOfLine => edit.cursor = 0,
+            Action::EndOfLine => edit.cursor = edit.buffer.len(),
+            Action::ForwardChar => edit.cursor = edit.forward_chars(edit.cursor, 1),
+            Action::BackwardChar => edit.cursor = edit.backward_chars(edit.cursor, 1),
+            Action::ForwardWord => edit.cursor = edit.word_end(),
+            Action::BackwardWord => edit.cursor = edit.word_start(),
+            Action::DeleteCharOrEof | Action::DeleteChar => {
+                let end = edit.forward_chars(edit.cursor, 1);
+                edit.buffer.drain(edit.cursor..end);
+            }
+            Action::BackwardDeleteChar => {
+                let start = edit.backward_chars(edit.cursor, 1);
+                edit.buffer.drain(start..edit.cursor);
+                edit.cursor = start;
+            }
+            Action::KillLine => {
+                let end = edit.buffer.len();
+                self.kill(edit, end);
+            }
+            Action::UnixLineDiscard => self.kill(edit, 0),
+            Action::UnixWordRubout => {
+                let start = edit.big_word_start();
+                self.kill(edit, start);
+            }
+            Action::KillWord => {
+                let end = edit.word_end();
+                self.kill(edit, end);
+            }
+            Action::BackwardKillWord => {
+                let start = edit.word_start();
+                self.kill(edit, start);
+            }
+            Action::Undo => edit.undo(false),
+            Action::Redo => edit.undo(true),
+            Action::PreviousHistory => edit.recall(edit.entry.saturating_sub(1)),
+            Action::NextHistory => edit.recall((edit.entry + 1).min(edit.history.len())),
+            Action::Yank => {
+                for c in self.killed.chars() {
+                    edit.buffer.insert(edit.cursor, c);
+                    edit.cursor += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HISTORY: [&str; 3] = ["echo one", "ls two", "echo three"];
+
+    /// Types `keys` in vi's command mode on `line`, with the cursor at
+    /// `cursor` and `HISTORY` to recall, returning the line and where the
+    /// cursor is left.
+    fn vi(line: &str, cursor: usize, keys: &str) -> (String, usize) {
+        type_keys(true, line, cursor, keys)
+    }
+
+    /// Types `keys` on `line` as `vi` does, in Emacs mode.
+    fn emacs(line: &str, cursor: usize, keys: &str) -> (String, usize) {
+        type_keys(false, line, cursor, keys)
+    }
+
+    fn type_keys(vi: bool, line: &str, cursor: usize, keys: &str) -> (String, usize) {
+        let mut shell = Shell::new();
+        shell.options.vi = vi;
+        let mut editor = Editor::default();
+        let mut keys = keys.as_bytes().iter();
+        let mut edit = Edit::new(&shell, None, &mut keys, "", "");
+        edit.history = Cow::Owned(HISTORY.iter().map(|line| line.to_string()).collect());
+        edit.entry = HISTORY.len();
+        edit.buffer = line.chars().collect();
+        edit.cursor = cursor;
+        edit.saved = (edit.buffer.clone(), cursor);
+        edit.insert = !vi;
+        loop {
+            match editor.step(&mut edit, &shell) {
+                Ok(None) => {}
+                Ok(Some(_)) => break,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        (edit.buffer.iter().collect(), edit.cursor)
+    }
+
+    fn cursor_after(keys: &str, cursor: usize) -> usize {
+        vi("echo hello-world foo", cursor, keys).1
+    }
+
+    #[test]
+    fn moves_by_characters_and_words() {
+        assert_eq!(cursor_after("l", 0), 1);
+        assert_eq!(cursor_after("3h", 4), 1);
+        assert_eq!(cursor_after("w", 0), 5);
+        assert_eq!(cursor_after("2w", 0), 10);
+        assert_eq!(cursor_after("W", 5), 17);
+        assert_eq!(cursor_after("e", 0), 3);
+        assert_eq!(cursor_after("b", 17), 11);
+        assert_eq!(cursor_after("B", 17), 5);
+        assert_eq!(cursor_after("0", 10), 0);
+        // The cursor stays on the last character rather than after it
+        assert_eq!(cursor_after("$", 0), 19);
+        assert_eq!(cursor_after("10l", 15), 19);
+    }
+
+    #[test]
+    fn deletes_characters() {
+        assert_eq!(vi("hello", 0, "x"), ("ello".to_string(), 0));
+        assert_eq!(vi("hello", 1, "3x"), ("ho".to_string(), 1));
+        assert_eq!(vi("hello", 4, "x"), ("hell".to_string(), 3));
+        assert_eq!(vi("hello", 3, "X"), ("helo".to_string(), 2));
+    }
+
+    #[test]
+    fn applies_operators_to_motions() {
+        assert_eq!(vi("echo hello world", 0, "dw"), ("hello world".to_string(), 0));
+        assert_eq!(vi("echo hello world", 0, "2dw"), ("world".to_string(), 0));
+        assert_eq!(vi("echo hello world", 11, "db"), ("echo world".to_string(), 5));
+        assert_eq!(vi("echo hello world", 0, "de"), (" hello world".to_string(), 0));
+        assert_eq!(vi("echo hello world", 5, "d$"), ("echo ".to_string(), 4));
+        assert_eq!(vi("echo hello world", 4, "D"), ("echo".to_string(), 3));
+        assert_eq!(vi("echo hello world", 7, "dd"), (String::new(), 0));
+    }
+
+    #[test]
+    fn changes_text_in_insert_mode() {
+        // `cw` stops at the end of the word, leaving the space after it
+        assert_eq!(vi("echo hello", 0, "cwprint\x1b"), ("print hello".to_string(), 4));
+        assert_eq!(vi("echo", 0, "A hi\x1b"), ("echo hi".to_string(), 6));
+        assert_eq!(vi("hi", 1, "Isay \x1b"), ("say hi".to_string(), 3));
+        assert_eq!(vi("ab", 0, "aX\x1b"), ("aXb".to_string(), 1));
+        assert_eq!(vi("echo hello", 2, "ccls\x1b"), ("ls".to_string(), 1));
+    }
+
+    #[test]
+    fn yanks_and_puts() {
+        assert_eq!(vi("echo hi", 0, "ywP"), ("echo echo hi".to_string(), 4));
+        assert_eq!(vi("ab", 0, "xp"), ("ba".to_string(), 1));
+        // Yanking leaves the line alone
+        assert_eq!(vi("echo hi", 5, "yy"), ("echo hi".to_string(), 5));
+    }
+
+    #[test]
+    fn searches_history() {
+        assert_eq!(vi("", 0, "/ls\r"), ("ls two".to_string(), 0));
+        assert_eq!(vi("", 0, "/echo\r"), ("echo three".to_string(), 0));
+        assert_eq!(vi("", 0, "/nothing\r"), (String::new(), 0));
+        // `?` searches forward, back towards the new line
+        assert_eq!(vi("", 0, "/one\r?echo\r"), ("echo three".to_string(), 0));
+    }
+
+    #[test]
+    fn repeats_searches() {
+        assert_eq!(vi("", 0, "/echo\rn"), ("echo one".to_string(), 0));
+        assert_eq!(vi("", 0, "/echo\rnN"), ("echo three".to_string(), 0));
+        // Past the last match there's nothing more to find
+        assert_eq!(vi("", 0, "/echo\rnn"), ("echo one".to_string(), 0));
+        // An empty pattern is the last one again
+        assert_eq!(vi("", 0, "/echo\r/\r"), ("echo one".to_string(), 0));
+    }
+
+    #[test]
+    fn gives_up_on_searches() {
+        assert_eq!(vi("keep", 2, "/ls\x1b"), ("keep".to_string(), 2));
+        assert_eq!(vi("keep", 2, "/l\x7f\x7f"), ("keep".to_string(), 2));
+        assert_eq!(vi("keep", 2, "/ls\x07"), ("keep".to_string(), 2));
+    }
+
+    #[test]
+    fn undoes_changes() {
+        // Characters typed one after another go together
+        assert_eq!(emacs("", 0, "echo hi\x1f"), (String::new(), 0));
+        assert_eq!(emacs("", 0, "echo\x02\x02x\x1f"), ("echo".to_string(), 2));
+        assert_eq!(emacs("", 0, "ab\x7fc\x1f"), ("a".to_string(), 1));
+        assert_eq!(emacs("", 0, "ab\x7fc\x1f\x1f"), ("ab".to_string(), 2));
+        assert_eq!(emacs("echo hi", 7, "\x17\x1f"), ("echo hi".to_string(), 7));
+        assert_eq!(emacs("hi", 2, "\x1f"), ("hi".to_string(), 2));
+
+        assert_eq!(vi("hello", 0, "xu"), ("hello".to_string(), 0));
+        assert_eq!(vi("hello", 0, "xxuu"), ("hello".to_string(), 0));
+        assert_eq!(vi("echo hello world", 0, "dwdwuu"), ("echo hello world".to_string(), 0));
+    }
+
+    #[test]
+    fn redoes_changes_undone() {
+        assert_eq!(emacs("", 0, "ab\x1f\x1b_"), ("ab".to_string(), 2));
+        assert_eq!(emacs("", 0, "ab\x7f\x1f\x1f\x1b_\x1b_"), ("a".to_string(), 1));
+        // A new change can't be followed by one undone before it
+        assert_eq!(emacs("", 0, "ab\x1fc\x1b_"), ("c".to_string(), 1));
+
+        assert_eq!(vi("hello", 0, "xu\x12"), ("ello".to_string(), 0));
+        assert_eq!(vi("hello", 0, "xxuu\x12"), ("ello".to_string(), 0));
+    }
+}
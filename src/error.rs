@@ -0,0 +1,113 @@
+//! The errors the shell reports, and the exit status each one gives the
+//! command it happened in.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum ShellError {
+    /// A token where the grammar doesn't allow one
+    UnexpectedToken(String),
+    /// A command line that ended in the middle of a command
+    UnexpectedEof,
+    /// Input that ended inside a quote
+    UnmatchedQuote(char),
+    /// A `${...}` that isn't a valid parameter expansion
+    BadSubstitution(String),
+    /// A `${VAR:?message}` or `${VAR?message}` whose parameter wasn't set
+    ParameterUnset { name: String, message: String },
+    /// A `${VAR:=word}` naming a special or positional parameter
+    CannotAssign(String),
+    /// A pattern that matched no files, with failglob set
+    NoMatch(String),
+    /// A redirection target that expanded to no words or several
+    AmbiguousRedirect(String),
+    /// A file that couldn't be opened, such as a redirection target
+    File { path: String, source: io::Error },
+    /// A command that is neither a builtin nor in PATH
+    NotFound(String),
+    /// A command that was found but couldn't be executed
+    Exec { command: String, source: io::Error },
+    /// A builtin given arguments it can't make sense of
+    Usage { command: String, message: String },
+    /// A builtin that failed
+    Builtin { command: String, message: String },
+    /// A history reference, such as `!foo`, that can't be expanded
+    History { reference: String, problem: &'static str },
+    /// A system call the shell made on its own behalf, such as `fork`
+    System { call: &'static str, source: io::Error },
+    /// A command or directory found through a relative or world-writable
+    /// entry of `variable`, PATH or CDPATH, with SHELLOB_PATHCHECK set
+    UntrustedEntry { path: String, variable: &'static str, entry: String, reason: &'static str },
+    /// Input that ended before a here-document's delimiter line. Only a
+    /// warning: the body is what was read.
+    HereDocEof(String),
+}
+
+impl ShellError {
+    /// The exit status of a command that failed with this error: 2 for
+    /// misuse, 126 for a command that can't be executed, 127 for one
+    /// that wasn't found, and 1 otherwise.
+    pub fn status(&self) -> i32 {
+        match self {
+            ShellError::UnexpectedToken(_)
+            | ShellError::UnexpectedEof
+            | ShellError::UnmatchedQuote(_)
+            | ShellError::Usage { .. } => 2,
+            ShellError::Exec { source, .. } if source.kind() == io::ErrorKind::PermissionDenied => 126,
+            ShellError::NotFound(_) | ShellError::Exec { .. } => 127,
+            _ => 1,
+        }
+    }
+
+    pub fn builtin(command: &str, message: impl fmt::Display) -> ShellError {
+        ShellError::Builtin { command: command.to_string(), message: message.to_string() }
+    }
+
+    pub fn usage(command: &str, message: impl fmt::Display) -> ShellError {
+        ShellError::Usage { command: command.to_string(), message: message.to_string() }
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShellError::UnexpectedToken(token) => write!(f, "syntax error near unexpected token `{}'", token),
+            ShellError::UnexpectedEof => write!(f, "syntax error: unexpected end of file"),
+            ShellError::UnmatchedQuote(quote) => {
+                write!(f, "unexpected EOF while looking for matching `{}'", quote)
+            }
+            ShellError::BadSubstitution(body) => write!(f, "${{{}}}: bad substitution", body),
+            ShellError::ParameterUnset { name, message } => write!(f, "{}: {}", name, message),
+            ShellError::CannotAssign(name) => write!(f, "${}: cannot assign in this way", name),
+            ShellError::NoMatch(pattern) => write!(f, "no match: {}", pattern),
+            ShellError::AmbiguousRedirect(target) => write!(f, "{}: ambiguous redirect", target),
+            ShellError::File { path, source } => write!(f, "{}: {}", path, source),
+            ShellError::NotFound(command) => write!(f, "{}: command not found", command),
+            ShellError::Exec { command, source } => write!(f, "{}: {}", command, source),
+            ShellError::Usage { command, message } | ShellError::Builtin { command, message } => {
+                write!(f, "{}: {}", command, message)
+            }
+            ShellError::History { reference, problem } => write!(f, "{}: {}", reference, problem),
+            ShellError::System { call, source } => write!(f, "{}: {}", call, source),
+            ShellError::UntrustedEntry { path, variable, entry, reason } => {
+                write!(f, "{}: found via {} {} entry '{}'", path, reason, variable, entry)
+            }
+            ShellError::HereDocEof(delimiter) => {
+                write!(f, "warning: here-document delimited by end-of-file (wanted `{}')", delimiter)
+            }
+        }
+    }
+}
+
+impl Error for ShellError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShellError::File { source, .. }
+            | ShellError::Exec { source, .. }
+            | ShellError::System { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,547 @@
+//! Running parsed command lines: lists, pipelines, groups and simple
+//! commands, and waiting for the jobs they start.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{self, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::jobs::{Job, JobState, Jobs};
+use crate::lexer;
+use crate::output;
+use crate::expand;
+use crate::parser::{self, AndOr, CaseArm, CaseEnd, Command, CommandKind, Connector, List, Pipeline};
+use crate::redirect::{FdTable, Redirect, RedirectOp};
+use crate::signals;
+use crate::sys::{self, WaitStatus};
+use crate::{CommandType, Shell, ShellError};
+
+impl Shell {
+    // Parses and runs a command line, given the bodies of the
+    // here-documents it starts.
+    pub fn handle_command(&mut self, input: &str, heredocs: VecDeque<String>) {
+        match parser::parse(&lexer::lex(input), heredocs) {
+            Ok(list) => self.run_list(&list),
+            Err(e) => self.last_status = self.report(&e),
+        }
+    }
+
+    fn run_list(&mut self, list: &List) {
+        for item in &list.items {
+            self.last_status = if item.background {
+                self.run_background(&item.and_or)
+            } else {
+                self.run_and_or(&item.and_or)
+            };
+            self.run_traps();
+            if self.flow.is_some() {
+                break;
+            }
+        }
+    }
+
+    // `a && b` runs b only if a succeeded and `a || b` only if it failed;
+    // a skipped pipeline passes the previous status along.
+    fn run_and_or(&mut self, and_or: &AndOr) -> i32 {
+        self.last_status = self.run_pipeline(&and_or.first, false);
+        for (connector, pipeline) in &and_or.rest {
+            if self.flow.is_some() {
+                break;
+            }
+            let run = match connector {
+                Connector::And => self.last_status == 0,
+                Connector::Or => self.last_status != 0,
+            };
+            if run {
+                self.last_status = self.run_pipeline(pipeline, false);
+            }
+        }
+        self.last_status
+    }
+
+    // Starts an and-or list that ended in `&`. A lone pipeline becomes
+    // the job itself; a longer list needs a subshell to run it.
+    fn run_background(&mut self, and_or: &AndOr) -> i32 {
+        self.wait_for_job_slot();
+        if and_or.rest.is_empty() {
+            return self.run_pipeline(&and_or.first, true);
+        }
+        let run = |shell: &mut Shell| shell.run_and_or(and_or);
+        match self.fork_stage(None, None, None, 0, false, run) {
+            Ok(pid) => self.finish_spawn(&[pid], true, &format!("{} &", and_or.text())),
+            Err(e) => self.report(&e),
+        }
+    }
+
+    // With MAXJOBS set, waits until fewer than that many background jobs
+    // are running, so a script can start any number of them a few at a
+    // time.
+    fn wait_for_job_slot(&mut self) {
+        let Some(max) = self.parameter("MAXJOBS").and_then(|max| max.parse::<usize>().ok()) else {
+            return;
+        };
+        while self.jobs.running() >= max.max(1) {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn run_pipeline(&mut self, pipeline: &Pipeline, background: bool) -> i32 {
+        let pending = self.process_substitutions.borrow().len();
+        let status = match pipeline.commands.as_slice() {
+            // A lone command runs in the shell itself, unless it is a
+            // subshell or has to go in the background
+            [command] if !background && !matches!(command.kind, CommandKind::Subshell(_)) => {
+                let status = self.run_command(command, &pipeline.text, false);
+                self.set_variable("PIPESTATUS", &status.to_string());
+                status
+            }
+            commands => {
+                let text = match background {
+                    true => format!("{} &", pipeline.text),
+                    false => pipeline.text.clone(),
+                };
+                self.run_stages(commands, background, &text)
+            }
+        };
+        self.finish_process_substitutions(pending);
+
+        // `! pipeline` inverts the status
+        if pipeline.negated && !background {
+            (status == 0) as i32
+        } else {
+            status
+        }
+    }
+
+    // Starts each command of a pipeline in a process of its own, with a
+    // pipe from each one's stdout to the next one's stdin, and waits for
+    // them or puts them in the background as one job.
+    fn run_stages(&mut self, commands: &[Command], background: bool, text: &str) -> i32 {
+        let mut pids = Vec::new();
+        let mut stdin = None;
+        for (i, command) in commands.iter().enumerate() {
+            let (reader, writer) = if i + 1 < commands.len() {
+                match sys::pipe() {
+                    Ok((reader, writer)) => (Some(reader), Some(writer)),
+                    Err(source) => {
+                        self.report(&ShellError::System { call: "pipe", source });
+                        break;
+                    }
+                }
+            } else {
+                (None, None)
+            };
+            let pgid = pids.first().copied().unwrap_or(0);
+            let run = |shell: &mut Shell| shell.run_command(command, "", true);
+            match self.fork_stage(stdin.take(), writer, reader.as_ref(), pgid, !background, run) {
+                Ok(pid) => pids.push(pid),
+                Err(e) => {
+                    self.report(&e);
+                    break;
+                }
+            }
+            stdin = reader;
+        }
+        // If a stage couldn't be started, the one before it mustn't be
+        // left writing to a pipe nobody reads
+        drop(stdin);
+        if pids.is_empty() {
+            return 1;
+        }
+
+        let status = self.finish_spawn(&pids, background, text);
+        if pids.len() < commands.len() {
+            1
+        } else {
+            status
+        }
+    }
+
+    // Forks a copy of the shell to run `run` as one process of a job,
+    // with `stdin` and `stdout`, if given, as its standard input and
+    // output, and `other`, the far end of the pipe its output goes to,
+    // closed. With job control the child joins process group `pgid`, or
+    // starts its own if that is 0, and takes the terminal if it runs in
+    // the foreground. Returns the child's pid.
+    fn fork_stage(
+        &mut self,
+        stdin: Option<File>,
+        stdout: Option<File>,
+        other: Option<&File>,
+        pgid: i32,
+        foreground: bool,
+        run: impl FnOnce(&mut Shell) -> i32,
+    ) -> Result<i32, ShellError> {
+        output::flush();
+        let pid = match sys::fork() {
+            Ok(0) => {
+                // The child has no jobs of its own and leaves job control
+                // to the shell that started it. Exiting it isn't logging
+                // out either, and it draws its own random numbers. Signals
+                // ignored stay ignored, but the others aren't trapped.
+                self.jobs = Jobs::default();
+                self.login = false;
+                self.random.set(Shell::random_seed());
+                self.traps.retain(|_, command| command.is_empty());
+                if self.job_control {
+                    let _ = sys::setpgid(0, pgid);
+                    if foreground {
+                        let _ = sys::tcsetpgrp(0, sys::getpgrp());
+                    }
+                }
+                let _ = signals::restore_defaults();
+                if let Some(other) = other {
+                    let _ = sys::close(other.as_raw_fd());
+                }
+
+                // Without job control a background job can't be stopped
+                // for reading the terminal, so it must not read it at all
+                let stdin = match stdin {
+                    None if !foreground && !self.job_control => File::open("/dev/null").ok(),
+                    stdin => stdin,
+                };
+                self.job_control = false;
+                // The child's own descriptors are its to change, so the
+                // files standing in for the shell's can take their place
+                if let Err(source) = self.streams.install_here() {
+                    process::exit(self.report(&ShellError::System { call: "dup2", source }));
+                }
+                self.streams = FdTable::default();
+                for (file, fd) in [(stdin, 0), (stdout, 1)] {
+                    let Some(file) = file else {
+                        continue;
+                    };
+                    if let Err(source) = sys::dup2(file.as_raw_fd(), fd) {
+                        process::exit(self.report(&ShellError::System { call: "dup2", source }));
+                    }
+                }
+
+                let status = run(self);
+                let status = self.run_exit_trap(status);
+                output::flush();
+                process::exit(status);
+            }
+            Ok(pid) => pid,
+            Err(source) => return Err(ShellError::System { call: "fork", source }),
+        };
+        // Also done by the child itself; whichever runs first wins
+        if self.job_control {
+            let _ = sys::setpgid(pid, if pgid == 0 { pid } else { pgid });
+        }
+        Ok(pid)
+    }
+
+    // Runs one command in the shell itself. With `replace` the shell is a
+    // copy forked to run just this command, so an external command can
+    // take its place instead of being waited for. Any error is reported
+    // here and turned into the command's status.
+    fn run_command(&mut self, command: &Command, text: &str, replace: bool) -> i32 {
+        let result = match &command.kind {
+            CommandKind::Simple(words) => self.execute_simple(words, &command.redirects, text, replace),
+            // A subshell has been forked for by the time it gets here
+            CommandKind::Subshell(body) | CommandKind::Group(body) => {
+                self.execute_group(body, &command.redirects)
+            }
+            CommandKind::Case(word, arms) => self.execute_case(word, arms, &command.redirects),
+        };
+        result.unwrap_or_else(|e| self.report(&e))
+    }
+
+    // Builds the fd table for a command's redirections, expanding their
+    // targets and applying them left to right.
+    fn open_redirections(&mut self, redirects: &[Redirect]) -> Result<FdTable, ShellError> {
+        let mut fds = FdTable::default();
+        for redirect in redirects {
+            let mut redirect = redirect.clone();
+            if let RedirectOp::HereDoc { expand, .. } = redirect.op {
+                if expand {
+                    redirect.target = self.expand_heredoc(&redirect.target);
+                    if let Some(e) = self.expansion_error.take() {
+                        return Err(e);
+                    }
+                }
+            } else {
+                let mut targets = self.expand_words(std::slice::from_ref(&redirect.target));
+                if let Some(e) = self.expansion_error.take() {
+                    return Err(e);
+                }
+                if targets.len() != 1 {
+                    return Err(ShellError::AmbiguousRedirect(redirect.target));
+                }
+                redirect.target = targets.remove(0);
+            }
+            if let Err(source) = fds.apply(&redirect, self.options.noclobber, &self.streams) {
+                return Err(ShellError::File { path: redirect.target, source });
+            }
+        }
+        Ok(fds)
+    }
+
+    // Runs the list inside `( ... )` or `{ ...; }` with the group's
+    // redirections standing in for the shell's own descriptors while it
+    // runs.
+    fn execute_group(&mut self, body: &List, redirects: &[Redirect]) -> Result<i32, ShellError> {
+        let fds = self.open_redirections(redirects)?;
+        let saved = self.streams.overlay(fds);
+        self.run_list(body);
+        self.streams.restore(saved);
+        Ok(self.last_status)
+    }
+
+    // Runs the commands of the first arm of a `case` with a pattern the
+    // word matches, then goes on as the arm ends: `;&` runs the next
+    // arm's commands too, whatever its patterns, and `;;&` tries the
+    // patterns of the arms after it. The status is the last command's,
+    // or 0 if none ran.
+    fn execute_case(&mut self, word: &str, arms: &[CaseArm], redirects: &[Redirect]) -> Result<i32, ShellError> {
+        let word = self.expand_word(word);
+        if let Some(e) = self.expansion_error.take() {
+            return Err(e);
+        }
+        let fds = self.open_redirections(redirects)?;
+        let saved = self.streams.overlay(fds);
+        let mut status = 0;
+        let mut fall_through = false;
+        for arm in arms {
+            let mut matched = fall_through;
+            for pattern in &arm.patterns {
+                if matched {
+                    break;
+                }
+                matched = expand::matches(&self.expand_pattern(pattern), &word);
+            }
+            if let Some(e) = self.expansion_error.take() {
+                self.streams.restore(saved);
+                return Err(e);
+            }
+            if !matched {
+                continue;
+            }
+            self.run_list(&arm.body);
+            status = self.last_status;
+            if self.flow.is_some() || arm.end == CaseEnd::Break {
+                break;
+            }
+            fall_through = arm.end == CaseEnd::FallThrough;
+        }
+        self.streams.restore(saved);
+        Ok(status)
+    }
+
+    // Runs one simple command, returning its exit status.
+    fn execute_simple(
+        &mut self,
+        words: &[String],
+        redirects: &[Redirect],
+        text: &str,
+        replace: bool,
+    ) -> Result<i32, ShellError> {
+        self.substitution_status.set(None);
+        let words = self.expand_words(words);
+        if let Some(e) = self.expansion_error.take() {
+            return Err(e);
+        }
+        if words.is_empty() && redirects.is_empty() {
+            return Ok(self.substitution_status.take().unwrap_or(self.last_status));
+        }
+        let fds = self.open_redirections(redirects)?;
+
+        // Leading `NAME=value` words set shell variables when they make up
+        // the whole command, and only the command's environment otherwise
+        let count = words.iter().take_while(|word| Shell::assignment(word).is_some()).count();
+        let (assignments, words) = words.split_at(count);
+        let assignments = assignments.iter().filter_map(|word| Shell::assignment(word));
+        if words.is_empty() {
+            for (name, value) in assignments {
+                self.set_variable(name, value);
+            }
+            return Ok(self.substitution_status.take().unwrap_or(0));
+        }
+
+        // With autocd, a directory typed on its own is changed into,
+        // unless there is a command by that name
+        let autocd;
+        let words = match words {
+            [dir] if self.options.autocd
+                && !self.commands.contains_key(dir)
+                && self.find_in_path(dir).is_none()
+                && self.logical_path(Path::new(dir)).is_dir() =>
+            {
+                autocd = ["cd".to_string(), "--".to_string(), dir.clone()];
+                &autocd[..]
+            }
+            words => words,
+        };
+
+        let command = &words[0];
+        let arguments = &words[1..];
+
+        if let Some(cmd_type) = self.commands.get(command).cloned() {
+            // Handle builtin commands
+            match cmd_type {
+                CommandType::Builtin(func) => {
+                    let saved = self.streams.overlay(fds);
+                    let file = self.streams.get(1).and_then(|file| file.try_clone().ok());
+                    let mut redirected = file.as_ref().map(BufWriter::new);
+                    let out: &mut dyn Write = match &mut redirected {
+                        Some(writer) => writer,
+                        None => &mut output::Stdout,
+                    };
+                    // Reported here so the message goes to the builtin's
+                    // own stderr
+                    let status = func(self, arguments, out).unwrap_or_else(|e| self.report(&e));
+                    // Stdout itself is left to flush when it has to
+                    if let Some(mut writer) = redirected {
+                        let _ = writer.flush();
+                    }
+                    self.streams.restore(saved);
+                    Ok(status)
+                }
+            }
+        } else if let Some(path) = self.command_path(command) {
+            // Execute the external command
+            let mut cmd = process::Command::new(path);
+            cmd.arg0(command).args(arguments);
+            cmd.env_clear().envs(self.environment()).envs(assignments);
+
+            // Unredirected streams are the shell's own terminal, or the
+            // files standing in for its descriptors, so interactive and
+            // full-screen programs work
+            let mut fds = self.streams.inherited_by(fds).map_err(|source| ShellError::System { call: "dup", source })?;
+            let stdio = |file: Option<File>| file.map_or_else(Stdio::inherit, Stdio::from);
+            cmd.stdin(stdio(fds.take(0)))
+                .stdout(stdio(fds.take(1)))
+                .stderr(stdio(fds.take(2)));
+            fds.install(&mut cmd).map_err(|source| ShellError::System { call: "dup", source })?;
+            let failed = |source| ShellError::Exec { command: command.clone(), source };
+
+            // The command writes straight to the shell's stdout, so
+            // anything still buffered on our side has to go out first
+            output::flush();
+            if replace {
+                // The forked shell has restored the signal dispositions
+                // already. This only returns if the command couldn't be
+                // run.
+                return Err(failed(cmd.exec()));
+            }
+
+            // With job control each command gets its own process group,
+            // so Ctrl-C and Ctrl-Z reach it but not the shell. The child
+            // also takes the terminal itself, so it never runs before
+            // owning it; the shell does the same after spawning. Its stdin
+            // may have been redirected away from the terminal by then, in
+            // which case it's left to the shell.
+            if self.job_control {
+                cmd.process_group(0);
+                unsafe {
+                    cmd.pre_exec(|| {
+                        let _ = sys::tcsetpgrp(0, sys::getpid());
+                        Ok(())
+                    });
+                }
+            }
+            unsafe {
+                cmd.pre_exec(signals::restore_defaults);
+            }
+
+            let child = cmd.spawn().map_err(failed)?;
+            Ok(self.finish_spawn(&[child.id() as i32], false, text))
+        } else {
+            Err(ShellError::NotFound(command.clone()))
+        }
+    }
+
+    // The file to run for the external command `name`: the name itself
+    // if it has a slash in it, which isn't looked for in PATH, or else
+    // the first match in PATH.
+    fn command_path(&self, name: &str) -> Option<String> {
+        match name.contains('/') {
+            true => Some(name.to_string()),
+            false => self.find_in_path(name),
+        }
+    }
+
+    // Enters freshly started processes in the jobs table as one job if
+    // they run in the background, or waits for them otherwise.
+    fn finish_spawn(&mut self, pids: &[i32], background: bool, text: &str) -> i32 {
+        let job = Job::new(pids, self.job_control, text, JobState::Running);
+        if !background {
+            return self.wait_foreground(job);
+        }
+        let pid = job.last_pid();
+        let job = self.jobs.add(job);
+        let _ = writeln!(self.streams.writer(1), "[{}] {}", job.id, pid);
+        self.last_background = Some(pid);
+        0
+    }
+
+    // Hands the terminal to the job's process group and waits for it to
+    // exit or stop, resuming it first if it is stopped. A job that stops
+    // is (re-)entered in the jobs table; fresh commands have id 0 until
+    // then. Returns the job's exit status, 128+n for signal n, after
+    // recording each process's in PIPESTATUS. With pipefail that's the
+    // status of the last process to fail.
+    pub fn wait_foreground(&mut self, mut job: Job) -> i32 {
+        if self.job_control {
+            let _ = sys::tcsetpgrp(0, job.pgid());
+            if let Some(tmodes) = job.tmodes.take() {
+                let _ = sys::tcsetattr(0, &tmodes);
+            }
+        }
+        if job.state == JobState::Stopped {
+            let _ = job.resume();
+        }
+        let status = job.wait();
+        if self.job_control {
+            let _ = sys::tcsetpgrp(0, sys::getpgrp());
+            if let Ok(WaitStatus::Stopped(_)) = status {
+                job.tmodes = sys::tcgetattr(0).ok();
+            }
+            // Whatever the job did to the terminal, the shell gets its
+            // own modes back
+            if let Some(tmodes) = &self.tmodes {
+                let _ = sys::tcsetattr(0, tmodes);
+            }
+        }
+
+        let statuses = matches!(job.state, JobState::Done(_)).then(|| job.statuses());
+        let status = match status {
+            Ok(WaitStatus::Stopped(sig)) => {
+                let job = if job.id == 0 {
+                    self.jobs.add(job)
+                } else {
+                    self.jobs.insert(job)
+                };
+                let _ = writeln!(self.streams.writer(1), "\n{}", job);
+                128 + sig
+            }
+            Ok(WaitStatus::Signaled(sig)) => {
+                if sig == sys::SIGINT {
+                    // Move past the ^C the terminal echoed
+                    signals::take_interrupt();
+                    let _ = writeln!(self.streams.writer(1));
+                }
+                128 + sig
+            }
+            Ok(WaitStatus::Exited(code)) => code,
+            Ok(WaitStatus::Continued) => 0,
+            Err(source) => self.report(&ShellError::System { call: "waitpid", source }),
+        };
+
+        let Some(statuses) = statuses else {
+            self.set_variable("PIPESTATUS", &status.to_string());
+            return status;
+        };
+        let words: Vec<_> = statuses.iter().map(i32::to_string).collect();
+        self.set_variable("PIPESTATUS", &words.join(" "));
+        match statuses.iter().rev().find(|&&status| status != 0) {
+            Some(&failed) if self.options.pipefail => failed,
+            _ => status,
+        }
+    }
+}
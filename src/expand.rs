@@ -0,0 +1,666 @@
+//! Parameter expansion: the operators that can follow a name inside
+//! `${...}`, and the shell patterns some of them take.
+
+use crate::ShellError;
+
+/// Names of parameters that aren't variables
+const SPECIAL: &[char] = &['?', '$', '!', '#', '@', '*'];
+
+/// Whether `c` names a special or positional parameter, such as `?` in
+/// `$?` or `1` in `$1`.
+pub fn is_special(c: char) -> bool {
+    SPECIAL.contains(&c) || c.is_ascii_digit()
+}
+
+/// Splits the body of a `${...}` into the parameter name and whatever
+/// follows it.
+fn split_name(body: &str) -> (&str, &str) {
+    if body.starts_with(SPECIAL) {
+        return body.split_at(1);
+    }
+    // `${10}` and up need the braces; `$10` is `${1}0`
+    if body.starts_with(|c: char| c.is_ascii_digit()) {
+        let end = body.find(|c: char| !c.is_ascii_digit()).unwrap_or(body.len());
+        return body.split_at(end);
+    }
+    let end = body
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(body.len());
+    body.split_at(end)
+}
+
+/// What expanding a `${...}` needs from the shell.
+pub trait Context {
+    /// The value of the parameter `name`, if it's set
+    fn lookup(&self, name: &str) -> Option<String>;
+    /// Sets the variable `name`, for `${VAR:=word}`
+    fn assign(&mut self, name: &str, value: &str);
+    /// A word an operator takes (a default, pattern or replacement),
+    /// expanded
+    fn word(&mut self, word: &str) -> String;
+}
+
+/// Expands the body of a `${...}`, looking names up and expanding the
+/// words operators take in `context`. Fails for a bad substitution, a
+/// `${VAR:?message}` whose parameter isn't set, or a `${1:=word}`.
+pub fn parameter(body: &str, context: &mut dyn Context) -> Result<String, ShellError> {
+    let bad = || ShellError::BadSubstitution(body.to_string());
+    // `${#VAR}` is the length of the value in characters, but `${#}` is
+    // the `#` parameter itself
+    if let Some(name) = body.strip_prefix('#').filter(|name| !name.is_empty()) {
+        let (name, rest) = split_name(name);
+        if name.is_empty() || !rest.is_empty() {
+            return Err(bad());
+        }
+        return Ok(context.lookup(name).unwrap_or_default().chars().count().to_string());
+    }
+
+    let (name, rest) = split_name(body);
+    if name.is_empty() {
+        return Err(bad());
+    }
+    let value = context.lookup(name);
+    if rest.is_empty() {
+        return Ok(value.unwrap_or_default());
+    }
+
+    // `:-`, `:=`, `:?` and `:+` treat an empty value like an unset one;
+    // without the colon they only look at whether it is set
+    let (colon, op) = match rest.strip_prefix(':') {
+        Some(op) => (true, op),
+        None => (false, rest),
+    };
+    let set = value.as_ref().is_some_and(|value| !colon || !value.is_empty());
+    if let Some(default) = op.strip_prefix('-') {
+        return Ok(if set { value.unwrap_or_default() } else { context.word(default) });
+    }
+    if let Some(default) = op.strip_prefix('=') {
+        if set {
+            return Ok(value.unwrap_or_default());
+        }
+        if name.starts_with(is_special) {
+            return Err(ShellError::CannotAssign(name.to_string()));
+        }
+        let default = context.word(default);
+        context.assign(name, &default);
+        return Ok(default);
+    }
+    if let Some(message) = op.strip_prefix('?') {
+        if set {
+            return Ok(value.unwrap_or_default());
+        }
+        let message = match message.is_empty() {
+            true => "parameter null or not set".to_string(),
+            false => context.word(message),
+        };
+        return Err(ShellError::ParameterUnset { name: name.to_string(), message });
+    }
+    if let Some(alternative) = op.strip_prefix('+') {
+        return Ok(if set { context.word(alternative) } else { String::new() });
+    }
+    if colon {
+        return substring(&value.unwrap_or_default(), op).ok_or_else(bad);
+    }
+
+    let value = value.unwrap_or_default();
+    let result = if let Some(pattern) = rest.strip_prefix("##") {
+        remove_prefix(&value, &context.word(pattern), true)
+    } else if let Some(pattern) = rest.strip_prefix('#') {
+        remove_prefix(&value, &context.word(pattern), false)
+    } else if let Some(pattern) = rest.strip_prefix("%%") {
+        remove_suffix(&value, &context.word(pattern), true)
+    } else if let Some(pattern) = rest.strip_prefix('%') {
+        remove_suffix(&value, &context.word(pattern), false)
+    } else if let Some(spec) = rest.strip_prefix('/') {
+        let (all, spec) = match spec.strip_prefix('/') {
+            Some(spec) => (true, spec),
+            None => (false, spec),
+        };
+        let (pattern, replacement) = split_replacement(spec);
+        replace(&value, &context.word(pattern), &context.word(replacement), all)
+    } else {
+        return Err(bad());
+    };
+    Ok(result)
+}
+
+/// `${VAR:off}` and `${VAR:off:len}`: the characters from `off` on, or
+/// `len` of them. A negative offset counts from the end; it needs a
+/// space after the colon to tell it apart from `:-`. A negative length
+/// leaves off that many characters at the end.
+fn substring(value: &str, spec: &str) -> Option<String> {
+    let (offset, length) = match spec.split_once(':') {
+        Some((offset, length)) => (offset, Some(length)),
+        None => (spec, None),
+    };
+    let count = value.chars().count() as i64;
+    let offset: i64 = offset.trim().parse().ok()?;
+    let start = if offset < 0 { count + offset } else { offset };
+    if start < 0 || start > count {
+        return Some(String::new());
+    }
+    let end = match length {
+        Some(length) => {
+            let length: i64 = length.trim().parse().ok()?;
+            if length < 0 {
+                count + length
+            } else {
+                (start + length).min(count)
+            }
+        }
+        None => count,
+    };
+    if end < start {
+        return None;
+    }
+    Some(value.chars().skip(start as usize).take((end - start) as usize).collect())
+}
+
+/// Splits the `pat/repl` of a substitution at the first `/` that isn't
+/// escaped.
+fn split_replacement(spec: &str) -> (&str, &str) {
+    let mut escaped = false;
+    for (i, c) in spec.char_indices() {
+        match c {
+            '/' if !escaped => return (&spec[..i], &spec[i + 1..]),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    (spec, "")
+}
+
+/// Byte offsets of every character boundary in `s`, including the end.
+fn boundaries(s: &str) -> impl DoubleEndedIterator<Item = usize> + '_ {
+    s.char_indices().map(|(i, _)| i).chain(std::iter::once(s.len()))
+}
+
+/// `${VAR#pat}` and `${VAR##pat}`: removes the shortest or longest prefix
+/// matching `pattern`.
+fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut ends: Box<dyn Iterator<Item = usize>> = if longest {
+        Box::new(boundaries(value).rev())
+    } else {
+        Box::new(boundaries(value))
+    };
+    match ends.find(|&end| matches(pattern, &value[..end])) {
+        Some(end) => value[end..].to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// `${VAR%pat}` and `${VAR%%pat}`: removes the shortest or longest suffix
+/// matching `pattern`.
+fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut starts: Box<dyn Iterator<Item = usize>> = if longest {
+        Box::new(boundaries(value))
+    } else {
+        Box::new(boundaries(value).rev())
+    };
+    match starts.find(|&start| matches(pattern, &value[start..])) {
+        Some(start) => value[..start].to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// `${VAR/pat/repl}` and `${VAR//pat/repl}`: replaces the first or every
+/// longest match of `pattern`. A pattern starting with `#` or `%` must
+/// match at the start or end of the value.
+fn replace(value: &str, pattern: &str, replacement: &str, all: bool) -> String {
+    let (pattern, anchor) = match pattern.chars().next() {
+        Some(c @ ('#' | '%')) => (&pattern[1..], Some(c)),
+        _ => (pattern, None),
+    };
+    if pattern.is_empty() {
+        return value.to_string();
+    }
+
+    let mut result = String::new();
+    let mut rest = 0;
+    for start in boundaries(value) {
+        if start < rest || (anchor == Some('#') && start > 0) {
+            continue;
+        }
+        let end = boundaries(value).rev().take_while(|&end| end > start).find(|&end| {
+            (anchor != Some('%') || end == value.len()) && matches(pattern, &value[start..end])
+        });
+        if let Some(end) = end {
+            result.push_str(&value[rest..start]);
+            result.push_str(replacement);
+            rest = end;
+            if !all {
+                break;
+            }
+        }
+    }
+    result.push_str(&value[rest..]);
+    result
+}
+
+/// Whether `text` matches the shell pattern `pattern` as a whole. `*`
+/// matches any string, `?` any character, and `[...]` any character in
+/// the set, which may contain ranges and be negated with `!` or `^`. A
+/// backslash makes the next character literal.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_at(&pattern, &text)
+}
+
+// Matches one character at a time, and when that fails goes back to the
+// last `*` and has it take one more character. Only the last `*` ever
+// needs to: taking more with an earlier one can't let the rest match
+// where this one couldn't. So a pattern with many stars is no slower
+// than one with a single star.
+fn matches_at(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Just past the last `*` seen, and where in the text it stopped
+    let mut star = None;
+    while t < text.len() {
+        let c = text[t];
+        // How much of the pattern matches `c`, if any of it does
+        let step = match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, t));
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match bracket(&pattern[p + 1..], Some(c)) {
+                Some((true, rest)) => Some(pattern.len() - rest.len() - p),
+                Some((false, _)) => None,
+                // An unclosed `[` is an ordinary character
+                None => (c == '[').then_some(1),
+            },
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(2),
+            Some(&literal) => (literal == c).then_some(1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(step), _) => {
+                p += step;
+                t += 1;
+            }
+            (None, Some((after, from))) => {
+                star = Some((after, from + 1));
+                p = after;
+                t = from + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the bracket expression that `pattern` starts just
+/// after the `[`. Returns whether it matched and the pattern after the
+/// closing `]`, or None if the expression is never closed.
+fn bracket(pattern: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, mut i) = match pattern.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    let start = i;
+    while i < pattern.len() {
+        let first = pattern[i];
+        // A `]` right at the start is part of the set
+        if first == ']' && i > start {
+            return Some((matched != negated, &pattern[i + 1..]));
+        }
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            let last = pattern[i + 2];
+            matched |= c.is_some_and(|c| first <= c && c <= last);
+            i += 3;
+        } else {
+            matched |= c == Some(first);
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Removes every newline from the end of a command substitution's
+/// output. Newlines anywhere else stay, as does a `\r` that came before
+/// the last one.
+pub fn strip_trailing_newlines(output: &mut String) {
+    output.truncate(output.trim_end_matches('\n').len());
+}
+
+/// Brace expansion of one unexpanded word: `a{b,c}d` becomes `abd` and
+/// `acd`, and `{1..3}` becomes `1`, `2` and `3`. Quoted braces, `${` and
+/// braces with neither a comma nor a range inside are left alone.
+pub fn braces(word: &str) -> Vec<String> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    for (k, &(open, c)) in chars.iter().enumerate() {
+        if c != '{' || is_quoted(word, open) || (k > 0 && chars[k - 1].1 == '$') {
+            continue;
+        }
+
+        // Find the matching `}` and the commas directly inside
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for &(i, c) in &chars[k..] {
+            if is_quoted(word, i) {
+                continue;
+            }
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(i);
+                        break;
+                    }
+                }
+                ',' if depth == 1 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            continue;
+        };
+
+        let alternatives = if commas.is_empty() {
+            match range(&word[open + 1..close]) {
+                Some(alternatives) => alternatives,
+                None => continue,
+            }
+        } else {
+            let mut bounds = vec![open];
+            bounds.extend(&commas);
+            bounds.push(close);
+            bounds.windows(2).map(|w| word[w[0] + 1..w[1]].to_string()).collect()
+        };
+
+        let (prefix, suffix) = (&word[..open], &word[close + 1..]);
+        return alternatives
+            .iter()
+            .flat_map(|alternative| braces(&format!("{}{}{}", prefix, alternative, suffix)))
+            .collect();
+    }
+    vec![word.to_string()]
+}
+
+/// Whether the character at byte offset `at` is inside quotes or escaped
+/// with a backslash.
+fn is_quoted(word: &str, at: usize) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in word.char_indices() {
+        if i == at {
+            return quote.is_some() || escaped;
+        }
+        match (c, quote) {
+            _ if escaped => escaped = false,
+            ('\\', q) if q != Some('\'') => escaped = true,
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// The words of a `{start..end}` or `{start..end..step}` range of
+/// integers or single letters. Integers written with a leading zero are
+/// padded to the same width.
+fn range(spec: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = spec.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, None),
+        [start, end, step] => (start, end, Some(step.parse::<i64>().ok()?)),
+        _ => return None,
+    };
+    let step = step.map_or(1, i64::abs).max(1);
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+        let width = if padded(start) || padded(end) { start.len().max(end.len()) } else { 0 };
+        return Some(
+            steps(first, last, step)
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect(),
+        );
+    }
+
+    let letter = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => Some(c as i64),
+            _ => None,
+        }
+    };
+    let (first, last) = (letter(start)?, letter(end)?);
+    Some(steps(first, last, step).map(|c| (c as u8 as char).to_string()).collect())
+}
+
+/// `first`, `first ± step`, ... up to `last`, counting down if `last` is
+/// smaller.
+fn steps(first: i64, last: i64, step: i64) -> impl Iterator<Item = i64> {
+    let count = (first - last).abs() / step + 1;
+    let step = if last < first { -step } else { step };
+    (0..count).map(move |i| first + i * step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Variables for `${...}` to look up, with the words operators take
+    /// used as they are.
+    struct Vars(HashMap<String, String>);
+
+    impl Context for Vars {
+        fn lookup(&self, name: &str) -> Option<String> {
+            self.0.get(name).cloned()
+        }
+
+        fn assign(&mut self, name: &str, value: &str) {
+            self.0.insert(name.to_string(), value.to_string());
+        }
+
+        fn word(&mut self, word: &str) -> String {
+            word.to_string()
+        }
+    }
+
+    fn vars() -> Vars {
+        let vars = [("set", "value"), ("empty", ""), ("path", "/usr/lib/file.tar.gz"), ("wide", "héllo")];
+        Vars(vars.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect())
+    }
+
+    /// Expands `${body}`, with the error as it would be reported if it
+    /// fails.
+    fn expand(body: &str) -> Result<String, String> {
+        parameter(body, &mut vars()).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn matches_patterns() {
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+        assert!(matches("*", ""));
+        assert!(matches("**", "abc"));
+        assert!(matches("a*c", "abbbc"));
+        assert!(matches("a*c", "ac"));
+        assert!(!matches("a*c", "abcd"));
+        assert!(matches("*.tar.*", "file.tar.gz"));
+        assert!(!matches("*.tar.*", "file.tgz"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(matches("*b*b*", "abab"));
+        assert!(!matches("*b*b*", "ab"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[!a-c]x", "bx"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[", "["));
+        assert!(matches("*[", "ab["));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches("*\\?", "what?"));
+        assert!(!matches("*\\?", "what"));
+    }
+
+    #[test]
+    fn matches_many_stars_quickly() {
+        let text = "a".repeat(100);
+        assert!(!matches("*a*a*a*a*b", &text));
+        assert!(matches("*a*a*a*a*", &text));
+        let mut vars = vars();
+        vars.assign("v", &text);
+        assert_eq!(parameter("v//*a*a*a*a*b/x", &mut vars).unwrap(), text);
+        assert_eq!(parameter("v//*a*a*a*a/x", &mut vars).unwrap(), "x");
+    }
+
+    #[test]
+    fn expands_nested_braces() {
+        assert_eq!(braces("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(braces("{a,b{1,2}}c"), ["ac", "b1c", "b2c"]);
+        assert_eq!(braces("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(braces("{{a,b},{c,d}}"), ["a", "b", "c", "d"]);
+        assert_eq!(braces("x{,y}"), ["x", "xy"]);
+        assert_eq!(braces("{a,{1..2}}"), ["a", "1", "2"]);
+    }
+
+    #[test]
+    fn expands_ranges() {
+        assert_eq!(braces("{1..3}"), ["1", "2", "3"]);
+        assert_eq!(braces("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(braces("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(braces("{01..3}"), ["01", "02", "03"]);
+        assert_eq!(braces("{1..10..4}"), ["1", "5", "9"]);
+        assert_eq!(braces("{1..7..-3}"), ["1", "4", "7"]);
+        assert_eq!(braces("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(braces("{C..A}"), ["C", "B", "A"]);
+        assert_eq!(braces("f{1..2}.{a..b}"), ["f1.a", "f1.b", "f2.a", "f2.b"]);
+        // Not ranges, so left alone
+        assert_eq!(braces("{1..}"), ["{1..}"]);
+        assert_eq!(braces("{a..5}"), ["{a..5}"]);
+        assert_eq!(braces("{ab..c}"), ["{ab..c}"]);
+        assert_eq!(braces("{1..3..x}"), ["{1..3..x}"]);
+    }
+
+    #[test]
+    fn leaves_quoted_braces_and_commas_alone() {
+        assert_eq!(braces("'{a,b}'"), ["'{a,b}'"]);
+        assert_eq!(braces("\"{a,b}\""), ["\"{a,b}\""]);
+        assert_eq!(braces("\\{a,b}"), ["\\{a,b}"]);
+        assert_eq!(braces("{a,'b,c'}"), ["a", "'b,c'"]);
+        assert_eq!(braces("{a\\,b,c}"), ["a\\,b", "c"]);
+        assert_eq!(braces("{a,\"}\"}"), ["a", "\"}\""]);
+        assert_eq!(braces("${a,b}"), ["${a,b}"]);
+        assert_eq!(braces("{a}"), ["{a}"]);
+        assert_eq!(braces("{}"), ["{}"]);
+    }
+
+    #[test]
+    fn leaves_unmatched_braces_alone() {
+        assert_eq!(braces("a{b,c"), ["a{b,c"]);
+        assert_eq!(braces("a}b,c{"), ["a}b,c{"]);
+        assert_eq!(braces("{{a,b}"), ["{a", "{b"]);
+        assert_eq!(braces("{a,b}}"), ["a}", "b}"]);
+        assert_eq!(braces("{a,b{c"), ["{a,b{c"]);
+    }
+
+    #[test]
+    fn uses_defaults_for_unset_and_empty_values() {
+        assert_eq!(expand("set:-d").unwrap(), "value");
+        assert_eq!(expand("empty:-d").unwrap(), "d");
+        assert_eq!(expand("unset:-d").unwrap(), "d");
+        assert_eq!(expand("set-d").unwrap(), "value");
+        assert_eq!(expand("empty-d").unwrap(), "");
+        assert_eq!(expand("unset-d").unwrap(), "d");
+    }
+
+    #[test]
+    fn assigns_defaults_to_unset_and_empty_variables() {
+        let mut vars = vars();
+        assert_eq!(parameter("set:=d", &mut vars).unwrap(), "value");
+        assert_eq!(parameter("empty=d", &mut vars).unwrap(), "");
+        assert_eq!(vars.lookup("empty").unwrap(), "");
+        assert_eq!(parameter("empty:=d", &mut vars).unwrap(), "d");
+        assert_eq!(parameter("unset=u", &mut vars).unwrap(), "u");
+        assert_eq!(vars.lookup("set").unwrap(), "value");
+        assert_eq!(vars.lookup("empty").unwrap(), "d");
+        assert_eq!(vars.lookup("unset").unwrap(), "u");
+        assert_eq!(expand("1:=d").unwrap_err(), "$1: cannot assign in this way");
+    }
+
+    #[test]
+    fn fails_for_unset_and_empty_values_with_a_question_mark() {
+        assert_eq!(expand("set:?oops").unwrap(), "value");
+        assert_eq!(expand("empty:?").unwrap_err(), "empty: parameter null or not set");
+        assert_eq!(expand("unset:?oops").unwrap_err(), "unset: oops");
+        assert_eq!(expand("empty?oops").unwrap(), "");
+        assert_eq!(expand("unset?").unwrap_err(), "unset: parameter null or not set");
+    }
+
+    #[test]
+    fn uses_alternatives_for_set_values() {
+        assert_eq!(expand("set:+a").unwrap(), "a");
+        assert_eq!(expand("empty:+a").unwrap(), "");
+        assert_eq!(expand("unset:+a").unwrap(), "");
+        assert_eq!(expand("empty+a").unwrap(), "a");
+        assert_eq!(expand("unset+a").unwrap(), "");
+    }
+
+    #[test]
+    fn counts_characters() {
+        assert_eq!(expand("#set").unwrap(), "5");
+        assert_eq!(expand("#wide").unwrap(), "5");
+        assert_eq!(expand("#empty").unwrap(), "0");
+        assert_eq!(expand("#unset").unwrap(), "0");
+        assert_eq!(expand("#set:-x").unwrap_err(), "${#set:-x}: bad substitution");
+    }
+
+    #[test]
+    fn removes_prefixes_and_suffixes() {
+        assert_eq!(expand("path#*/").unwrap(), "usr/lib/file.tar.gz");
+        assert_eq!(expand("path##*/").unwrap(), "file.tar.gz");
+        assert_eq!(expand("path%.*").unwrap(), "/usr/lib/file.tar");
+        assert_eq!(expand("path%%.*").unwrap(), "/usr/lib/file");
+        assert_eq!(expand("path#lib").unwrap(), "/usr/lib/file.tar.gz");
+        assert_eq!(expand("wide%l?").unwrap(), "hél");
+        for op in ["#", "##", "%", "%%"] {
+            assert_eq!(expand(&format!("empty{}*", op)).unwrap(), "");
+            assert_eq!(expand(&format!("unset{}*", op)).unwrap(), "");
+        }
+    }
+
+    #[test]
+    fn replaces_matches() {
+        assert_eq!(expand("path/l/L").unwrap(), "/usr/Lib/file.tar.gz");
+        assert_eq!(expand("path//l/L").unwrap(), "/usr/Lib/fiLe.tar.gz");
+        assert_eq!(expand("path//\\//:").unwrap(), ":usr:lib:file.tar.gz");
+        assert_eq!(expand("path//.*").unwrap(), "/usr/lib/file");
+        assert_eq!(expand("path/#\\/usr/~").unwrap(), "~/lib/file.tar.gz");
+        assert_eq!(expand("path/%gz/bz2").unwrap(), "/usr/lib/file.tar.bz2");
+        assert_eq!(expand("path/#lib/x").unwrap(), "/usr/lib/file.tar.gz");
+        assert_eq!(expand("path/x/y").unwrap(), "/usr/lib/file.tar.gz");
+        for op in ["/", "//"] {
+            assert_eq!(expand(&format!("empty{}*/x", op)).unwrap(), "");
+            assert_eq!(expand(&format!("unset{}*/x", op)).unwrap(), "");
+        }
+    }
+
+    fn stripped(output: &str) -> String {
+        let mut output = output.to_string();
+        strip_trailing_newlines(&mut output);
+        output
+    }
+
+    #[test]
+    fn strips_only_trailing_newlines() {
+        assert_eq!(stripped("no newline"), "no newline");
+        assert_eq!(stripped("one\n"), "one");
+        assert_eq!(stripped("several\n\n\n"), "several");
+        assert_eq!(stripped("a\n\nb \n"), "a\n\nb ");
+        assert_eq!(stripped("  spaced  out\t\n"), "  spaced  out\t");
+        assert_eq!(stripped("crlf\r\n"), "crlf\r");
+        assert_eq!(stripped("\n\n\n"), "");
+        assert_eq!(stripped(""), "");
+    }
+}
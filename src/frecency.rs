@@ -0,0 +1,137 @@
+//! The database `j` jumps with: the directories `cd` went to, ranked by
+//! how often and how recently, kept in a file between sessions. Each line
+//! of the file is `path|rank|time`, the time in seconds since the epoch.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Once the ranks add up to more than this, they are all scaled down and
+/// the directories that fall below 1 are forgotten.
+const MAX_TOTAL_RANK: f64 = 9000.0;
+
+struct Entry {
+    path: String,
+    /// Roughly the number of visits, decayed as the database fills up
+    rank: f64,
+    /// When it was last visited
+    time: u64,
+}
+
+impl Entry {
+    /// The rank weighted by how long ago the directory was visited.
+    fn score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.time);
+        match age {
+            _ if age < 60 * 60 => self.rank * 4.0,
+            _ if age < 24 * 60 * 60 => self.rank * 2.0,
+            _ if age < 7 * 24 * 60 * 60 => self.rank / 2.0,
+            _ => self.rank / 4.0,
+        }
+    }
+}
+
+pub struct Database {
+    file: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Database {
+    /// Reads the database in `file`. A file that doesn't exist yet is an
+    /// empty database, and lines that can't be read are dropped.
+    pub fn open(file: PathBuf) -> Database {
+        let text = fs::read_to_string(&file).unwrap_or_default();
+        let entries = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.rsplitn(3, '|');
+                let time = fields.next()?.parse().ok()?;
+                let rank = fields.next()?.parse().ok()?;
+                let path = fields.next()?.to_string();
+                Some(Entry { path, rank, time })
+            })
+            .collect();
+        Database { file, entries }
+    }
+
+    /// Records a visit to `dir`.
+    pub fn visit(&mut self, dir: &str) {
+        let now = now();
+        match self.entries.iter_mut().find(|entry| entry.path == dir) {
+            Some(entry) => {
+                entry.rank += 1.0;
+                entry.time = now;
+            }
+            None => self.entries.push(Entry { path: dir.to_string(), rank: 1.0, time: now }),
+        }
+        if self.entries.iter().map(|entry| entry.rank).sum::<f64>() > MAX_TOTAL_RANK {
+            for entry in &mut self.entries {
+                entry.rank *= 0.99;
+            }
+            self.entries.retain(|entry| entry.rank >= 1.0);
+        }
+    }
+
+    /// Drops `dir`, for a directory that no longer exists.
+    pub fn forget(&mut self, dir: &str) {
+        self.entries.retain(|entry| entry.path != dir);
+    }
+
+    /// The directories whose paths contain all of `patterns` in order,
+    /// with their scores, best first. Patterns are matched ignoring case
+    /// only if nothing matches them exactly.
+    pub fn matches(&self, patterns: &[String]) -> Vec<(&str, f64)> {
+        let now = now();
+        let find = |fold: bool| {
+            let mut found: Vec<_> = self
+                .entries
+                .iter()
+                .filter(|entry| contains_in_order(&entry.path, patterns, fold))
+                .map(|entry| (entry.path.as_str(), entry.score(now)))
+                .collect();
+            found.sort_by(|a, b| b.1.total_cmp(&a.1));
+            found
+        };
+        let found = find(false);
+        if found.is_empty() { find(true) } else { found }
+    }
+
+    /// Writes the database back, replacing the file as a whole so a
+    /// shell exiting halfway through doesn't leave half of it.
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = self.file.with_extension(format!("tmp{}", std::process::id()));
+        let mut out = io::BufWriter::new(fs::File::create(&temp)?);
+        for entry in &self.entries {
+            writeln!(out, "{}|{}|{}", entry.path, entry.rank, entry.time)?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?;
+        fs::rename(&temp, &self.file)
+    }
+}
+
+fn contains_in_order(path: &str, patterns: &[String], fold: bool) -> bool {
+    let path = if fold { path.to_lowercase() } else { path.to_string() };
+    let mut rest = path.as_str();
+    for pattern in patterns {
+        let pattern = if fold { pattern.to_lowercase() } else { pattern.clone() };
+        match rest.find(&pattern) {
+            Some(start) => rest = &rest[start + pattern.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+/// Whether visits to `dir` are worth recording: not the home directory,
+/// which is a bare `cd` away, and nothing a line of the file can't hold.
+pub fn tracked(dir: &Path, home: Option<&str>) -> bool {
+    home.is_none_or(|home| dir != Path::new(home)) && !dir.to_string_lossy().contains('\n')
+}
@@ -0,0 +1,228 @@
+//! The state of the git repository the shell is in, for the prompt's
+//! `\g`: the branch, or the commit if HEAD is detached, whether a merge or
+//! rebase is under way, whether files have changed, and how far the
+//! branch is ahead of and behind its upstream.
+//!
+//! A prompt has to come back straight away, so as much as possible is
+//! read from the files in the git directory rather than by running git.
+//! Changed files are found by comparing the sizes and modification times
+//! in the index with the files', without reading them, and only the
+//! ahead and behind counts, which need the commit graph, run git, and
+//! only when the branch and its upstream differ. All of it has a time
+//! limit; what isn't worked out in time is left out.
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long working out the state may take in all
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How many index entries are checked between looks at the clock
+const CHECK_EVERY: usize = 256;
+
+/// A repository: its working tree, the git directory, and the directory
+/// with the refs and config, which differs from the git directory in a
+/// linked worktree.
+struct Repository {
+    work_tree: PathBuf,
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+}
+
+/// The state of the repository `dir` is in, as in `main*|MERGING ↑1↓2`,
+/// or None if it isn't in one.
+pub fn status(dir: &Path) -> Option<String> {
+    let deadline = Instant::now() + TIMEOUT;
+    let repo = Repository::find(dir)?;
+    let head = fs::read_to_string(repo.git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let branch = head.strip_prefix("ref: refs/heads/");
+    let mut status = match (branch, head.strip_prefix("ref: ")) {
+        (Some(branch), _) => branch.to_string(),
+        (None, Some(reference)) => reference.to_string(),
+        (None, None) => head.get(..7)?.to_string(),
+    };
+    if repo.changed(deadline) == Some(true) {
+        status.push('*');
+    }
+    if let Some(operation) = repo.operation() {
+        status.push('|');
+        status.push_str(operation);
+    }
+    if let Some((ahead, behind)) = branch.and_then(|branch| repo.divergence(branch, deadline)) {
+        if ahead > 0 || behind > 0 {
+            status.push(' ');
+        }
+        if ahead > 0 {
+            status.push_str(&format!("↑{}", ahead));
+        }
+        if behind > 0 {
+            status.push_str(&format!("↓{}", behind));
+        }
+    }
+    Some(status)
+}
+
+impl Repository {
+    /// The repository `dir` or one of the directories above it holds.
+    fn find(dir: &Path) -> Option<Repository> {
+        for work_tree in dir.ancestors() {
+            let dot_git = work_tree.join(".git");
+            let git_dir = match fs::metadata(&dot_git) {
+                Ok(meta) if meta.is_dir() => dot_git,
+                // A worktree or submodule's `.git` is a file naming the
+                // real git directory
+                Ok(_) => {
+                    let text = fs::read_to_string(&dot_git).ok()?;
+                    work_tree.join(text.trim().strip_prefix("gitdir: ")?)
+                }
+                Err(_) => continue,
+            };
+            let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+                Ok(common) => git_dir.join(common.trim()),
+                Err(_) => git_dir.clone(),
+            };
+            return Some(Repository { work_tree: work_tree.to_path_buf(), git_dir, common_dir });
+        }
+        None
+    }
+
+    /// The merge, rebase or the like that has stopped part way, if one
+    /// has.
+    fn operation(&self) -> Option<&'static str> {
+        let exists = |name: &str| self.git_dir.join(name).exists();
+        if exists("rebase-merge") || exists("rebase-apply") {
+            Some("REBASE")
+        } else if exists("MERGE_HEAD") {
+            Some("MERGING")
+        } else if exists("CHERRY_PICK_HEAD") {
+            Some("CHERRY-PICKING")
+        } else if exists("REVERT_HEAD") {
+            Some("REVERTING")
+        } else if exists("BISECT_LOG") {
+            Some("BISECTING")
+        } else {
+            None
+        }
+    }
+
+    /// Whether any file in the index has changed in the working tree,
+    /// judging by its size and modification time, or None if that can't
+    /// be told in time or from this index's format.
+    fn changed(&self, deadline: Instant) -> Option<bool> {
+        let index = fs::read(self.git_dir.join("index")).ok()?;
+        if index.get(..4)? != b"DIRC" {
+            return None;
+        }
+        let version = u32::from_be_bytes(index.get(4..8)?.try_into().ok()?);
+        // Version 4 compresses paths against the entry before
+        if !(2..=3).contains(&version) {
+            return None;
+        }
+        let count = u32::from_be_bytes(index.get(8..12)?.try_into().ok()?) as usize;
+        let word = |at: usize| index.get(at..at + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()));
+        let half = |at: usize| index.get(at..at + 2).map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()));
+        let mut at = 12;
+        for i in 0..count {
+            if i % CHECK_EVERY == 0 && Instant::now() > deadline {
+                return None;
+            }
+            let (mtime, mode, size) = (word(at + 8)?, word(at + 24)?, word(at + 36)?);
+            let extended = half(at + 60)? & 0x4000 != 0;
+            // Version 3 entries can have more flags, among them one for
+            // files git has been told to leave alone in the working tree
+            let skip_worktree = extended && half(at + 62)? & 0x4000 != 0;
+            let path_start = at + if extended { 64 } else { 62 };
+            let path_len = index.get(path_start..)?.iter().position(|&b| b == 0)?;
+            let path = std::str::from_utf8(&index[path_start..path_start + path_len]).ok()?;
+            // Entries are padded with NULs to a multiple of 8 bytes
+            at += (path_start - at + path_len + 8) & !7;
+            // Submodules are left to themselves
+            if mode & 0o170000 == 0o160000 || skip_worktree {
+                continue;
+            }
+            let Ok(meta) = fs::symlink_metadata(self.work_tree.join(path)) else {
+                return Some(true);
+            };
+            if meta.mtime() as u32 != mtime || meta.size() as u32 != size {
+                return Some(true);
+            }
+        }
+        Some(false)
+    }
+
+    /// How many commits `branch` is ahead of and behind its upstream, if
+    /// it has one and git can tell in time.
+    fn divergence(&self, branch: &str, deadline: Instant) -> Option<(usize, usize)> {
+        let upstream = self.upstream(branch)?;
+        let local = format!("refs/heads/{}", branch);
+        if self.resolve(&local)? == self.resolve(&upstream)? {
+            return Some((0, 0));
+        }
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(&self.work_tree)
+            .args(["rev-list", "--count", "--left-right", &format!("{}...{}", local, upstream)])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) if status.success() => break,
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+                Ok(None) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return None;
+                }
+                _ => return None,
+            }
+        }
+        let output = child.wait_with_output().ok()?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let (ahead, behind) = output.trim().split_once('\t')?;
+        Some((ahead.parse().ok()?, behind.parse().ok()?))
+    }
+
+    /// The ref `branch` is set to track in the config.
+    fn upstream(&self, branch: &str) -> Option<String> {
+        let config = fs::read_to_string(self.common_dir.join("config")).ok()?;
+        let section = format!("[branch \"{}\"]", branch);
+        let (mut remote, mut merge) = (None, None);
+        let mut in_section = false;
+        for line in config.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_section = line == section;
+            } else if let (true, Some((key, value))) = (in_section, line.split_once('=')) {
+                match key.trim() {
+                    "remote" => remote = Some(value.trim().to_string()),
+                    "merge" => merge = Some(value.trim().to_string()),
+                    _ => {}
+                }
+            }
+        }
+        let merge = merge?;
+        match remote?.as_str() {
+            "." => Some(merge),
+            remote => Some(format!("refs/remotes/{}/{}", remote, merge.strip_prefix("refs/heads/")?)),
+        }
+    }
+
+    /// The commit `reference` names, from its file or from packed-refs.
+    fn resolve(&self, reference: &str) -> Option<String> {
+        if let Ok(text) = fs::read_to_string(self.common_dir.join(reference)) {
+            return Some(text.trim().to_string());
+        }
+        let packed = fs::read_to_string(self.common_dir.join("packed-refs")).ok()?;
+        packed.lines().find_map(|line| {
+            let (hash, name) = line.split_once(' ')?;
+            (name == reference).then(|| hash.to_string())
+        })
+    }
+}
@@ -0,0 +1,219 @@
+//! Filename generation: expanding `*`, `?` and `[...]` patterns against
+//! the filesystem.
+
+use std::fs;
+use std::path::Path;
+
+use crate::expand;
+
+/// Whether a path component contains an unescaped pattern character.
+fn is_pattern(component: &str) -> bool {
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Removes the backslashes that quote characters in a component.
+fn unescape(component: &str) -> String {
+    let mut result = String::new();
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// The entries of `dir` whose names match `pattern`. Names starting with
+/// `.` only match a pattern that starts with one too. Names that aren't
+/// UTF-8 are skipped: words are strings, so such a name couldn't be
+/// passed on as the file's name, only as some other one.
+fn matching_entries(dir: &str, pattern: &str) -> Vec<String> {
+    let path = if dir.is_empty() { "." } else { dir };
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.') || pattern.starts_with('.'))
+        .filter(|name| expand::matches(pattern, name))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Every path below `dir`, or only the directories with `dirs_only`,
+/// which are given a trailing slash. Symlinks aren't followed.
+fn walk(dir: &str, dirs_only: bool) -> Vec<String> {
+    let mut paths = Vec::new();
+    for name in matching_entries(dir, "*") {
+        let path = format!("{}{}", dir, name);
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+        if is_dir {
+            let below = format!("{}/", path);
+            paths.push(if dirs_only { below.clone() } else { path });
+            paths.extend(walk(&below, dirs_only));
+        } else if !dirs_only {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// Expands `pattern` to the paths that match it, sorted. Characters
+/// quoted with a backslash match literally. With `globstar`, a `**`
+/// component matches any number of directories. Returns nothing if no
+/// path matches.
+pub fn expand(pattern: &str, globstar: bool) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let mut next = Vec::new();
+        for dir in &paths {
+            if component.is_empty() {
+                // A doubled or trailing slash
+                next.push(dir.clone());
+            } else if globstar && *component == "**" && last {
+                next.extend(walk(dir, false));
+            } else if globstar && *component == "**" {
+                // Any number of directories, including none
+                next.push(dir.clone());
+                next.extend(walk(dir, true));
+            } else if is_pattern(component) {
+                for name in matching_entries(dir, component) {
+                    next.push(format!("{}{}", dir, name));
+                }
+            } else {
+                next.push(format!("{}{}", dir, unescape(component)));
+            }
+        }
+        if !last {
+            next = next
+                .into_iter()
+                .map(|path| if path.is_empty() || path.ends_with('/') { path } else { path + "/" })
+                .collect();
+        }
+        paths = next;
+    }
+
+    let mut paths: Vec<String> = paths
+        .into_iter()
+        .filter(|path| !path.is_empty() && Path::new(path).symlink_metadata().is_ok())
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// A directory of its own for `test` to make files in, empty to start
+    /// with, as a path ending in `/`.
+    fn scratch(test: &str) -> String {
+        let dir = env::temp_dir().join(format!("shellob-glob-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        format!("{}/", dir.display())
+    }
+
+    fn touch(dir: &str, paths: &[&str]) {
+        for path in paths {
+            let path = format!("{}{}", dir, path);
+            match path.strip_suffix('/') {
+                Some(path) => fs::create_dir_all(path).unwrap(),
+                None => drop(fs::File::create(path).unwrap()),
+            }
+        }
+    }
+
+    /// Expands `pattern` in `dir`, giving the paths relative to it.
+    fn glob(dir: &str, pattern: &str, globstar: bool) -> Vec<String> {
+        expand(&format!("{}{}", dir, pattern), globstar)
+            .into_iter()
+            .map(|path| path.strip_prefix(dir).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn tells_patterns_from_literal_components() {
+        assert!(is_pattern("*.rs"));
+        assert!(is_pattern("a?c"));
+        assert!(is_pattern("[ab]"));
+        assert!(!is_pattern("plain"));
+        assert!(!is_pattern("\\*.rs"));
+        assert!(is_pattern("\\**"));
+        assert_eq!(unescape("\\*.\\rs\\"), "*.rs");
+    }
+
+    #[test]
+    fn matches_names_in_sorted_order() {
+        let dir = scratch("sorted");
+        touch(&dir, &["b.rs", "a.rs", "c.txt", "B.rs", "ab.rs", "sub/", "sub/x.rs"]);
+        assert_eq!(glob(&dir, "*.rs", false), ["B.rs", "a.rs", "ab.rs", "b.rs"]);
+        assert_eq!(glob(&dir, "?.rs", false), ["B.rs", "a.rs", "b.rs"]);
+        assert_eq!(glob(&dir, "*/*.rs", false), ["sub/x.rs"]);
+        assert_eq!(glob(&dir, "*/", false), ["sub/"]);
+        assert!(glob(&dir, "*.md", false).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn matches_bracket_classes() {
+        let dir = scratch("brackets");
+        touch(&dir, &["a1", "a2", "a3", "b1", "a]", "a-", "a!"]);
+        assert_eq!(glob(&dir, "a[12]", false), ["a1", "a2"]);
+        assert_eq!(glob(&dir, "a[1-3]", false), ["a1", "a2", "a3"]);
+        assert_eq!(glob(&dir, "a[!1-2]", false), ["a!", "a-", "a3", "a]"]);
+        assert_eq!(glob(&dir, "a[^0-9]", false), ["a!", "a-", "a]"]);
+        assert_eq!(glob(&dir, "a[]]", false), ["a]"]);
+        assert_eq!(glob(&dir, "a[-]", false), ["a-"]);
+        assert_eq!(glob(&dir, "[ab]1", false), ["a1", "b1"]);
+        // An unclosed `[` matches itself
+        assert!(glob(&dir, "a[1", false).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn leaves_out_hidden_files_unless_the_pattern_starts_with_a_dot() {
+        let dir = scratch("hidden");
+        touch(&dir, &[".hidden", ".dir/", ".dir/inner", "shown", "sub/", "sub/.deep"]);
+        assert_eq!(glob(&dir, "*", false), ["shown", "sub"]);
+        assert_eq!(glob(&dir, "?hidden", false), Vec::<String>::new());
+        assert_eq!(glob(&dir, "[.]hidden", false), Vec::<String>::new());
+        assert_eq!(glob(&dir, ".*", false), [".dir", ".hidden"]);
+        assert_eq!(glob(&dir, ".*/*", false), [".dir/inner"]);
+        assert_eq!(glob(&dir, "*/*", false), Vec::<String>::new());
+        assert_eq!(glob(&dir, "**", true), ["shown", "sub"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn matches_any_depth_with_globstar() {
+        let dir = scratch("globstar");
+        touch(&dir, &["top.rs", "a/", "a/mid.rs", "a/b/", "a/b/deep.rs", "a/b/note.txt"]);
+        assert_eq!(glob(&dir, "**/*.rs", true), ["a/b/deep.rs", "a/mid.rs", "top.rs"]);
+        // Including the directory the `**` starts in
+        assert_eq!(glob(&dir, "**/", true), ["", "a/", "a/b/"]);
+        assert_eq!(glob(&dir, "**", true), ["a", "a/b", "a/b/deep.rs", "a/b/note.txt", "a/mid.rs", "top.rs"]);
+        // Without globstar `**` is just `*`
+        assert_eq!(glob(&dir, "**/*.rs", false), ["a/mid.rs"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! Colouring of the line being typed, with `set -o highlight`: commands
+//! green if they can be run and red if not, quoted text yellow and
+//! operators cyan. The line is split with the lexer each time it's drawn,
+//! so the colours follow it as it's edited.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::lexer::{self, Token};
+use crate::Shell;
+
+/// The SGR parameters for each kind of text
+const COMMAND: &str = "32";
+const UNKNOWN: &str = "31";
+const QUOTED: &str = "33";
+const OPERATOR: &str = "36";
+
+/// The colour of each character of `line`, as SGR parameters.
+pub fn colors(line: &[char], shell: &Shell) -> Vec<Option<&'static str>> {
+    let text: String = line.iter().collect();
+    let mut colors = vec![None; line.len()];
+    let mut pos = 0;
+    let mut command_position = true;
+    let mut redirect_target = false;
+    for token in lexer::lex(&text) {
+        while pos < line.len() && line[pos].is_whitespace() {
+            pos += 1;
+        }
+        let (Token::Word(word) | Token::Operator(word)) = &token;
+        let len = word.chars().count().min(line.len() - pos);
+        let span = pos..pos + len;
+        pos += len;
+        match token {
+            Token::Operator(operator) => {
+                colors[span].fill(Some(OPERATOR));
+                redirect_target = operator.contains(['<', '>']);
+                if !redirect_target {
+                    command_position = operator != ")";
+                }
+            }
+            Token::Word(word) => {
+                color_quotes(&line[span.clone()], &mut colors[span.clone()]);
+                if std::mem::take(&mut redirect_target) {
+                    continue;
+                }
+                if command_position && !is_assignment(&word) {
+                    let color = if runnable(&unquote(&word), shell) { COMMAND } else { UNKNOWN };
+                    for c in &mut colors[span] {
+                        c.get_or_insert(color);
+                    }
+                    command_position = false;
+                }
+            }
+        }
+    }
+    colors
+}
+
+/// Colours the quoted parts of a word.
+fn color_quotes(word: &[char], colors: &mut [Option<&'static str>]) {
+    let mut quote = None;
+    let mut escaped = false;
+    for (c, color) in word.iter().zip(colors) {
+        if quote.is_some() || matches!(c, '\'' | '"') && !escaped {
+            *color = Some(QUOTED);
+        }
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (None | Some('"'), '\\') => escaped = true,
+            (None, '\'' | '"') => quote = Some(*c),
+            (Some(q), c) if *c == q => quote = None,
+            _ => {}
+        }
+    }
+}
+
+/// Whether `word` sets a variable for the command after it.
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| Shell::is_name(name))
+}
+
+fn unquote(word: &str) -> String {
+    word.chars().filter(|c| !matches!(c, '\'' | '"' | '\\')).collect()
+}
+
+/// Whether `command` is something the shell could run: a builtin, a
+/// program on PATH or at the path given, or with `autocd` a directory.
+fn runnable(command: &str, shell: &Shell) -> bool {
+    let executable = |path: &Path| fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0);
+    if shell.options.autocd && shell.cwd.join(command).is_dir() {
+        return true;
+    }
+    if command.contains('/') {
+        return executable(&shell.cwd.join(command));
+    }
+    shell.commands.contains_key(command)
+        || shell
+            .parameter("PATH")
+            .unwrap_or_default()
+            .split(':')
+            .any(|dir| executable(&Path::new(if dir.is_empty() { "." } else { dir }).join(command)))
+}
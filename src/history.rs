@@ -0,0 +1,477 @@
+//! The command lines typed at the prompt, which the line editor can
+//! recall, and the file they are kept in between sessions.
+//!
+//! Each command is appended to the file as soon as it is run, with the
+//! file locked, so shells running at the same time don't lose each
+//! other's lines. A command that spans several lines is kept on one line
+//! of the file, with its newlines and backslashes escaped, after a
+//! `#time` line giving when it was run in seconds since the epoch.
+//!
+//! With `set -o sharehistory`, a shell also reads back the lines other
+//! shells have appended since it last looked, before each prompt and
+//! before each line it adds, so shells running side by side see each
+//! other's commands as they go.
+//!
+//! Lines typed at the prompt go through history expansion before they
+//! are run, so `!!` and the like refer to earlier lines.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::ShellError;
+use crate::lexer;
+use crate::sys;
+
+#[derive(Debug, Default, Clone)]
+pub struct History {
+    /// Oldest first
+    entries: Vec<String>,
+    /// When each entry was run, in seconds since the epoch
+    times: Vec<u64>,
+    /// Where entries are saved, if anywhere
+    file: Option<PathBuf>,
+    /// How far into the file this shell has read or written, in bytes
+    read_to: u64,
+    /// Whether lines other shells append are read back
+    shared: bool,
+}
+
+/// Which lines `add` leaves out, as set by the words of HISTCONTROL.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Control {
+    /// Lines starting with a space, a way to keep a command out of the
+    /// file: `ignorespace`
+    pub ignore_space: bool,
+    /// A line the same as the one before it: `ignoredups`
+    pub ignore_dups: bool,
+    /// Earlier copies of the line, wherever they are: `erasedups`
+    pub erase_dups: bool,
+}
+
+impl Control {
+    /// Reads a colon-separated list of the words above, where `ignoreboth`
+    /// is `ignorespace` and `ignoredups`. Other words are ignored.
+    pub fn parse(value: &str) -> Control {
+        let mut control = Control::default();
+        for word in value.split(':') {
+            match word {
+                "ignorespace" => control.ignore_space = true,
+                "ignoredups" => control.ignore_dups = true,
+                "ignoreboth" => (control.ignore_space, control.ignore_dups) = (true, true),
+                "erasedups" => control.erase_dups = true,
+                _ => {}
+            }
+        }
+        control
+    }
+}
+
+impl History {
+    /// Starts keeping history in `file`, beginning with the last `size`
+    /// entries already there. Entries from before times were kept are
+    /// taken to be from now.
+    pub fn open(file: PathBuf, size: usize) -> History {
+        let (text, read_to) = read_from(&file, 0).unwrap_or_default();
+        let mut history = History { file: Some(file), read_to, ..History::default() };
+        history.merge(&text);
+        history.trim(size);
+        history
+    }
+
+    /// Sets whether lines other shells append to the file are read back.
+    pub fn set_shared(&mut self, shared: bool) {
+        self.shared = shared;
+    }
+
+    /// Reads back the lines other shells have appended to the file since
+    /// this one last looked, keeping at most `size` entries.
+    pub fn reload(&mut self, size: usize) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if let Ok((text, read_to)) = read_from(file, self.read_to) {
+            self.read_to = read_to;
+            self.merge(&text);
+            self.trim(size);
+        }
+    }
+
+    /// The lines other shells have appended to the file since this one
+    /// last looked, for the line editor to offer without reloading.
+    pub fn unread(&self) -> Vec<String> {
+        let Some(file) = self.file.as_ref().filter(|_| self.shared) else {
+            return Vec::new();
+        };
+        let (text, _) = read_from(file, self.read_to).unwrap_or_default();
+        records(&text).into_iter().map(|(_, line)| decode(line)).collect()
+    }
+
+    /// Adds a command line that was run, keeping at most `size` entries.
+    /// Blank lines aren't kept, nor the lines `control` leaves out. Copies
+    /// `erase_dups` removes are only removed from memory; the file only
+    /// ever has lines appended.
+    pub fn add(&mut self, line: &str, size: usize, control: Control) {
+        if line.trim().is_empty()
+            || (control.ignore_space && line.starts_with(' '))
+            || (control.ignore_dups && self.entries.last().is_some_and(|last| last == line))
+        {
+            return;
+        }
+        if control.erase_dups {
+            while let Some(index) = self.entries.iter().position(|entry| entry == line) {
+                self.entries.remove(index);
+                self.times.remove(index);
+            }
+        }
+        let time = now();
+        // Not being able to save history isn't worth interrupting the
+        // user over
+        let _ = self.append(line, time);
+        self.entries.push(line.to_string());
+        self.times.push(time);
+        self.trim(size);
+    }
+
+    // Appends `line` to the file. If history is shared, what other shells
+    // have appended is read back first, under the same lock, so this
+    // shell's copy has the lines in the same order as the file.
+    fn append(&mut self, line: &str, time: u64) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        sys::flock(file.as_raw_fd(), sys::LOCK_EX)?;
+        if self.shared {
+            let mut text = String::new();
+            if file.seek(SeekFrom::End(0))? >= self.read_to {
+                file.seek(SeekFrom::Start(self.read_to))?;
+                file.read_to_string(&mut text)?;
+            }
+            self.merge(&text);
+        }
+        file.write_all(format!("#{}\n{}\n", time, encode(line)).as_bytes())?;
+        self.read_to = file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    // Adds the entries in `text`, a part of the file.
+    fn merge(&mut self, text: &str) {
+        let now = now();
+        for (time, line) in records(text) {
+            self.entries.push(decode(line));
+            self.times.push(time.unwrap_or(now));
+        }
+    }
+
+    // Drops the oldest entries, keeping at most `size`.
+    fn trim(&mut self, size: usize) {
+        let excess = self.entries.len().saturating_sub(size);
+        self.entries.drain(..excess);
+        self.times.drain(..excess);
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// When each of `entries` was run, in seconds since the epoch.
+    pub fn times(&self) -> &[u64] {
+        &self.times
+    }
+
+    /// A copy of the entries for a subshell, which can list them but
+    /// doesn't save what it runs.
+    pub fn detached(&self) -> History {
+        History { entries: self.entries.clone(), times: self.times.clone(), ..History::default() }
+    }
+
+    /// Forgets every entry. The file is left as it is.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.times.clear();
+    }
+
+    /// Removes entry `number`, counting from 1 for the oldest, or from -1
+    /// for the newest if negative. `None` if there is no such entry.
+    pub fn delete(&mut self, number: isize) -> Option<String> {
+        let index = match number {
+            n if n < 0 => self.entries.len().checked_sub(n.unsigned_abs())?,
+            n => (n as usize).checked_sub(1)?,
+        };
+        if index >= self.entries.len() {
+            return None;
+        }
+        self.times.remove(index);
+        Some(self.entries.remove(index))
+    }
+
+    /// Expands the history references in `line`: `!!`, `!n`, `!-n`,
+    /// `!prefix` and `!?string?` for earlier lines, each optionally
+    /// followed by `:` and the words to take (`n`, `^`, `$`, `*` or
+    /// `n-m`), and `!$`, `!^` and `!*` for words of the previous line.
+    /// Returns None if there are none. A `!` in single quotes, after a
+    /// backslash, or before a blank, `=`, `(` or `"` is left alone.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, ShellError> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut expanded = String::new();
+        let (mut single, mut double) = (false, false);
+        let mut found = false;
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if !single => {
+                    expanded.extend(&chars[i..(i + 2).min(chars.len())]);
+                    i += 1;
+                }
+                '\'' if !double => {
+                    single = !single;
+                    expanded.push('\'');
+                }
+                '"' if !single => {
+                    double = !double;
+                    expanded.push('"');
+                }
+                '!' if !single && chars.get(i + 1).is_some_and(|&c| !c.is_whitespace() && !"=(\"".contains(c)) => {
+                    let (text, len) = self.reference(&chars[i + 1..])?;
+                    expanded.push_str(&text);
+                    found = true;
+                    i += len;
+                }
+                c => expanded.push(c),
+            }
+            i += 1;
+        }
+        Ok(found.then_some(expanded))
+    }
+
+    // Expands the reference that `rest` follows the `!` of, returning
+    // the text and how many characters of `rest` it took up.
+    fn reference(&self, rest: &[char]) -> Result<(String, usize), ShellError> {
+        let ends_prefix = |c: &char| c.is_whitespace() || ";&|()<>:'\"".contains(*c);
+        let (event, mut len) = match rest[0] {
+            '!' => (self.entries.last(), 1),
+            // `!$`, `!^` and `!*` take words of the previous line
+            '$' | '^' | '*' => (self.entries.last(), 0),
+            '?' => {
+                let end = rest[1..].iter().position(|&c| c == '?').map_or(rest.len(), |end| end + 1);
+                let string: String = rest[1..end].iter().collect();
+                let event = self.entries.iter().rev().find(|entry| entry.contains(&string));
+                (event, (end + 1).min(rest.len()))
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let len = 1 + rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let number: String = rest[..len].iter().collect();
+                let index = match number.parse::<isize>() {
+                    Ok(n) if n < 0 => self.entries.len().checked_sub(n.unsigned_abs()),
+                    Ok(n) => (n as usize).checked_sub(1),
+                    Err(_) => None,
+                };
+                (index.and_then(|index| self.entries.get(index)), len)
+            }
+            _ => {
+                let len = rest.iter().position(ends_prefix).unwrap_or(rest.len());
+                let prefix: String = rest[..len].iter().collect();
+                (self.entries.iter().rev().find(|entry| entry.starts_with(&prefix)), len)
+            }
+        };
+        let reference = |len: usize| format!("!{}", rest[..len].iter().collect::<String>());
+        let event = event.ok_or_else(|| ShellError::History { reference: reference(len), problem: "event not found" })?;
+
+        // The words to take, after a `:` or in place of the event
+        let designator_start = match (len, rest.get(len)) {
+            (0, _) => 0,
+            (_, Some(':')) if rest.get(len + 1).is_some_and(|c| c.is_ascii_digit() || "^$*".contains(*c)) => len + 1,
+            _ => return Ok((event.clone(), len)),
+        };
+        let designator_len = match rest[designator_start] {
+            '^' | '$' | '*' => 1,
+            _ => {
+                let digits = |from: usize| rest[from..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let first = digits(designator_start);
+                match rest.get(designator_start + first) {
+                    Some('-') => {
+                        let after = designator_start + first + 1;
+                        let last = match rest.get(after) {
+                            Some('$') => 1,
+                            _ => digits(after),
+                        };
+                        first + 1 + last
+                    }
+                    _ => first,
+                }
+            }
+        };
+        len = designator_start + designator_len;
+        let designator: String = rest[designator_start..len].iter().collect();
+        let words: Vec<String> = lexer::lex(event).iter().map(|token| token.to_string()).collect();
+        let last = words.len().saturating_sub(1);
+        let index = |n: &str| match n {
+            "$" => Some(last),
+            "^" => Some(1),
+            n => n.parse().ok(),
+        };
+        let range = match designator.as_str() {
+            "*" => Some((1, last)),
+            d => match d.split_once('-') {
+                Some((first, end)) => index(first).zip(index(end)),
+                None => index(d).map(|n| (n, n)),
+            },
+        };
+        match range {
+            // `*` of a line with no arguments is empty
+            Some((1, 0)) if designator == "*" => Ok((String::new(), len)),
+            Some((first, end)) if first <= end && end < words.len() => Ok((words[first..=end].join(" "), len)),
+            _ => Err(ShellError::History { reference: reference(len), problem: "bad word specifier" }),
+        }
+    }
+
+    /// Cuts the file down to its last `size` entries, which other shells
+    /// may have added to as well.
+    pub fn truncate_file(&self, size: usize) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        sys::flock(file.as_raw_fd(), sys::LOCK_EX)?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        let records = records(&text);
+        if records.len() <= size {
+            return Ok(());
+        }
+        let mut kept = String::new();
+        for (time, line) in &records[records.len() - size..] {
+            if let Some(time) = time {
+                kept.push_str(&format!("#{}\n", time));
+            }
+            kept.push_str(line);
+            kept.push('\n');
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(kept.as_bytes())
+    }
+}
+
+/// Reads the file from `offset` on, returning the text and the offset of
+/// its end. A file cut shorter than `offset` by another shell has nothing
+/// new in it.
+fn read_from(path: &PathBuf, offset: u64) -> io::Result<(String, u64)> {
+    let mut file = File::open(path)?;
+    sys::flock(file.as_raw_fd(), sys::LOCK_SH)?;
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut text = String::new();
+    if len >= offset {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_to_string(&mut text)?;
+    }
+    Ok((text, len))
+}
+
+/// Splits the text of a history file into its entries, still encoded,
+/// each with the time from the `#time` line before it if there is one.
+fn records(text: &str) -> Vec<(Option<u64>, &str)> {
+    let mut records = Vec::new();
+    let mut time = None;
+    for line in text.lines() {
+        match line.strip_prefix('#').and_then(|time| time.parse().ok()) {
+            Some(stamp) => time = Some(stamp),
+            None => records.push((time.take(), line)),
+        }
+    }
+    records
+}
+
+fn encode(line: &str) -> String {
+    line.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn decode(line: &str) -> String {
+    let mut decoded = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                decoded.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                decoded.push('\\');
+                chars.next();
+            }
+            (c, _) => decoded.push(c),
+        }
+    }
+    decoded
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history of three lines, the newest `cp -r src 'my dir' dest`.
+    fn history() -> History {
+        let mut history = History::default();
+        for line in ["ls -l /tmp", "echo one two", "cp -r src 'my dir' dest"] {
+            history.add(line, 10, Control::default());
+        }
+        history
+    }
+
+    fn expand(line: &str) -> Option<String> {
+        history().expand(line).unwrap()
+    }
+
+    #[test]
+    fn expands_events() {
+        assert_eq!(expand("!!"), Some("cp -r src 'my dir' dest".to_string()));
+        assert_eq!(expand("!1 | wc"), Some("ls -l /tmp | wc".to_string()));
+        assert_eq!(expand("!-2"), Some("echo one two".to_string()));
+        assert_eq!(expand("!ec"), Some("echo one two".to_string()));
+        assert_eq!(expand("!?tmp?"), Some("ls -l /tmp".to_string()));
+        assert_eq!(expand("echo hi"), None);
+    }
+
+    #[test]
+    fn expands_words_of_the_previous_line() {
+        assert_eq!(expand("echo !$"), Some("echo dest".to_string()));
+        assert_eq!(expand("echo !^"), Some("echo -r".to_string()));
+        assert_eq!(expand("echo !*"), Some("echo -r src 'my dir' dest".to_string()));
+    }
+
+    #[test]
+    fn expands_word_designators() {
+        assert_eq!(expand("!!:0"), Some("cp".to_string()));
+        assert_eq!(expand("!!:3"), Some("'my dir'".to_string()));
+        assert_eq!(expand("!!:2-3"), Some("src 'my dir'".to_string()));
+        assert_eq!(expand("!!:2-$"), Some("src 'my dir' dest".to_string()));
+        assert_eq!(expand("!ls:$"), Some("/tmp".to_string()));
+        assert_eq!(expand("!-2:^"), Some("one".to_string()));
+        assert_eq!(expand("!2:*"), Some("one two".to_string()));
+    }
+
+    #[test]
+    fn leaves_quoted_and_escaped_bangs_alone() {
+        assert_eq!(expand("echo '!!' \\!! a!= !"), None);
+        assert_eq!(expand("echo \"!$\""), Some("echo \"dest\"".to_string()));
+    }
+
+    #[test]
+    fn reports_bad_references() {
+        let history = history();
+        assert!(matches!(history.expand("!nope"), Err(ShellError::History { problem: "event not found", .. })));
+        assert!(matches!(history.expand("!!:9"), Err(ShellError::History { problem: "bad word specifier", .. })));
+        assert!(matches!(history.expand("!!:3-1"), Err(ShellError::History { problem: "bad word specifier", .. })));
+        assert!(matches!(History::default().expand("!!"), Err(ShellError::History { problem: "event not found", .. })));
+    }
+}
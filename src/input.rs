@@ -0,0 +1,74 @@
+//! Reading command lines from stdin or a script.
+
+use std::io::{self, Write};
+use std::str::Lines;
+
+use crate::editor::Editor;
+use crate::prompt;
+use crate::signals;
+use crate::sys;
+use crate::Shell;
+
+pub enum Line {
+    Text(String),
+    /// Ctrl-C was pressed while the line was being typed
+    Interrupted,
+    Eof,
+}
+
+/// Reads one line from stdin, without the trailing newline.
+///
+/// Input is read a byte at a time straight from fd 0: a buffered reader
+/// would swallow input meant for the commands we run, and std's readers
+/// retry on EINTR, which would leave Ctrl-C unable to abandon a line.
+pub fn read_line() -> io::Result<Line> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        match sys::read(0, &mut byte) {
+            Ok(0) if line.is_empty() => return Ok(Line::Eof),
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                if signals::take_interrupt() {
+                    return Ok(Line::Interrupted);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Line::Text(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Where command lines come from.
+pub enum Source<'a> {
+    /// A terminal on stdin, with lines typed in the line editor
+    Terminal(Editor),
+    /// Stdin that isn't a terminal. Prompts are only shown if the shell
+    /// was made interactive, so they don't end up in the output.
+    Stdin { interactive: bool },
+    /// A script given to `Shell::eval`
+    Script(Lines<'a>),
+}
+
+impl Source<'_> {
+    /// Reads the next line, showing `prompt` first if it comes from a
+    /// terminal, and `right_prompt` at the right of the terminal if it's
+    /// one the line editor is used on.
+    pub fn read_line(&mut self, prompt: &str, right_prompt: &str, shell: &Shell) -> io::Result<Line> {
+        match self {
+            Source::Terminal(editor) => editor.read_line(prompt, right_prompt, shell),
+            Source::Stdin { interactive: false } => read_line(),
+            Source::Stdin { interactive: true } => {
+                print!("{}", prompt::printed(prompt));
+                io::stdout().flush()?;
+                read_line()
+            }
+            Source::Script(lines) => Ok(match lines.next() {
+                Some(line) => Line::Text(line.to_string()),
+                None => Line::Eof,
+            }),
+        }
+    }
+}
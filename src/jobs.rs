@@ -0,0 +1,323 @@
+//! The table of jobs the shell is keeping track of.
+
+use std::fmt;
+use std::io;
+
+use crate::sys::{self, Termios, WaitStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    /// Finished with the given wait status; removed once reported
+    Done(WaitStatus),
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobState::Running => write!(f, "Running"),
+            JobState::Stopped => write!(f, "Stopped"),
+            JobState::Done(WaitStatus::Exited(0)) => write!(f, "Done"),
+            JobState::Done(WaitStatus::Exited(code)) => write!(f, "Exit {}", code),
+            JobState::Done(WaitStatus::Signaled(sig)) => match *sig {
+                sys::SIGHUP => write!(f, "Hangup"),
+                sys::SIGINT => write!(f, "Interrupt"),
+                sys::SIGKILL => write!(f, "Killed"),
+                sys::SIGSEGV => write!(f, "Segmentation fault"),
+                sys::SIGPIPE => write!(f, "Broken pipe"),
+                sys::SIGTERM => write!(f, "Terminated"),
+                sig => write!(f, "Signal {}", sig),
+            },
+            JobState::Done(_) => write!(f, "Done"),
+        }
+    }
+}
+
+/// One process of a job, such as a command in a pipeline.
+struct Process {
+    pid: i32,
+    state: JobState,
+}
+
+pub struct Job {
+    pub id: usize,
+    /// The job's processes in pipeline order. The first one leads the
+    /// job's process group, if it has one, and the last one's status is
+    /// the job's.
+    processes: Vec<Process>,
+    /// Whether the job has its own process group, in which case signals
+    /// go to the whole group
+    pub group: bool,
+    pub command: String,
+    pub state: JobState,
+    /// Terminal modes the job had when it was stopped, restored when it
+    /// is brought back to the foreground
+    pub tmodes: Option<Termios>,
+    /// Whether the job has changed state since it was last reported
+    changed: bool,
+}
+
+impl fmt::Display for Job {
+    /// Formats the job the way it is reported when it changes state, as
+    /// the current job.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.line('+'))
+    }
+}
+
+impl Job {
+    /// A job for freshly spawned processes. Its id is assigned when it is
+    /// added to the table.
+    pub fn new(pids: &[i32], group: bool, command: &str, state: JobState) -> Job {
+        Job {
+            id: 0,
+            processes: pids.iter().map(|&pid| Process { pid, state }).collect(),
+            group,
+            command: command.to_string(),
+            state,
+            tmodes: None,
+            changed: false,
+        }
+    }
+
+    fn line(&self, marker: char) -> String {
+        format!("[{}]{}  {:<24}{}", self.id, marker, self.state.to_string(), self.command)
+    }
+
+    /// The id of the job's process group, if it has one.
+    pub fn pgid(&self) -> i32 {
+        self.processes[0].pid
+    }
+
+    /// The pid of the job's last process, expanded by `$!`.
+    pub fn last_pid(&self) -> i32 {
+        self.processes[self.processes.len() - 1].pid
+    }
+
+    /// The exit status of each of the job's processes, 128+n for one
+    /// killed by signal n. Ones that haven't finished count as 0.
+    pub fn statuses(&self) -> Vec<i32> {
+        self.processes
+            .iter()
+            .map(|p| match p.state {
+                JobState::Done(WaitStatus::Exited(code)) => code,
+                JobState::Done(WaitStatus::Signaled(sig)) => 128 + sig,
+                _ => 0,
+            })
+            .collect()
+    }
+
+    /// The pids of the job's processes that are still running.
+    pub fn pids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.processes.iter().filter(|p| !matches!(p.state, JobState::Done(_))).map(|p| p.pid)
+    }
+
+    pub fn signal(&self, sig: i32) -> io::Result<()> {
+        if self.group {
+            return sys::kill(-self.pgid(), sig);
+        }
+        for process in self.processes.iter().filter(|p| !matches!(p.state, JobState::Done(_))) {
+            sys::kill(process.pid, sig)?;
+        }
+        Ok(())
+    }
+
+    /// Sends SIGCONT to a stopped job and marks it running again.
+    pub fn resume(&mut self) -> io::Result<()> {
+        self.signal(sys::SIGCONT)?;
+        for process in &mut self.processes {
+            if process.state == JobState::Stopped {
+                process.state = JobState::Running;
+            }
+        }
+        self.state = JobState::Running;
+        Ok(())
+    }
+
+    /// Works out the job's state from its processes': done once they all
+    /// are, with the last one's status, and stopped if any of them is.
+    fn update_state(&mut self) {
+        let last = self.processes[self.processes.len() - 1].state;
+        self.state = if self.processes.iter().all(|p| matches!(p.state, JobState::Done(_))) {
+            last
+        } else if self.processes.iter().any(|p| p.state == JobState::Stopped) {
+            JobState::Stopped
+        } else {
+            JobState::Running
+        };
+    }
+
+    /// Checks, without blocking, whether the job has changed state.
+    /// Returns whether it did.
+    pub fn poll(&mut self) -> bool {
+        let options = sys::WNOHANG | sys::WUNTRACED | sys::WCONTINUED;
+        for process in &mut self.processes {
+            if let JobState::Done(_) = process.state {
+                continue;
+            }
+            process.state = match sys::waitpid(process.pid, options) {
+                Ok(Some(WaitStatus::Stopped(_))) => JobState::Stopped,
+                Ok(Some(WaitStatus::Continued)) => JobState::Running,
+                Ok(Some(status)) => JobState::Done(status),
+                Ok(None) => continue,
+                // Already reaped elsewhere; nothing more will be heard of it
+                Err(_) => JobState::Done(WaitStatus::Exited(0)),
+            };
+        }
+        let before = self.state;
+        self.update_state();
+        self.changed |= self.state != before;
+        self.state != before
+    }
+
+    /// Waits until every process of the job has finished or one of them
+    /// stops. Returns the stopped process's status in the latter case and
+    /// the last process's otherwise.
+    pub fn wait(&mut self) -> io::Result<WaitStatus> {
+        for process in &mut self.processes {
+            if let JobState::Done(_) = process.state {
+                continue;
+            }
+            match sys::waitpid(process.pid, sys::WUNTRACED)? {
+                Some(WaitStatus::Stopped(sig)) => {
+                    process.state = JobState::Stopped;
+                    self.state = JobState::Stopped;
+                    return Ok(WaitStatus::Stopped(sig));
+                }
+                Some(WaitStatus::Continued) | None => {}
+                Some(status) => process.state = JobState::Done(status),
+            }
+        }
+        self.update_state();
+        match self.state {
+            JobState::Done(status) => Ok(status),
+            _ => Ok(WaitStatus::Continued),
+        }
+    }
+}
+
+/// The table of jobs. Jobs are kept in the order they last became
+/// current: the last one is the current job (`%+`), the one before it the
+/// previous job (`%-`).
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    /// Adds a new job as the current job, numbering it one past the
+    /// highest job id in use.
+    pub fn add(&mut self, mut job: Job) -> &Job {
+        job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.insert(job)
+    }
+
+    /// Puts a job taken out with `remove` back as the current job.
+    pub fn insert(&mut self, job: Job) -> &Job {
+        self.jobs.push(job);
+        self.jobs.last().unwrap()
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    /// Makes the job the current job.
+    pub fn make_current(&mut self, id: usize) {
+        if let Some(job) = self.remove(id) {
+            self.insert(job);
+        }
+    }
+
+    /// Resolves a job spec (`%n`, `%%`, `%+`, `%-`, `%prefix` or a bare
+    /// number) to a job id. No spec means the current job.
+    pub fn resolve(&self, spec: Option<&str>) -> Result<usize, String> {
+        let spec = spec.unwrap_or("%+");
+        let name = spec.strip_prefix('%').unwrap_or(spec);
+        let job = match name {
+            "" | "%" | "+" => self.jobs.last(),
+            "-" => self.jobs.iter().rev().nth(1),
+            _ => match name.parse::<usize>() {
+                Ok(id) => self.jobs.iter().find(|job| job.id == id),
+                Err(_) => self.jobs.iter().rev().find(|job| job.command.starts_with(name)),
+            },
+        };
+        job.map(|job| job.id)
+            .ok_or_else(|| format!("{}: no such job", spec))
+    }
+
+    fn marker(&self, id: usize) -> char {
+        let mut order = self.jobs.iter().rev();
+        if order.next().is_some_and(|job| job.id == id) {
+            '+'
+        } else if order.next().is_some_and(|job| job.id == id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    /// Formats the given jobs in id order, then drops the finished ones
+    /// since they have now been reported.
+    fn report(&mut self, ids: &[usize]) -> Vec<String> {
+        let mut jobs: Vec<&Job> = self.jobs.iter().filter(|job| ids.contains(&job.id)).collect();
+        jobs.sort_by_key(|job| job.id);
+        let lines = jobs.iter().map(|job| job.line(self.marker(job.id))).collect();
+        self.jobs.retain(|job| !matches!(job.state, JobState::Done(_)));
+        lines
+    }
+
+    /// Polls every job and returns the listing lines for all of them.
+    pub fn list(&mut self) -> Vec<String> {
+        for job in &mut self.jobs {
+            job.poll();
+        }
+        let ids: Vec<usize> = self.jobs.iter().map(|job| job.id).collect();
+        self.report(&ids)
+    }
+
+    /// Polls every job, reaping finished ones, and returns notices for
+    /// those that finished or stopped since the last check.
+    pub fn notify(&mut self) -> Vec<String> {
+        let ids: Vec<usize> = self
+            .jobs
+            .iter_mut()
+            .filter_map(|job| {
+                job.poll();
+                let changed = std::mem::take(&mut job.changed) && job.state != JobState::Running;
+                changed.then_some(job.id)
+            })
+            .collect();
+        self.report(&ids)
+    }
+
+    /// Polls every job and counts the ones still running. Those that
+    /// finished are left for `notify` to report.
+    pub fn running(&mut self) -> usize {
+        for job in &mut self.jobs {
+            job.poll();
+        }
+        self.jobs.iter().filter(|job| job.state == JobState::Running).count()
+    }
+
+    /// Sends SIGHUP to stopped jobs, followed by SIGCONT so they actually
+    /// receive it. Called when the shell exits so they aren't left behind.
+    pub fn hang_up_stopped(&self) {
+        for job in &self.jobs {
+            if job.state == JobState::Stopped {
+                let _ = job.signal(sys::SIGHUP);
+                let _ = job.signal(sys::SIGCONT);
+            }
+        }
+    }
+}
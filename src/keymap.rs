@@ -0,0 +1,280 @@
+//! Which keys do what in the line editor, and the changes to that made
+//! with `bind`.
+//!
+//! There is a keymap for Emacs mode and one for each of vi's modes. Emacs
+//! mode and vi's insert mode have tables of default keys, here; vi's
+//! command mode reads its keys in the editor along with their counts and
+//! motions, and `bind` can only add to or take from those.
+//!
+//! Keys are named in readline's notation, as in `"\C-g"` for Ctrl-G and
+//! `"\ef"` or `"\M-f"` for Alt-F. A key can be bound to one of the
+//! editor's functions, by its readline name, or with `bind -x` to a
+//! command, which runs with the line being typed in READLINE_LINE and
+//! the cursor's position in READLINE_POINT, and can change both.
+
+use std::collections::HashMap;
+
+use crate::editor::{self, Action, Key};
+
+/// The editor's functions by name.
+pub const FUNCTIONS: &[(&str, Action)] = &[
+    ("accept-line", Action::AcceptLine),
+    ("backward-char", Action::BackwardChar),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("backward-word", Action::BackwardWord),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("clear-screen", Action::ClearScreen),
+    ("complete", Action::Complete),
+    ("delete-char", Action::DeleteChar),
+    ("delete-char-or-eof", Action::DeleteCharOrEof),
+    ("end-of-line", Action::EndOfLine),
+    ("forward-char", Action::ForwardChar),
+    ("forward-word", Action::ForwardWord),
+    ("kill-line", Action::KillLine),
+    ("kill-word", Action::KillWord),
+    ("next-history", Action::NextHistory),
+    ("previous-history", Action::PreviousHistory),
+    ("redo", Action::Redo),
+    ("reverse-search-history", Action::ReverseSearchHistory),
+    ("undo", Action::Undo),
+    ("unix-line-discard", Action::UnixLineDiscard),
+    ("unix-word-rubout", Action::UnixWordRubout),
+    ("vi-movement-mode", Action::ViCommandMode),
+    ("yank", Action::Yank),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Keymap {
+    Emacs,
+    ViInsert,
+    ViCommand,
+}
+
+impl Keymap {
+    /// The keymap named `name`, as `bind -m` takes it.
+    pub fn named(name: &str) -> Option<Keymap> {
+        match name {
+            "emacs" | "emacs-standard" => Some(Keymap::Emacs),
+            "vi-insert" => Some(Keymap::ViInsert),
+            "vi" | "vi-command" | "vi-move" => Some(Keymap::ViCommand),
+            _ => None,
+        }
+    }
+
+    /// What `key` does in this keymap unless bound to something else.
+    pub fn default(self, key: Key) -> Option<Action> {
+        match self {
+            Keymap::Emacs => emacs_binding(key),
+            Keymap::ViInsert => vi_insert_binding(key),
+            Keymap::ViCommand => None,
+        }
+    }
+}
+
+/// What a key was bound to with `bind`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    Action(Action),
+    /// A command to run, from `bind -x`
+    Command(String),
+    /// Nothing, after `bind -r`. A character still types itself.
+    Unbound,
+}
+
+/// The keys bound with `bind`, which take the place of the defaults.
+#[derive(Default, Clone)]
+pub struct Bindings {
+    keys: HashMap<(Keymap, Key), Binding>,
+}
+
+impl Bindings {
+    /// What `key` was bound to in `keymap`, if it was.
+    pub fn get(&self, keymap: Keymap, key: Key) -> Option<&Binding> {
+        self.keys.get(&(keymap, key))
+    }
+
+    pub fn bind(&mut self, keymap: Keymap, key: Key, binding: Binding) {
+        self.keys.insert((keymap, key), binding);
+    }
+
+    /// Every key that does something in `keymap`, with what it does,
+    /// ordered by key.
+    pub fn list(&self, keymap: Keymap) -> Vec<(Key, Binding)> {
+        let mut keys: Vec<(Key, Binding)> = KEYS
+            .iter()
+            .filter(|key| self.get(keymap, **key).is_none())
+            .filter_map(|&key| Some((key, Binding::Action(keymap.default(key)?))))
+            .chain(self.keys.iter().filter(|((map, _), _)| *map == keymap).map(|((_, key), binding)| (*key, binding.clone())))
+            .filter(|(_, binding)| *binding != Binding::Unbound)
+            .collect();
+        keys.sort_by_key(|(key, _)| key_sequence(*key));
+        keys
+    }
+}
+
+/// The keys that aren't characters, which the default tables may bind.
+const KEYS: &[Key] = &[
+    Key::Ctrl('a'), Key::Ctrl('b'), Key::Ctrl('c'), Key::Ctrl('d'), Key::Ctrl('e'), Key::Ctrl('f'), Key::Ctrl('g'),
+    Key::Ctrl('k'), Key::Ctrl('l'), Key::Ctrl('n'), Key::Ctrl('o'), Key::Ctrl('p'), Key::Ctrl('q'), Key::Ctrl('r'),
+    Key::Ctrl('s'), Key::Ctrl('t'), Key::Ctrl('u'), Key::Ctrl('v'), Key::Ctrl('w'), Key::Ctrl('x'), Key::Ctrl('y'),
+    Key::Ctrl('z'), Key::Ctrl('_'), Key::Alt('b'), Key::Alt('d'), Key::Alt('f'), Key::Alt('_'), Key::AltBackspace, Key::Escape, Key::Enter, Key::Tab,
+    Key::BackTab, Key::Backspace, Key::Up, Key::Down, Key::Left, Key::Right, Key::Home, Key::End, Key::Delete,
+];
+
+/// The Emacs-style keys, as readline binds them by default.
+fn emacs_binding(key: Key) -> Option<Action> {
+    Some(match key {
+        Key::Enter => Action::AcceptLine,
+        Key::Ctrl('a') | Key::Home => Action::BeginningOfLine,
+        Key::Ctrl('e') | Key::End => Action::EndOfLine,
+        Key::Ctrl('f') | Key::Right => Action::ForwardChar,
+        Key::Ctrl('b') | Key::Left => Action::BackwardChar,
+        Key::Alt('f') => Action::ForwardWord,
+        Key::Alt('b') => Action::BackwardWord,
+        Key::Ctrl('d') => Action::DeleteCharOrEof,
+        Key::Delete => Action::DeleteChar,
+        Key::Backspace => Action::BackwardDeleteChar,
+        Key::Ctrl('k') => Action::KillLine,
+        Key::Ctrl('u') => Action::UnixLineDiscard,
+        Key::Ctrl('w') => Action::UnixWordRubout,
+        Key::Alt('d') => Action::KillWord,
+        Key::AltBackspace => Action::BackwardKillWord,
+        Key::Ctrl('y') => Action::Yank,
+        Key::Ctrl('p') | Key::Up => Action::PreviousHistory,
+        Key::Ctrl('n') | Key::Down => Action::NextHistory,
+        Key::Ctrl('r') => Action::ReverseSearchHistory,
+        Key::Tab => Action::Complete,
+        Key::Ctrl('l') => Action::ClearScreen,
+        Key::Ctrl('_') => Action::Undo,
+        Key::Alt('_') => Action::Redo,
+        _ => return None,
+    })
+}
+
+/// The keys of vi's insert mode. Anything not bound is typed in.
+fn vi_insert_binding(key: Key) -> Option<Action> {
+    Some(match key {
+        Key::Enter => Action::AcceptLine,
+        Key::Escape => Action::ViCommandMode,
+        Key::Home => Action::BeginningOfLine,
+        Key::End => Action::EndOfLine,
+        Key::Right => Action::ForwardChar,
+        Key::Left => Action::BackwardChar,
+        Key::Ctrl('d') => Action::DeleteCharOrEof,
+        Key::Delete => Action::DeleteChar,
+        Key::Backspace | Key::Ctrl('h') => Action::BackwardDeleteChar,
+        Key::Ctrl('u') => Action::UnixLineDiscard,
+        Key::Ctrl('w') => Action::UnixWordRubout,
+        Key::Up => Action::PreviousHistory,
+        Key::Down => Action::NextHistory,
+        Key::Ctrl('r') => Action::ReverseSearchHistory,
+        Key::Tab => Action::Complete,
+        Key::Ctrl('l') => Action::ClearScreen,
+        Key::Ctrl('_') => Action::Undo,
+        _ => return None,
+    })
+}
+
+/// The readline name of `action`.
+pub fn function_name(action: Action) -> &'static str {
+    FUNCTIONS.iter().find(|(_, a)| *a == action).map_or("", |(name, _)| name)
+}
+
+/// Splits a binding as `bind` takes it, `"keyseq": what`, into the key
+/// and what it's bound to, with any double quotes around that removed.
+pub fn parse_binding(text: &str) -> Result<(Key, &str), String> {
+    let rest = text.trim_start().strip_prefix('"').ok_or_else(|| format!("{}: key sequence must be quoted", text))?;
+    // The closing quote is the first without a backslash before it
+    let mut escaped = false;
+    let end = rest
+        .char_indices()
+        .find_map(|(i, c)| {
+            let closes = c == '"' && !escaped;
+            escaped = c == '\\' && !escaped;
+            closes.then_some(i)
+        })
+        .ok_or_else(|| format!("{}: no closing `\"' in key sequence", text))?;
+    let key = parse_key(&rest[..end])?;
+    let what = rest[end + 1..].trim_start().strip_prefix(':').ok_or_else(|| format!("{}: missing `:' after key sequence", text))?.trim();
+    let what = what.strip_prefix('"').and_then(|what| what.strip_suffix('"')).unwrap_or(what);
+    Ok((key, what))
+}
+
+/// The key a key sequence in readline's notation is typed with. The
+/// sequence is turned into the bytes it stands for and read the way the
+/// editor reads keys from the terminal, so it has to be one key.
+pub fn parse_key(sequence: &str) -> Result<Key, String> {
+    let mut bytes = Vec::new();
+    let mut chars = sequence.chars().peekable();
+    while chars.peek().is_some() {
+        escaped_byte(&mut chars, &mut bytes).ok_or_else(|| format!("\"{}\": bad key sequence", sequence))?;
+    }
+    editor::parse_key(&bytes).ok_or_else(|| format!("\"{}\": not a single key", sequence))
+}
+
+/// Reads one character of a key sequence, with `\C-` and `\M-` before
+/// it, or one backslash escape, into `bytes`.
+fn escaped_byte(chars: &mut std::iter::Peekable<std::str::Chars>, bytes: &mut Vec<u8>) -> Option<()> {
+    let c = chars.next()?;
+    if c != '\\' {
+        bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+        return Some(());
+    }
+    let c = chars.next()?;
+    let byte = match c {
+        'C' | 'M' if chars.peek() == Some(&'-') => {
+            chars.next();
+            if c == 'M' {
+                bytes.push(0x1b);
+                return escaped_byte(chars, bytes);
+            }
+            let mut control = Vec::new();
+            escaped_byte(chars, &mut control)?;
+            match control.as_slice() {
+                &[byte @ 0x3f..=0x7e] => byte.to_ascii_uppercase() ^ 0x40,
+                _ => return None,
+            }
+        }
+        'e' => 0x1b,
+        'a' => 0x07,
+        'b' => 0x08,
+        'd' => 0x7f,
+        'f' => 0x0c,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 0x0b,
+        '\\' | '"' | '\'' => c as u8,
+        _ => return None,
+    };
+    bytes.push(byte);
+    Some(())
+}
+
+/// `key` in readline's notation, as `bind` lists it.
+pub fn key_sequence(key: Key) -> String {
+    let char = |c: char| match c {
+        '\\' | '"' => format!("\\{}", c),
+        c => c.to_string(),
+    };
+    match key {
+        Key::Char(c) => char(c),
+        Key::Ctrl(c) => format!("\\C-{}", c),
+        Key::Alt(c) => format!("\\e{}", char(c)),
+        Key::AltBackspace => "\\e\\C-?".to_string(),
+        Key::Escape => "\\e".to_string(),
+        Key::Enter => "\\C-m".to_string(),
+        Key::Tab => "\\C-i".to_string(),
+        Key::BackTab => "\\e[Z".to_string(),
+        Key::Backspace => "\\C-?".to_string(),
+        Key::Up => "\\e[A".to_string(),
+        Key::Down => "\\e[B".to_string(),
+        Key::Right => "\\e[C".to_string(),
+        Key::Left => "\\e[D".to_string(),
+        Key::Home => "\\e[H".to_string(),
+        Key::End => "\\e[F".to_string(),
+        Key::Delete => "\\e[3~".to_string(),
+        Key::Paste | Key::Unknown => String::new(),
+    }
+}
@@ -0,0 +1,338 @@
+//! Splitting command lines into words and operators, and tracking the
+//! quoting and nesting of partial lines.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Tracks quoting and `( ... )` / `{ ...; }` nesting while a line is
+/// scanned one character at a time.
+pub struct Nesting {
+    pub quote: Option<char>,
+    pub escaped: bool,
+    pub depth: i32,
+    /// Whether the next word would start a command, which is the only
+    /// place `{` and `}` are special
+    command_start: bool,
+}
+
+impl Nesting {
+    pub fn new() -> Self {
+        Nesting { quote: None, escaped: false, depth: 0, command_start: true }
+    }
+
+    /// Feeds the next character, given the one after it.
+    pub fn feed(&mut self, c: char, next: Option<char>) {
+        if self.escaped {
+            self.escaped = false;
+            return;
+        }
+        if let Some(q) = self.quote {
+            if c == q {
+                self.quote = None;
+            } else if c == '\\' && q == '"' {
+                self.escaped = true;
+            }
+            return;
+        }
+
+        let word_ends = |next: Option<char>| {
+            next.is_none_or(|n| n.is_whitespace() || ";&|<>)".contains(n))
+        };
+        match c {
+            '\\' => self.escaped = true,
+            '\'' | '"' | '`' => self.quote = Some(c),
+            '(' => self.depth += 1,
+            ')' => self.depth -= 1,
+            '{' if self.command_start && next.is_none_or(char::is_whitespace) => {
+                self.depth += 1;
+            }
+            '}' if self.command_start && self.depth > 0 && word_ends(next) => self.depth -= 1,
+            _ => {}
+        }
+        self.command_start = match c {
+            c if c.is_whitespace() => self.command_start,
+            ';' | '&' | '|' | '(' => true,
+            '{' | '!' => self.command_start && next.is_none_or(char::is_whitespace),
+            _ => false,
+        };
+    }
+}
+
+/// A token of a command line: a word, still quoted and unexpanded, or
+/// an operator.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String),
+    Operator(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(text) | Token::Operator(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// The operators the lexer knows. Every prefix of an operator is an
+/// operator too, so they can be read greedily.
+const OPERATORS: &[&str] = &[
+    "&&", "||", ";;&", ";;", ";&", ";", "&", "|", "(", ")", "<<<", "<<-", "<<", "<&", "<", ">>", ">&", ">|", ">",
+    "&>>", "&>",
+];
+
+/// Splits a command line into words and operators. Whitespace separates
+/// words, and operators end them, except inside quotes, after a
+/// backslash, or within `$(...)`, `${...}`, `<(...)` and backquotes.
+/// Digits directly before a redirection operator belong to it, as in
+/// `2>`.
+pub fn lex(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                }
+            }
+            '\\' => {
+                word.push(c);
+                word.extend(chars.next());
+            }
+            '\'' => {
+                word.push(c);
+                for c in chars.by_ref() {
+                    word.push(c);
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                word.push(c);
+                while let Some(c) = chars.next() {
+                    word.push(c);
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        '$' | '`' => lex_nested(c, &mut chars, &mut word),
+                        _ => {}
+                    }
+                }
+            }
+            '$' | '`' => {
+                word.push(c);
+                lex_nested(c, &mut chars, &mut word);
+            }
+            // Process substitution
+            '<' | '>' if chars.peek() == Some(&'(') => {
+                word.push(c);
+                lex_nested('$', &mut chars, &mut word);
+            }
+            c if OPERATORS.contains(&c.to_string().as_str()) => {
+                let mut operator = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    let longer = format!("{}{}", operator, next);
+                    if !OPERATORS.contains(&longer.as_str()) {
+                        break;
+                    }
+                    operator = longer;
+                    chars.next();
+                }
+                let io_number = !word.is_empty() && word.chars().all(|c| c.is_ascii_digit());
+                if io_number && (c == '<' || c == '>') {
+                    operator.insert_str(0, &std::mem::take(&mut word));
+                } else if !word.is_empty() {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                }
+                tokens.push(Token::Operator(operator));
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+    tokens
+}
+
+/// Copies the rest of a `$(...)`, `${...}`, `${ ...; }` or backquoted
+/// substitution whose `$` or backquote was just read into `word`, if one
+/// starts here.
+fn lex_nested(c: char, chars: &mut Peekable<Chars>, word: &mut String) {
+    match (c, chars.peek().copied()) {
+        ('`', _) => {
+            while let Some(c) = chars.next() {
+                word.push(c);
+                match c {
+                    '`' => break,
+                    '\\' => word.extend(chars.next()),
+                    _ => {}
+                }
+            }
+        }
+        ('$', Some('(')) => {
+            chars.next();
+            word.push('(');
+            word.push_str(&read_substitution(chars));
+            word.push(')');
+        }
+        ('$', Some('{')) if starts_group_substitution(chars) => {
+            chars.next();
+            word.push('{');
+            word.push_str(&read_group_substitution(chars));
+            word.push('}');
+        }
+        ('$', Some('{')) => {
+            let mut depth = 0;
+            for c in chars.by_ref() {
+                word.push(c);
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether the `{` `chars` is at starts a `${ ...; }`, which a blank
+/// after it tells apart from a parameter.
+pub fn starts_group_substitution(chars: &Peekable<Chars>) -> bool {
+    let mut ahead = chars.clone();
+    ahead.next() == Some('{') && ahead.next().is_some_and(char::is_whitespace)
+}
+
+/// Reads the command of a `${ ...; }` whose `${` was just read, leaving
+/// `chars` past the closing brace. As with a `{ ...; }` group, only a
+/// `}` where a command could start closes it, but it needn't end a word:
+/// `"${ cmd;}"` is closed before the quote.
+pub fn read_group_substitution(chars: &mut Peekable<Chars>) -> String {
+    let mut command = String::new();
+    let mut nesting = Nesting::new();
+    nesting.feed('{', Some(' '));
+    while let Some(c) = chars.next() {
+        let next = if c == '}' { None } else { chars.peek().copied() };
+        nesting.feed(c, next);
+        if nesting.depth == 0 {
+            break;
+        }
+        command.push(c);
+    }
+    command
+}
+
+/// Reads the command of a `$(...)` whose `$(` was just read, leaving
+/// `chars` past the closing parenthesis.
+pub fn read_substitution(chars: &mut Peekable<Chars>) -> String {
+    let mut command = String::new();
+    let mut nesting = Nesting::new();
+    while let Some(c) = chars.next() {
+        nesting.feed(c, chars.peek().copied());
+        if nesting.depth < 0 {
+            break;
+        }
+        command.push(c);
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str) -> Token {
+        Token::Word(text.to_string())
+    }
+
+    fn op(text: &str) -> Token {
+        Token::Operator(text.to_string())
+    }
+
+    #[test]
+    fn splits_words_on_whitespace() {
+        assert_eq!(lex("  echo   a\tb  "), [word("echo"), word("a"), word("b")]);
+        assert_eq!(lex(""), []);
+    }
+
+    #[test]
+    fn keeps_quotes_and_escapes_in_words() {
+        assert_eq!(lex("echo 'a b' \"c d\""), [word("echo"), word("'a b'"), word("\"c d\"")]);
+        assert_eq!(lex("echo a\\ b"), [word("echo"), word("a\\ b")]);
+        assert_eq!(lex("echo \"a \\\" ;b\""), [word("echo"), word("\"a \\\" ;b\"")]);
+        assert_eq!(lex("echo 'a;|b'c"), [word("echo"), word("'a;|b'c")]);
+    }
+
+    #[test]
+    fn reads_operators_greedily() {
+        assert_eq!(lex("a&&b||c;d&"), [word("a"), op("&&"), word("b"), op("||"), word("c"), op(";"), word("d"), op("&")]);
+        assert_eq!(lex("a | b"), [word("a"), op("|"), word("b")]);
+        assert_eq!(lex("a >>f <<<s &>g"), [word("a"), op(">>"), word("f"), op("<<<"), word("s"), op("&>"), word("g")]);
+        assert_eq!(lex("cat <<-EOF"), [word("cat"), op("<<-"), word("EOF")]);
+    }
+
+    #[test]
+    fn attaches_descriptor_numbers_to_redirections() {
+        assert_eq!(lex("a 2>err 3<&0"), [word("a"), op("2>"), word("err"), op("3<&"), word("0")]);
+        // Only a whole word of digits is a descriptor number
+        assert_eq!(lex("echo a2>f"), [word("echo"), word("a2"), op(">"), word("f")]);
+        assert_eq!(lex("echo 2 >f"), [word("echo"), word("2"), op(">"), word("f")]);
+    }
+
+    #[test]
+    fn keeps_substitutions_whole() {
+        assert_eq!(lex("echo $(a | b; c)"), [word("echo"), word("$(a | b; c)")]);
+        assert_eq!(lex("echo $(a $(b) \")\")x"), [word("echo"), word("$(a $(b) \")\")x")]);
+        assert_eq!(lex("echo ${a:-b c}"), [word("echo"), word("${a:-b c}")]);
+        assert_eq!(lex("echo `a | b`"), [word("echo"), word("`a | b`")]);
+        assert_eq!(lex("diff <(a) >(b c)"), [word("diff"), word("<(a)"), word(">(b c)")]);
+        assert_eq!(lex("x=${ a | b; echo '}' }; } c"), [word("x=${ a | b; echo '}' }; }"), word("c")]);
+        assert_eq!(lex("echo \"${ a;}\";"), [word("echo"), word("\"${ a;}\""), op(";")]);
+    }
+
+    #[test]
+    fn lexes_groups() {
+        assert_eq!(lex("(a)"), [op("("), word("a"), op(")")]);
+        assert_eq!(lex("{ a; }"), [word("{"), word("a"), op(";"), word("}")]);
+    }
+
+    #[test]
+    fn tracks_nesting() {
+        let scan = |line: &str| {
+            let mut nesting = Nesting::new();
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                nesting.feed(c, chars.peek().copied());
+            }
+            nesting
+        };
+        assert_eq!(scan("echo 'a").quote, Some('\''));
+        assert_eq!(scan("echo \"a\\\"").quote, Some('"'));
+        assert_eq!(scan("echo \"a\" 'b'").quote, None);
+        assert!(scan("echo a\\").escaped);
+        assert_eq!(scan("(a; (b)").depth, 1);
+        assert_eq!(scan("{ a; }").depth, 0);
+        assert_eq!(scan("{ a;").depth, 1);
+        // Braces are only special where a command starts
+        assert_eq!(scan("echo {").depth, 0);
+        assert_eq!(scan("echo '('").depth, 0);
+    }
+
+    #[test]
+    fn reads_substitution_to_its_closing_parenthesis() {
+        let mut chars = "a (b) ')' c) rest".chars().peekable();
+        assert_eq!(read_substitution(&mut chars), "a (b) ')' c");
+        assert_eq!(chars.collect::<String>(), " rest");
+    }
+}
@@ -0,0 +1,2554 @@
+//! shellob, a basic shell. The `Shell` type runs command lines, either
+//! read interactively with `Shell::repl` or given to `Shell::eval`.
+
+mod complete;
+mod dirs;
+mod editor;
+mod error;
+mod exec;
+mod expand;
+mod frecency;
+#[cfg(feature = "git-prompt")]
+mod git;
+mod glob;
+mod highlight;
+mod history;
+mod input;
+mod jobs;
+mod keymap;
+mod lexer;
+mod output;
+mod parser;
+mod prompt;
+mod redirect;
+mod signals;
+mod sys;
+mod unicode;
+
+use std::io::{self, Write};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::fs;
+use std::io::{Read, Seek};
+use std::os::unix::io::AsRawFd;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub use error::ShellError;
+
+use input::{Line, Source};
+use lexer::{Nesting, Token};
+use dirs::DirStack;
+use editor::Editor;
+use history::History;
+use jobs::{JobState, Jobs};
+use keymap::{Binding, Bindings, Keymap};
+use redirect::{FdTable, RedirectOp};
+use sys::{Termios, WaitStatus};
+
+/// The optional features in Cargo.toml and whether this build has them.
+const FEATURES: &[(&str, bool)] = &[("git-prompt", cfg!(feature = "git-prompt"))];
+
+#[derive(Clone)]
+enum CommandType {
+    Builtin(fn(&mut Shell, &[String], &mut dyn Write) -> Result<i32, ShellError>),
+}
+
+/// What stops the shell running the commands it has in order: `return`
+/// out of a sourced file, or `exit` with the status the shell exits with.
+/// Each loop running commands breaks off when one is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+    Return,
+    Exit(i32),
+}
+
+/// A shell variable. Exported variables are passed in the environment
+/// of every command the shell runs; a variable can be exported before
+/// it has a value.
+#[derive(Clone)]
+struct Variable {
+    value: Option<String>,
+    exported: bool,
+}
+
+#[derive(Default, Clone)]
+struct Options {
+    noclobber: bool,
+    /// Give a pipeline the status of the last stage that failed, not
+    /// just of the last stage
+    pipefail: bool,
+    /// Write stdout a block at a time rather than a line at a time
+    bufferoutput: bool,
+    /// Drop patterns that match no files instead of passing them on
+    nullglob: bool,
+    /// Fail the command when a pattern matches no files
+    failglob: bool,
+    /// Let `**` match any number of directories
+    globstar: bool,
+    /// Edit lines with vi's keys instead of Emacs's
+    vi: bool,
+    /// Change into a directory typed as a command
+    autocd: bool,
+    /// Read back the history other shells save as they go
+    sharehistory: bool,
+    /// Suggest the rest of the line being typed from history
+    autosuggest: bool,
+    /// Colour the line being typed
+    highlight: bool,
+    /// Complete words ignoring case
+    nocasecomplete: bool,
+    /// Complete words to names that have them anywhere, not just at the
+    /// start
+    substringcomplete: bool,
+}
+
+impl Options {
+    const NAMES: [&'static str; 13] = [
+        "noclobber",
+        "pipefail",
+        "bufferoutput",
+        "nullglob",
+        "failglob",
+        "globstar",
+        "autocd",
+        "sharehistory",
+        "autosuggest",
+        "highlight",
+        "nocasecomplete",
+        "substringcomplete",
+        "vi",
+    ];
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "noclobber" => Some(&mut self.noclobber),
+            "pipefail" => Some(&mut self.pipefail),
+            "bufferoutput" => Some(&mut self.bufferoutput),
+            "nullglob" => Some(&mut self.nullglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "autocd" => Some(&mut self.autocd),
+            "sharehistory" => Some(&mut self.sharehistory),
+            "autosuggest" => Some(&mut self.autosuggest),
+            "highlight" => Some(&mut self.highlight),
+            "nocasecomplete" => Some(&mut self.nocasecomplete),
+            "substringcomplete" => Some(&mut self.substringcomplete),
+            "vi" => Some(&mut self.vi),
+            _ => None,
+        }
+    }
+}
+
+/// The exit status of a command line, as `$?` expands it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitStatus {
+    code: i32,
+}
+
+impl ExitStatus {
+    /// The status code: the command's exit code, or 128+n if it was
+    /// killed by signal n.
+    pub fn code(self) -> i32 {
+        self.code
+    }
+
+    pub fn success(self) -> bool {
+        self.code == 0
+    }
+}
+
+/// A shell: its variables, options, jobs and the state of the last
+/// command.
+pub struct Shell {
+    commands: HashMap<String, CommandType>,
+    options: Options,
+    /// Shell variables, starting out as the exported environment the
+    /// shell was started with
+    variables: HashMap<String, Variable>,
+    jobs: Jobs,
+    job_control: bool,
+    /// The terminal modes the shell expects, restored whenever a
+    /// foreground job gives the terminal back
+    tmodes: Option<Termios>,
+    /// Exit status of the last command, expanded by `$?`
+    last_status: i32,
+    /// The shell's own pid, expanded by `$$`. Kept rather than looked
+    /// up so subshells expand it to their parent's pid.
+    pid: i32,
+    /// Pid of the last job started in the background, expanded by `$!`
+    last_background: Option<i32>,
+    /// The name the shell was started as, expanded by `$0`
+    name: String,
+    /// Positional parameters `$1`, `$2`, ..., set with `set --`
+    positional: Vec<String>,
+    /// The logical working directory, which keeps the symlinks `cd`
+    /// went through, printed by `pwd`
+    cwd: PathBuf,
+    /// The directories `pushd` left, below `cwd`
+    dir_stack: DirStack,
+    /// Directories named with `hash -d`, which `~name` expands to
+    named_dirs: HashMap<String, String>,
+    /// Whether this is a login shell, which runs `~/.shellob_logout`
+    /// when it exits
+    login: bool,
+    /// Whether prompts are shown even though stdin isn't a terminal
+    interactive: bool,
+    /// The command lines typed at the prompt
+    history: History,
+    /// The keys bound in the line editor with `bind`
+    bindings: Bindings,
+    /// Exit status of the last command substitution in the command being
+    /// expanded, which becomes the status of a bare assignment
+    substitution_status: Cell<Option<i32>>,
+    /// Why expanding the command being run failed, if it did. The
+    /// command isn't run then.
+    expansion_error: Cell<Option<ShellError>>,
+    /// The shell's ends of the pipes to `<(...)` and `>(...)` process
+    /// substitutions, with the pids writing or reading them. They are
+    /// closed and reaped once the command using them is done.
+    process_substitutions: RefCell<Vec<(fs::File, i32)>>,
+    /// Files standing in for the shell's own descriptors: the pipes
+    /// `eval` sends output through, and the redirections of the group or
+    /// builtin being run. The commands the shell runs get them in place
+    /// of its own, and its messages go to them.
+    streams: FdTable,
+    /// Where `eval` sends the output and errors it would otherwise write
+    /// to the process's stdout and stderr
+    stdout: Option<Box<dyn Write + Send>>,
+    stderr: Option<Box<dyn Write + Send>>,
+    /// Set by `return` and `exit` until the commands they cut short have
+    /// been left
+    flow: Option<Flow>,
+    /// How many sourced files deep the shell is, which `return` needs to
+    /// be more than 0
+    sourcing: usize,
+    /// When SECONDS was last set, or else when the shell started, and
+    /// the value it was set to. It counts up from there.
+    seconds: (Instant, u64),
+    /// The state of the generator `$RANDOM` draws from
+    random: Cell<u64>,
+    /// How many lines have been read of the script, or the input, being
+    /// run
+    lines_read: usize,
+    /// The line of it the command line being run started on, expanded by
+    /// `$LINENO`
+    line_number: usize,
+    /// The commands `trap` set for signals, by number, with 0 for the
+    /// shell exiting. An empty one ignores the signal.
+    traps: BTreeMap<i32, String>,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Shell::new()
+    }
+}
+
+impl Shell {
+    /// A shell with the process's environment as its variables. It does
+    /// no job control until `repl` takes over the terminal.
+    pub fn new() -> Self {
+        let mut commands = HashMap::new();
+        
+        commands.insert("cd".to_string(), CommandType::Builtin(|shell, args, out| {
+            // `-L` (the default) follows symlinks logically, `-P` resolves
+            // them first
+            let mut physical = false;
+            let mut args = args.iter().peekable();
+            while let Some(flag) = args.next_if(|arg| arg.starts_with('-') && arg.len() > 1) {
+                match flag.as_str() {
+                    "-L" => physical = false,
+                    "-P" => physical = true,
+                    "--" => break,
+                    _ => return Err(ShellError::usage("cd", format!("{}: invalid option", flag))),
+                }
+            }
+            // No directory means HOME, and `-` the previous directory,
+            // which is printed since it isn't on the command line
+            let target = args.next().map(String::as_str);
+            let new_dir = match target {
+                None => shell.parameter("HOME").ok_or_else(|| ShellError::builtin("cd", "HOME not set"))?,
+                Some("-") => shell.parameter("OLDPWD").ok_or_else(|| ShellError::builtin("cd", "OLDPWD not set"))?,
+                Some(dir) => dir.to_string(),
+            };
+            // A directory found through CDPATH is printed too
+            let found = shell.search_cdpath(&new_dir);
+            let dir = found.as_deref().unwrap_or(&new_dir);
+            shell.change_dir(dir, physical).map_err(|e| ShellError::builtin("cd", format!("{}: {}", dir, e)))?;
+            if target == Some("-") || found.is_some_and(|path| path != new_dir) {
+                let _ = writeln!(out, "{}", shell.cwd.display());
+            }
+            Ok(0)
+        }));
+
+        commands.insert("pushd".to_string(), CommandType::Builtin(|shell, args, out| {
+            let failed = |dir: &Path, e: io::Error| ShellError::builtin("pushd", format!("{}: {}", dir.display(), e));
+            let cwd = shell.cwd.clone();
+            match args.first() {
+                // No directory swaps the top two entries
+                None => {
+                    let top = shell.dir_stack.top().ok_or_else(|| ShellError::builtin("pushd", "no other directory"))?;
+                    let top = top.to_path_buf();
+                    shell.change_dir(&top, false).map_err(|e| failed(&top, e))?;
+                    shell.dir_stack.replace_top(cwd);
+                }
+                Some(arg) if arg.len() > 1 && (arg.starts_with('+') || arg.starts_with('-')) => {
+                    let index = shell.dir_stack.index(arg).ok_or_else(|| {
+                        ShellError::builtin("pushd", format!("{}: directory stack index out of range", arg))
+                    })?;
+                    let mut stack = shell.dir_stack.clone();
+                    let top = stack.rotate(&cwd, index);
+                    shell.change_dir(&top, false).map_err(|e| failed(&top, e))?;
+                    shell.dir_stack = stack;
+                }
+                Some(dir) => {
+                    shell.change_dir(dir, false).map_err(|e| failed(Path::new(dir), e))?;
+                    shell.dir_stack.push(cwd);
+                }
+            }
+            let _ = writeln!(out, "{}", shell.dirs(false).join(" "));
+            Ok(0)
+        }));
+
+        commands.insert("popd".to_string(), CommandType::Builtin(|shell, args, out| {
+            if shell.dir_stack.is_empty() {
+                return Err(ShellError::builtin("popd", "directory stack empty"));
+            }
+            let index = match args.first() {
+                None => 0,
+                Some(arg) => shell.dir_stack.index(arg).ok_or_else(|| {
+                    ShellError::builtin("popd", format!("{}: directory stack index out of range", arg))
+                })?,
+            };
+            // Popping the working directory changes to the entry below it;
+            // any other entry is just dropped
+            if index == 0 {
+                let top = shell.dir_stack.top().unwrap().to_path_buf();
+                shell
+                    .change_dir(&top, false)
+                    .map_err(|e| ShellError::builtin("popd", format!("{}: {}", top.display(), e)))?;
+                shell.dir_stack.remove(1);
+            } else {
+                shell.dir_stack.remove(index);
+            }
+            let _ = writeln!(out, "{}", shell.dirs(false).join(" "));
+            Ok(0)
+        }));
+
+        commands.insert("dirs".to_string(), CommandType::Builtin(|shell, args, out| {
+            let (mut long, mut lines, mut numbered) = (false, false, false);
+            let mut entry = None;
+            for arg in args {
+                match arg.as_str() {
+                    "-c" => {
+                        shell.dir_stack.clear();
+                        return Ok(0);
+                    }
+                    "-l" => long = true,
+                    "-p" => lines = true,
+                    "-v" => numbered = true,
+                    index if index.len() > 1 && (index.starts_with('+') || index.starts_with('-')) => {
+                        entry = Some(shell.dir_stack.index(index).ok_or_else(|| {
+                            ShellError::builtin("dirs", format!("{}: directory stack index out of range", index))
+                        })?);
+                    }
+                    other => return Err(ShellError::usage("dirs", format!("{}: invalid argument", other))),
+                }
+            }
+            let dirs = shell.dirs(long);
+            if let Some(index) = entry {
+                let _ = writeln!(out, "{}", dirs[index]);
+            } else if numbered {
+                for (index, dir) in dirs.iter().enumerate() {
+                    let _ = writeln!(out, "{:2}  {}", index, dir);
+                }
+            } else if lines {
+                for dir in &dirs {
+                    let _ = writeln!(out, "{}", dir);
+                }
+            } else {
+                let _ = writeln!(out, "{}", dirs.join(" "));
+            }
+            Ok(0)
+        }));
+
+        commands.insert("hash".to_string(), CommandType::Builtin(|shell, args, out| {
+            // `-d` lists or defines named directories; otherwise the
+            // names are only checked, since commands aren't cached
+            if args.first().is_some_and(|arg| arg == "-d") {
+                if args.len() == 1 {
+                    let mut names: Vec<_> = shell.named_dirs.iter().collect();
+                    names.sort();
+                    for (name, dir) in names {
+                        let _ = writeln!(out, "{}={}", name, dir);
+                    }
+                    return Ok(0);
+                }
+                let mut status = 0;
+                for arg in &args[1..] {
+                    match arg.split_once('=') {
+                        Some((name, dir)) if Shell::is_name(name) => {
+                            shell.named_dirs.insert(name.to_string(), dir.to_string());
+                        }
+                        Some(_) => status = shell.report(&ShellError::builtin("hash", format!("{}: invalid name", arg))),
+                        None => match shell.named_dirs.get(arg) {
+                            Some(dir) => {
+                                let _ = writeln!(out, "{}={}", arg, dir);
+                            }
+                            None => status = shell.report(&ShellError::builtin("hash", format!("{}: not found", arg))),
+                        },
+                    }
+                }
+                return Ok(status);
+            }
+            let mut status = 0;
+            for name in args {
+                if !shell.commands.contains_key(name) && shell.find_in_path(name).is_none() {
+                    status = shell.report(&ShellError::builtin("hash", format!("{}: not found", name)));
+                }
+            }
+            Ok(status)
+        }));
+
+        // `j` (or `z`) jumps to the directory visited most often and most
+        // recently whose path contains the given strings in order
+        let jump: fn(&mut Shell, &[String], &mut dyn Write) -> Result<i32, ShellError> = |shell, args, out| {
+            let list = args.first().is_some_and(|arg| arg == "-l");
+            let patterns = if list { &args[1..] } else { args };
+            let file = shell.frecency_file().ok_or_else(|| ShellError::builtin("j", "HOME not set"))?;
+            let mut database = frecency::Database::open(file);
+            let found: Vec<_> = database.matches(patterns).into_iter().map(|(dir, score)| (dir.to_string(), score)).collect();
+            if list || patterns.is_empty() {
+                for (dir, score) in found.iter().rev() {
+                    let _ = writeln!(out, "{:<10.1} {}", score, dir);
+                }
+                return Ok(0);
+            }
+            // Directories that have gone away are dropped on the way
+            let (gone, found): (Vec<_>, Vec<_>) = found.into_iter().partition(|(dir, _)| !Path::new(dir).is_dir());
+            if !gone.is_empty() {
+                for (dir, _) in &gone {
+                    database.forget(dir);
+                }
+                let _ = database.save();
+            }
+            let (dir, _) = found
+                .first()
+                .ok_or_else(|| ShellError::builtin("j", format!("{}: no match", patterns.join(" "))))?;
+            shell.change_dir(dir, false).map_err(|e| ShellError::builtin("j", format!("{}: {}", dir, e)))?;
+            Ok(0)
+        };
+        commands.insert("j".to_string(), CommandType::Builtin(jump));
+        commands.insert("z".to_string(), CommandType::Builtin(jump));
+
+        // `history [n]` lists the last n (or all) lines typed at the prompt,
+        // `-c` forgets them and `-d n` forgets one. With HISTTIMEFORMAT set,
+        // entries are listed with when they were run in that format.
+        commands.insert("history".to_string(), CommandType::Builtin(|shell, args, out| {
+            match args.first().map(String::as_str) {
+                Some("-c") => shell.history.clear(),
+                Some("-d") => {
+                    let number = args.get(1).ok_or_else(|| ShellError::usage("history", "-d: option requires an argument"))?;
+                    let deleted = number.parse().ok().and_then(|n| shell.history.delete(n));
+                    if deleted.is_none() {
+                        return Err(ShellError::builtin("history", format!("{}: history position out of range", number)));
+                    }
+                }
+                Some(flag) if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(ShellError::usage("history", format!("{}: invalid option", flag)));
+                }
+                count => {
+                    let entries = shell.history.entries();
+                    let count = match count {
+                        Some(count) => count
+                            .parse()
+                            .map_err(|_| ShellError::usage("history", format!("{}: numeric argument required", count)))?,
+                        None => entries.len(),
+                    };
+                    let start = entries.len().saturating_sub(count);
+                    let format = shell.parameter("HISTTIMEFORMAT");
+                    for (i, (entry, &time)) in entries.iter().zip(shell.history.times()).enumerate().skip(start) {
+                        let time = format.as_ref().map(|format| sys::format_time(time, format)).unwrap_or_default();
+                        let _ = writeln!(out, "{:5}  {}{}", i + 1, time, entry);
+                    }
+                }
+            }
+            Ok(0)
+        }));
+
+        commands.insert("fc".to_string(), CommandType::Builtin(|shell, args, out| shell.fix_command(args, out)));
+
+        commands.insert("bind".to_string(), CommandType::Builtin(|shell, args, out| shell.bind(args, out)));
+        commands.insert("trap".to_string(), CommandType::Builtin(|shell, args, out| shell.trap(args, out)));
+
+        commands.insert("pwd".to_string(), CommandType::Builtin(|shell, args, out| {
+            let mut physical = false;
+            for flag in args.iter().filter(|arg| arg.starts_with('-')) {
+                match flag.as_str() {
+                    "-L" => physical = false,
+                    "-P" => physical = true,
+                    _ => return Err(ShellError::usage("pwd", format!("{}: invalid option", flag))),
+                }
+            }
+            let dir = match physical {
+                true => env::current_dir().map_err(|e| ShellError::builtin("pwd", e))?,
+                false => shell.cwd.clone(),
+            };
+            let _ = writeln!(out, "{}", dir.display());
+            Ok(0)
+        }));
+
+        commands.insert("echo".to_string(), CommandType::Builtin(|_, args, out| {
+            let _ = writeln!(out, "{}", args.join(" "));
+            Ok(0)
+        }));
+        
+        // Without a status, `exit` exits with the last command's. Like
+        // any exit status, the one given is taken modulo 256. Nothing
+        // more is run, and the shell exits once out of whatever it was
+        // running.
+        commands.insert("exit".to_string(), CommandType::Builtin(|shell, args, _| {
+            let code = match args {
+                [] => shell.last_status,
+                [code] => match code.parse::<i64>() {
+                    Ok(code) => code.rem_euclid(256) as i32,
+                    Err(_) => shell.report(&ShellError::usage("exit", format!("{}: numeric argument required", code))),
+                },
+                _ => return Err(ShellError::builtin("exit", "too many arguments")),
+            };
+            shell.flow = Some(Flow::Exit(code));
+            Ok(code)
+        }));
+
+        // Stops running the file being sourced. `source` returns the
+        // status given, or else the last command's.
+        commands.insert("return".to_string(), CommandType::Builtin(|shell, args, _| {
+            if shell.sourcing == 0 {
+                return Err(ShellError::builtin("return", "can only `return' from a sourced file"));
+            }
+            let code = match args {
+                [] => shell.last_status,
+                [code] => match code.parse::<i64>() {
+                    Ok(code) => code.rem_euclid(256) as i32,
+                    Err(_) => return Err(ShellError::usage("return", format!("{}: numeric argument required", code))),
+                },
+                _ => return Err(ShellError::builtin("return", "too many arguments")),
+            };
+            shell.flow = Some(Flow::Return);
+            Ok(code)
+        }));
+
+        // Runs the commands in a file in this shell, so what they set
+        // stays set. A name without a `/` is looked for in PATH first.
+        // Any arguments after it are the positional parameters while it
+        // runs.
+        let source = CommandType::Builtin(|shell, args, _| {
+            let Some((name, args)) = args.split_first() else {
+                return Err(ShellError::usage("source", "filename argument required"));
+            };
+            let path = match name.contains('/') {
+                true => None,
+                false => shell.find_in_path(name),
+            };
+            let path = path.unwrap_or_else(|| name.clone());
+            let script = fs::read_to_string(&path).map_err(|source| ShellError::File { path, source })?;
+            let positional = match args {
+                [] => None,
+                args => Some(std::mem::replace(&mut shell.positional, args.to_vec())),
+            };
+            let status = shell.run_sourced(&script);
+            if let Some(positional) = positional {
+                shell.positional = positional;
+            }
+            Ok(status)
+        });
+        commands.insert("source".to_string(), source.clone());
+        commands.insert(".".to_string(), source);
+
+        // Clears the scrollback too, unless given -x, as ncurses's clear
+        // does, without needing a terminfo entry
+        commands.insert("clear".to_string(), CommandType::Builtin(|_, args, out| {
+            let scrollback = match args {
+                [] => true,
+                [flag] if flag == "-x" => false,
+                [arg, ..] => return Err(ShellError::usage("clear", format!("{}: invalid option", arg))),
+            };
+            let _ = write!(out, "{}{}", editor::CLEAR_SCREEN, if scrollback { "\x1b[3J" } else { "" });
+            let _ = out.flush();
+            Ok(0)
+        }));
+
+        let version = CommandType::Builtin(|_, _, out| {
+            let _ = writeln!(out, "{}", Shell::version_info());
+            Ok(0)
+        });
+        commands.insert("version".to_string(), version.clone());
+        commands.insert("shellob".to_string(), version);
+
+        commands.insert("set".to_string(), CommandType::Builtin(|shell, args, out| {
+            if args.is_empty() || args == ["-o"] {
+                for name in Options::NAMES {
+                    let enabled = *shell.options.get_mut(name).unwrap();
+                    let _ = writeln!(out, "{}\t{}", name, if enabled { "on" } else { "off" });
+                }
+                return Ok(0);
+            }
+            let mut args = args.iter();
+            while let Some(flag) = args.next() {
+                // `--` or the first word that isn't an option starts the
+                // new positional parameters
+                let enable = match flag.chars().next() {
+                    _ if flag == "--" => {
+                        shell.positional = args.cloned().collect();
+                        break;
+                    }
+                    Some('-') => true,
+                    Some('+') => false,
+                    _ => {
+                        shell.positional = std::iter::once(flag).chain(args).cloned().collect();
+                        break;
+                    }
+                };
+                let name = match &flag[1..] {
+                    "o" => args.next().map_or("", String::as_str),
+                    "C" => "noclobber",
+                    other => other,
+                };
+                match shell.options.get_mut(name) {
+                    // Emacs keys are what's used without vi's
+                    _ if name == "emacs" => shell.options.vi = !enable,
+                    Some(option) => *option = enable,
+                    None => return Err(ShellError::usage("set", format!("{}: invalid option name", name))),
+                }
+            }
+            output::set_buffered(shell.options.bufferoutput);
+            Ok(0)
+        }));
+
+        commands.insert("shift".to_string(), CommandType::Builtin(|shell, args, _| {
+            let count = match args.first().map(|count| count.parse::<usize>()) {
+                None => 1,
+                Some(Ok(count)) => count,
+                Some(Err(_)) => {
+                    return Err(ShellError::builtin("shift", format!("{}: numeric argument required", args[0])));
+                }
+            };
+            if count > shell.positional.len() {
+                return Ok(1);
+            }
+            shell.positional.drain(..count);
+            Ok(0)
+        }));
+
+        commands.insert("export".to_string(), CommandType::Builtin(|shell, args, out| {
+            if args.is_empty() || args == ["-p"] {
+                let mut names: Vec<_> = shell.variables.iter().filter(|(_, v)| v.exported).collect();
+                names.sort_by_key(|(name, _)| name.as_str());
+                for (name, variable) in names {
+                    let _ = match &variable.value {
+                        Some(value) => writeln!(out, "export {}=\"{}\"", name, value),
+                        None => writeln!(out, "export {}", name),
+                    };
+                }
+                return Ok(0);
+            }
+            let mut status = 0;
+            for word in args {
+                let (name, value) = match word.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (word.as_str(), None),
+                };
+                if !Shell::is_name(name) {
+                    let error = ShellError::builtin("export", format!("`{}': not a valid identifier", word));
+                    status = shell.report(&error);
+                    continue;
+                }
+                if let Some(value) = value {
+                    shell.set_variable(name, value);
+                }
+                shell
+                    .variables
+                    .entry(name.to_string())
+                    .or_insert(Variable { value: None, exported: false })
+                    .exported = true;
+            }
+            Ok(status)
+        }));
+
+        commands.insert("jobs".to_string(), CommandType::Builtin(|shell, _, out| {
+            for line in shell.jobs.list() {
+                let _ = writeln!(out, "{}", line);
+            }
+            Ok(0)
+        }));
+
+        commands.insert("fg".to_string(), CommandType::Builtin(|shell, args, out| {
+            let id = shell.jobs.resolve(args.first().map(String::as_str)).map_err(|e| ShellError::builtin("fg", e))?;
+            let job = shell.jobs.remove(id).unwrap();
+            let _ = writeln!(out, "{}", job.command);
+            let _ = out.flush();
+            Ok(shell.wait_foreground(job))
+        }));
+
+        commands.insert("bg".to_string(), CommandType::Builtin(|shell, args, out| {
+            let id = shell.jobs.resolve(args.first().map(String::as_str)).map_err(|e| ShellError::builtin("bg", e))?;
+            shell.jobs.make_current(id);
+            let job = shell.jobs.get_mut(id).unwrap();
+            if job.state == JobState::Running {
+                // Not a failure, but said on stderr all the same
+                shell.report(&ShellError::builtin("bg", format!("job {} already in background", id)));
+                return Ok(0);
+            }
+            let _ = job.resume();
+            let _ = writeln!(out, "[{}]+ {} &", job.id, job.command.trim_end_matches('&').trim_end());
+            Ok(0)
+        }));
+
+        commands.insert("type".to_string(), CommandType::Builtin(|shell, args, out| {
+            let Some(name) = args.first() else {
+                let _ = writeln!(out, "type: not enough arguments");
+                return Ok(1);
+            };
+            match name.as_str() {
+                builtin if shell.commands.contains_key(builtin) => {
+                    let _ = writeln!(out, "{} is a shellob builtin", builtin);
+                    Ok(0)
+                }
+                cmd => {
+                    if let Some(path) = shell.find_in_path(cmd) {
+                        let _ = writeln!(out, "{} is {}", cmd, path);
+                        Ok(0)
+                    } else {
+                        let _ = writeln!(out, "{}: not found", cmd);
+                        Ok(1)
+                    }
+                }
+            }
+        }));
+
+        let mut shell = Shell {
+            commands,
+            options: Options::default(),
+            variables: env::vars()
+                .map(|(name, value)| (name, Variable { value: Some(value), exported: true }))
+                .collect(),
+            jobs: Jobs::default(),
+            job_control: false,
+            tmodes: None,
+            last_status: 0,
+            pid: sys::getpid(),
+            last_background: None,
+            name: env::args().next().unwrap_or_else(|| "shellob".to_string()),
+            positional: Vec::new(),
+            cwd: Shell::initial_cwd(),
+            dir_stack: DirStack::default(),
+            named_dirs: HashMap::new(),
+            login: false,
+            interactive: false,
+            history: History::default(),
+            bindings: Bindings::default(),
+            substitution_status: Cell::new(None),
+            expansion_error: Cell::new(None),
+            process_substitutions: RefCell::new(Vec::new()),
+            streams: FdTable::default(),
+            stdout: None,
+            stderr: None,
+            flow: None,
+            sourcing: 0,
+            seconds: (Instant::now(), 0),
+            random: Cell::new(Shell::random_seed()),
+            lines_read: 0,
+            line_number: 0,
+            traps: BTreeMap::new(),
+        };
+        // Children see where the shell started even if PWD was missing or
+        // stale
+        let cwd = shell.cwd.to_string_lossy().into_owned();
+        shell.export_variable("PWD", &cwd);
+        shell
+    }
+
+    /// Sends the output of the commands `eval` runs, and the shell's own,
+    /// to `writer` instead of the process's stdout.
+    pub fn set_stdout(&mut self, writer: impl Write + Send + 'static) {
+        self.stdout = Some(Box::new(writer));
+    }
+
+    /// Sends error messages from `eval`, the commands' and the shell's
+    /// own, to `writer` instead of the process's stderr.
+    pub fn set_stderr(&mut self, writer: impl Write + Send + 'static) {
+        self.stderr = Some(Box::new(writer));
+    }
+
+    /// Sets the name `$0` expands to, such as the script being run.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    /// Sets the positional parameters `$1`, `$2`, ...
+    pub fn set_positional(&mut self, args: Vec<String>) {
+        self.positional = args;
+    }
+
+    /// Buffers what the shell writes to stdout, as `set -o bufferoutput`
+    /// does, which is worth it when running a script. The buffer is
+    /// flushed whenever something else could write to the same place.
+    pub fn set_buffered_output(&mut self, buffered: bool) {
+        self.options.bufferoutput = buffered;
+        output::set_buffered(buffered);
+    }
+
+    /// Makes `repl` show prompts even if stdin isn't a terminal.
+    pub fn set_interactive(&mut self) {
+        self.interactive = true;
+    }
+
+    /// Runs `script` one command line after another, as if each had been
+    /// typed at the prompt, and returns the status of the last command.
+    /// Here-document bodies and lines that continue a command are taken
+    /// from the script too. `exit` stops the script with the status it
+    /// gives; it's up to the caller to act on it, or call `exit` itself.
+    ///
+    /// Output goes to the writers given to `set_stdout` and `set_stderr`,
+    /// if there are any. Commands write to pipes the shell reads into
+    /// them, so `eval` returns once the pipes are closed, which a command
+    /// left running in the background holds up.
+    ///
+    /// ```
+    /// use std::io::{self, Write};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use shellob::Shell;
+    ///
+    /// #[derive(Clone, Default)]
+    /// struct Output(Arc<Mutex<Vec<u8>>>);
+    ///
+    /// impl Write for Output {
+    ///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    ///         self.0.lock().unwrap().write(buf)
+    ///     }
+    ///
+    ///     fn flush(&mut self) -> io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let output = Output::default();
+    /// let mut shell = Shell::new();
+    /// shell.set_stdout(output.clone());
+    /// let status = shell.eval("greeting=hello\necho $greeting | tr a-z A-Z; false");
+    /// assert_eq!(status.code(), 1);
+    /// assert_eq!(*output.0.lock().unwrap(), b"HELLO\n");
+    /// ```
+    pub fn eval(&mut self, script: &str) -> ExitStatus {
+        self.flow = None;
+        let mut pumps = Vec::new();
+        for fd in [1, 2] {
+            let writer = match fd {
+                1 => self.stdout.take(),
+                _ => self.stderr.take(),
+            };
+            let Some(writer) = writer else {
+                continue;
+            };
+            match Shell::capture(writer) {
+                Ok((file, pump)) => {
+                    self.streams.insert(fd, file);
+                    pumps.push((fd, pump));
+                }
+                Err(source) => self.last_status = self.report(&ShellError::System { call: "pipe", source }),
+            }
+        }
+
+        // Lines are numbered from the start of the script
+        let outer = (std::mem::take(&mut self.lines_read), self.line_number);
+        let mut source = Source::Script(script.lines());
+        while let Ok(Line::Text(line)) = self.next_line(&mut source, "", "") {
+            self.run_line(line, &mut source);
+            if self.flow.is_some() {
+                break;
+            }
+        }
+        (self.lines_read, self.line_number) = outer;
+
+        output::flush();
+        for (fd, pump) in pumps {
+            // Closing the shell's end of the pipe lets the pump finish
+            // once the commands holding the other copies are done
+            self.streams.take(fd);
+            let writer = pump.join().ok();
+            match fd {
+                1 => self.stdout = writer,
+                _ => self.stderr = writer,
+            }
+        }
+        ExitStatus { code: self.last_status }
+    }
+
+    // Makes a pipe whose reading end a thread copies into `writer`,
+    // returning the writing end and the thread, which hands the writer
+    // back when the pipe is closed.
+    fn capture(mut writer: Box<dyn Write + Send>) -> io::Result<(fs::File, JoinHandle<Box<dyn Write + Send>>)> {
+        let (mut reader, file) = sys::pipe()?;
+        let pump = thread::spawn(move || {
+            let _ = io::copy(&mut reader, &mut writer);
+            let _ = writer.flush();
+            writer
+        });
+        Ok((file, pump))
+    }
+
+    /// Prompts for command lines on stdin and runs them until the end of
+    /// input or `exit`. With a terminal on stdin the shell takes it over
+    /// and does job control.
+    pub fn repl(&mut self) -> ! {
+        self.job_control = sys::isatty(0);
+        if self.job_control {
+            self.take_terminal();
+        }
+        if let Err(source) = signals::install() {
+            self.report(&ShellError::System { call: "sigaction", source });
+        }
+
+        let mut source = match sys::isatty(0) {
+            true => Source::Terminal(Editor::default()),
+            false => Source::Stdin { interactive: self.interactive },
+        };
+        if let (Source::Terminal(_), Some(file)) = (&source, self.history_file()) {
+            self.history = History::open(file, self.history_size());
+        }
+        loop {
+            self.run_traps();
+            if signals::take_child_changed() {
+                for notice in self.jobs.notify() {
+                    let _ = writeln!(self.streams.writer(1), "{}", notice);
+                }
+            }
+            self.history.set_shared(self.options.sharehistory);
+            if self.options.sharehistory {
+                self.history.reload(self.history_size());
+            }
+            if matches!(source, Source::Terminal(_) | Source::Stdin { interactive: true }) {
+                self.run_hook("PROMPT_COMMAND", None);
+            }
+            if let Some(Flow::Exit(code)) = self.flow {
+                self.exit(code);
+            }
+
+            output::flush();
+            let (prompt, right_prompt) = (self.primary_prompt(), self.right_prompt());
+            let line = match self.next_line(&mut source, &prompt, &right_prompt) {
+                Ok(Line::Text(line)) => line,
+                Ok(Line::Interrupted) => {
+                    println!();
+                    continue;
+                }
+                Ok(Line::Eof) => self.exit(self.last_status),
+                Err(source) => self.exit(self.report(&ShellError::System { call: "read", source })),
+            };
+            self.run_line(line, &mut source);
+            if let Some(Flow::Exit(code)) = self.flow {
+                self.exit(code);
+            }
+        }
+    }
+
+    // Reads the next line from `source`, counting it for `$LINENO`.
+    fn next_line(&mut self, source: &mut Source, prompt: &str, right_prompt: &str) -> io::Result<Line> {
+        let line = source.read_line(prompt, right_prompt, self);
+        if let Ok(Line::Text(_)) = line {
+            self.lines_read += 1;
+        }
+        line
+    }
+
+    // Runs the command line that starts with `line`, reading whatever
+    // more of it there is from `source`.
+    fn run_line(&mut self, line: String, source: &mut Source) {
+        self.line_number = self.lines_read;
+        let mut input = match self.read_continuation(line, source) {
+            Ok(Some(input)) => input,
+            Ok(None) => return,
+            Err(e) => {
+                self.last_status = self.report(&e);
+                return;
+            }
+        };
+        // Lines typed at the prompt can refer to earlier ones. Like bash,
+        // an expanded line is shown before it runs.
+        if let Source::Terminal(_) = source {
+            match self.history.expand(&input) {
+                Ok(Some(expanded)) => {
+                    println!("{}", expanded);
+                    input = expanded;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.last_status = self.report(&e);
+                    return;
+                }
+            }
+            self.history.add(&input, self.history_size(), self.history_control());
+        }
+        let interactive = matches!(source, Source::Terminal(_) | Source::Stdin { interactive: true });
+        if interactive && !input.trim().is_empty() {
+            self.run_hook("PREEXEC_COMMAND", Some(&[input.clone()]));
+        }
+        // Several lines pasted at once run one after the other, as they
+        // would in a script
+        let pasted = matches!(source, Source::Terminal(_)) && Shell::has_line_break(&input);
+        let command = Shell::strip_comment(&input).trim();
+        let heredocs = match pasted {
+            true => VecDeque::new(),
+            false => match self.read_heredocs(command, source) {
+                Some(heredocs) => heredocs,
+                None => return,
+            },
+        };
+        let started = (Instant::now(), sys::cpu_times());
+        match pasted {
+            true => {
+                self.eval(&input);
+            }
+            false => self.handle_command(command, heredocs),
+        }
+        if interactive {
+            self.report_time(&input, started);
+        }
+    }
+
+    // Reports how long the command line `command` took, as TIMEFMT says,
+    // if it took at least REPORTTIME seconds of wall-clock time. In
+    // TIMEFMT, `%E` is the time it took, `%U` and `%S` the CPU time spent
+    // in user code and in the kernel, and `%J` the command line.
+    fn report_time(&self, command: &str, (started, cpu): (Instant, sys::CpuTimes)) {
+        let Some(threshold) = self.parameter("REPORTTIME").and_then(|threshold| threshold.parse::<f64>().ok()) else {
+            return;
+        };
+        let elapsed = started.elapsed();
+        if elapsed.as_secs_f64() < threshold {
+            return;
+        }
+        let now = sys::cpu_times();
+        let format = self.parameter("TIMEFMT").unwrap_or_else(|| "%J  %E total".to_string());
+        let mut report = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                report.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('E') => report.push_str(&Shell::format_duration(elapsed)),
+                Some('U') => report.push_str(&Shell::format_duration(now.user.saturating_sub(cpu.user))),
+                Some('S') => report.push_str(&Shell::format_duration(now.system.saturating_sub(cpu.system))),
+                Some('J') => report.push_str(command.lines().next().unwrap_or("").trim()),
+                Some('%') => report.push('%'),
+                other => {
+                    report.push('%');
+                    report.extend(other);
+                }
+            }
+        }
+        let _ = writeln!(self.streams.writer(2), "{}", report);
+    }
+
+    // `duration` as people write it: seconds to two places under a
+    // minute, and minutes and seconds or hours and minutes above.
+    fn format_duration(duration: Duration) -> String {
+        let secs = duration.as_secs();
+        match secs {
+            0..=59 => format!("{:.2}s", duration.as_secs_f64()),
+            60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+            _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+        }
+    }
+
+    // Runs the command in the variable `name`, if there is one: the one
+    // in PROMPT_COMMAND before each prompt, and the one in PREEXEC_COMMAND
+    // before each command line typed, with the line as `$1`. `$?` is left
+    // as it was, for the prompt or the command line to see.
+    fn run_hook(&mut self, name: &str, args: Option<&[String]>) {
+        let Some(command) = self.parameter(name).filter(|command| !command.trim().is_empty()) else {
+            return;
+        };
+        let status = self.last_status;
+        let positional = args.map(|args| std::mem::replace(&mut self.positional, args.to_vec()));
+        self.eval(&command);
+        if let Some(positional) = positional {
+            self.positional = positional;
+        }
+        self.last_status = status;
+    }
+
+    // Reports `error` and returns the exit status it gives.
+    fn report(&self, error: &ShellError) -> i32 {
+        let _ = writeln!(self.streams.writer(2), "shellob: {}", error);
+        error.status()
+    }
+
+    // Records why expanding the command being run failed, so it isn't
+    // run. Only the first error is kept.
+    fn fail_expansion(&self, error: ShellError) {
+        let first = self.expansion_error.take().unwrap_or(error);
+        self.expansion_error.set(Some(first));
+    }
+
+    // Puts the shell in its own process group in the foreground of the
+    // terminal, waiting to be foregrounded first if started in the
+    // background. Must run before the job-control signals are ignored.
+    fn take_terminal(&mut self) {
+        loop {
+            match sys::tcgetpgrp(0) {
+                Ok(pgrp) if pgrp == sys::getpgrp() => break,
+                Ok(_) => {
+                    let _ = sys::kill(-sys::getpgrp(), sys::SIGTTIN);
+                }
+                Err(_) => {
+                    self.job_control = false;
+                    return;
+                }
+            }
+        }
+
+        // Fails harmlessly if we already lead a session
+        let _ = sys::setpgid(0, 0);
+        if sys::tcsetpgrp(0, sys::getpid()).is_err() {
+            self.job_control = false;
+            return;
+        }
+        self.tmodes = sys::tcgetattr(0).ok();
+    }
+
+    /// Exits the process with status `code`, after running
+    /// `~/.shellob_logout` if this is a login shell and trimming the
+    /// history file.
+    pub fn exit(&mut self, code: i32) -> ! {
+        let code = self.run_exit_trap(code);
+        // Cleared first so an `exit` in the logout file doesn't run it
+        // again
+        if std::mem::take(&mut self.login) {
+            if let Some(home) = self.parameter("HOME") {
+                self.source_file(&Path::new(&home).join(".shellob_logout"));
+            }
+        }
+        let _ = self.history.truncate_file(self.history_file_size());
+        self.jobs.hang_up_stopped();
+        output::flush();
+        std::process::exit(code)
+    }
+
+    /// Makes this a login shell: sets SHELL to this shell and has
+    /// `~/.shellob_logout` run when the shell exits.
+    pub fn login(&mut self) {
+        self.login = true;
+        if let Ok(exe) = env::current_exe() {
+            self.export_variable("SHELL", &exe.to_string_lossy());
+        }
+    }
+
+    /// Runs `/etc/profile` and `~/.profile`, as a login shell does when
+    /// it starts.
+    pub fn source_profile(&mut self) {
+        self.source_file(Path::new("/etc/profile"));
+        if let Some(home) = self.parameter("HOME") {
+            self.source_file(&Path::new(&home).join(".profile"));
+        }
+    }
+
+    /// Runs `~/.shellobrc`, as an interactive shell does when it starts.
+    pub fn source_rc(&mut self) {
+        if let Some(home) = self.parameter("HOME") {
+            self.source_file(&Path::new(&home).join(".shellobrc"));
+        }
+    }
+
+    // Runs the commands in `file` as if they were typed at the prompt. A
+    // file that doesn't exist is skipped quietly.
+    fn source_file(&mut self, file: &Path) {
+        match fs::read_to_string(file) {
+            Ok(script) => {
+                self.run_sourced(&script);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => {
+                self.report(&ShellError::File { path: file.display().to_string(), source });
+            }
+        }
+    }
+
+    // Runs `script`, read from a sourced file, where `return` ends it
+    // early. An `exit` is left set for whatever is running `source`.
+    fn run_sourced(&mut self, script: &str) -> i32 {
+        self.sourcing += 1;
+        let status = self.eval(script);
+        self.sourcing -= 1;
+        if self.flow == Some(Flow::Return) {
+            self.flow = None;
+        }
+        status.code()
+    }
+
+    /// What `--version` and the `version` builtin print: the version and
+    /// target, then the optional features, each marked `+` if this build
+    /// has it and `-` if not.
+    pub fn version_info() -> String {
+        let features: Vec<String> = FEATURES
+            .iter()
+            .map(|&(name, enabled)| format!("{}{}", if enabled { '+' } else { '-' }, name))
+            .collect();
+        format!(
+            "shellob {} ({}-{})\nfeatures: {}",
+            env!("CARGO_PKG_VERSION"),
+            env::consts::ARCH,
+            env::consts::OS,
+            features.join(" ")
+        )
+    }
+
+    // The working directory to start with: $PWD if it is an absolute path
+    // to the current directory, so the symlinks it went through are kept,
+    // or else the physical path.
+    fn initial_cwd() -> PathBuf {
+        let physical = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        let Ok(pwd) = env::var("PWD") else {
+            return physical;
+        };
+        let pwd = PathBuf::from(pwd);
+        let plain = pwd.is_absolute() && pwd.components().all(|c| matches!(c, Component::RootDir | Component::Normal(_)));
+        let same = |a: &Path, b: &Path| match (fs::metadata(a), fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        };
+        if plain && same(&pwd, &physical) {
+            pwd
+        } else {
+            physical
+        }
+    }
+
+    // The path `dir` leads to from the logical working directory, with
+    // `.` and `..` resolved textually: `..` after a symlink goes back to
+    // the directory holding the link, not to the parent of its target.
+    fn logical_path(&self, dir: &Path) -> PathBuf {
+        let mut path = self.cwd.clone();
+        for component in dir.components() {
+            match component {
+                Component::RootDir => path = PathBuf::from("/"),
+                Component::ParentDir => {
+                    path.pop();
+                }
+                Component::Normal(name) => path.push(name),
+                Component::CurDir | Component::Prefix(_) => {}
+            }
+        }
+        path
+    }
+
+    // The first directory `dir` names in one of the CDPATH entries, where
+    // an empty entry means the working directory. Paths that are absolute
+    // or start with `.` or `..` aren't searched for. Entries are checked
+    // like PATH's, except for the working directory itself, which cd
+    // falls back to anyway.
+    fn search_cdpath(&self, dir: &str) -> Option<String> {
+        let cdpath = self.parameter("CDPATH")?;
+        let first = Path::new(dir).components().next()?;
+        if !matches!(first, Component::Normal(_)) {
+            return None;
+        }
+        cdpath.split(':').find_map(|entry| {
+            let path = match entry {
+                "" => dir.to_string(),
+                _ => format!("{}/{}", entry.trim_end_matches('/'), dir),
+            };
+            if !self.logical_path(Path::new(&path)).is_dir() {
+                return None;
+            }
+            let trusted = matches!(entry, "" | "." | "./")
+                || self.trust_entry("CDPATH", entry, &self.logical_path(Path::new(entry)), &path);
+            trusted.then_some(path)
+        })
+    }
+
+    // The directory stack as `dirs` shows it, with the home directory
+    // abbreviated to `~` unless `long`.
+    fn dirs(&self, long: bool) -> Vec<String> {
+        let home = if long { None } else { self.parameter("HOME") };
+        self.dir_stack.entries(&self.cwd).map(|dir| dirs::abbreviate(dir, home.as_deref())).collect()
+    }
+
+    // Changes the working directory to `dir`, logically unless
+    // `physical`. Like bash, a logical path that doesn't work falls back
+    // to `dir` as it is, after which the working directory is the
+    // physical one.
+    fn change_dir(&mut self, dir: impl AsRef<Path>, physical: bool) -> io::Result<()> {
+        let logical = self.logical_path(dir.as_ref());
+        let cwd = if !physical && env::set_current_dir(&logical).is_ok() {
+            logical
+        } else {
+            env::set_current_dir(dir)?;
+            env::current_dir()?
+        };
+        self.export_variable("PWD", &cwd.to_string_lossy());
+        let old = std::mem::replace(&mut self.cwd, cwd);
+        self.export_variable("OLDPWD", &old.to_string_lossy());
+        self.record_visit();
+        Ok(())
+    }
+
+    // The file history is kept in: HISTFILE, or `~/.shellob_history` if
+    // it isn't set. An empty HISTFILE keeps history from being saved.
+    fn history_file(&self) -> Option<PathBuf> {
+        match self.parameter("HISTFILE") {
+            Some(file) if file.is_empty() => None,
+            Some(file) => Some(PathBuf::from(file)),
+            None => Some(Path::new(&self.parameter("HOME")?).join(".shellob_history")),
+        }
+    }
+
+    // How many history entries to keep in memory: HISTSIZE, or 500.
+    fn history_size(&self) -> usize {
+        self.parameter("HISTSIZE").and_then(|size| size.parse().ok()).unwrap_or(500)
+    }
+
+    // Which lines are left out of history, from HISTCONTROL.
+    fn history_control(&self) -> history::Control {
+        self.parameter("HISTCONTROL").map(|value| history::Control::parse(&value)).unwrap_or_default()
+    }
+
+    // `fc` lists (`-l`), or edits and reruns, a range of history entries
+    // given as numbers, negative offsets or prefixes. `fc -s old=new` (or
+    // `-e -`) reruns an entry with `old` replaced by `new` and no editor.
+    // Entries are edited with `-e`'s editor, FCEDIT, EDITOR or vi. Like
+    // bash, the `fc` line itself is replaced in history by what it runs.
+    fn fix_command(&mut self, args: &[String], out: &mut dyn Write) -> Result<i32, ShellError> {
+        let (mut list, mut numbers, mut reverse, mut editor) = (false, true, false, None);
+        let mut args = args.iter().peekable();
+        while let Some(arg) = args.next_if(|arg| arg.starts_with('-') && !arg[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            if arg == "--" {
+                break;
+            }
+            for flag in arg[1..].chars() {
+                match flag {
+                    'l' => list = true,
+                    'n' => numbers = false,
+                    'r' => reverse = true,
+                    's' => editor = Some("-".to_string()),
+                    'e' => {
+                        let name = args.next().ok_or_else(|| ShellError::usage("fc", "-e: option requires an argument"))?;
+                        editor = Some(name.clone());
+                    }
+                    _ => return Err(ShellError::usage("fc", format!("-{}: invalid option", flag))),
+                }
+            }
+        }
+        let mut operands: Vec<&String> = args.collect();
+
+        // The `fc` line being run isn't one of the entries it works on
+        let mut entries = self.history.entries().to_vec();
+        let typed = entries.last().is_some_and(|line| line.split_whitespace().next() == Some("fc"));
+        if typed {
+            entries.pop();
+        }
+        let find = |spec: &str| -> Result<usize, ShellError> {
+            let found = match spec.parse::<isize>() {
+                Ok(n) if n < 0 => entries.len().checked_sub(n.unsigned_abs()),
+                Ok(n) => (n as usize).checked_sub(1).filter(|&index| index < entries.len()),
+                Err(_) => entries.iter().rposition(|entry| entry.starts_with(spec)),
+            };
+            found.ok_or_else(|| ShellError::builtin("fc", format!("{}: history specification out of range", spec)))
+        };
+        let newest = entries.len().checked_sub(1).ok_or_else(|| ShellError::builtin("fc", "no command found"))?;
+
+        let commands = if editor.as_deref() == Some("-") && !list {
+            let substitution = operands.first().and_then(|operand| operand.split_once('=')).map(|(old, new)| (old.to_string(), new.to_string()));
+            if substitution.is_some() {
+                operands.remove(0);
+            }
+            let mut command = match operands.first() {
+                Some(spec) => entries[find(spec)?].clone(),
+                None => entries[newest].clone(),
+            };
+            if let Some((old, new)) = substitution.filter(|(old, _)| !old.is_empty()) {
+                command = command.replace(&old, &new);
+            }
+            command
+        } else {
+            let default_first = if list { newest.saturating_sub(15) } else { newest };
+            let first = operands.first().map(|spec| find(spec)).transpose()?.unwrap_or(default_first);
+            let last = match operands.get(1) {
+                Some(spec) => find(spec)?,
+                None if list => newest,
+                None => first,
+            };
+            let mut range: Vec<usize> = (first.min(last)..=first.max(last)).collect();
+            if reverse != (first > last) {
+                range.reverse();
+            }
+            if list {
+                for index in range {
+                    let _ = match numbers {
+                        true => writeln!(out, "{}\t{}", index + 1, entries[index]),
+                        false => writeln!(out, "\t{}", entries[index]),
+                    };
+                }
+                return Ok(0);
+            }
+            let lines: Vec<&str> = range.iter().map(|&index| entries[index].as_str()).collect();
+            let editor = editor
+                .or_else(|| self.parameter("FCEDIT"))
+                .or_else(|| self.parameter("EDITOR"))
+                .unwrap_or_else(|| "vi".to_string());
+            self.edit_commands(&lines.join("\n"), &editor)?
+        };
+
+        if typed {
+            self.history.delete(-1);
+        }
+        let command = commands.trim_end();
+        if command.is_empty() {
+            return Ok(0);
+        }
+        let _ = writeln!(out, "{}", command);
+        let _ = out.flush();
+        self.history.add(command, self.history_size(), self.history_control());
+        Ok(self.eval(command).code())
+    }
+
+    // Opens `commands` in `editor` for `fc` and returns what was saved.
+    fn edit_commands(&mut self, commands: &str, editor: &str) -> Result<String, ShellError> {
+        let file = env::temp_dir().join(format!("shellob-fc-{}", sys::getpid()));
+        fs::write(&file, format!("{}\n", commands)).map_err(|e| ShellError::builtin("fc", e))?;
+        let status = self.eval(&format!("{} '{}'", editor, file.display().to_string().replace('\'', "'\\''")));
+        let edited = fs::read_to_string(&file);
+        let _ = fs::remove_file(&file);
+        match (status.code(), edited) {
+            (0, Ok(edited)) => Ok(edited),
+            (0, Err(e)) => Err(ShellError::builtin("fc", e)),
+            (code, _) => Err(ShellError::builtin("fc", format!("{}: exited with status {}", editor, code))),
+        }
+    }
+
+    // `bind '"keyseq": function'` binds a key in the line editor to one
+    // of its functions, `bind -x '"keyseq": command'` to a command and
+    // `bind -r keyseq` to nothing. `-l` lists the functions, `-p` the keys
+    // bound to them, `-X` the keys bound to commands and `-q function` the
+    // keys that do it. Keys are bound in the keymap of the editing mode
+    // the shell is in, or the one named with `-m`.
+    fn bind(&mut self, args: &[String], out: &mut dyn Write) -> Result<i32, ShellError> {
+        let mut keymap = if self.options.vi { Keymap::ViInsert } else { Keymap::Emacs };
+        let function = |name: &str| {
+            keymap::FUNCTIONS
+                .iter()
+                .find(|(function, _)| *function == name)
+                .map(|(_, action)| *action)
+                .ok_or_else(|| ShellError::builtin("bind", format!("{}: unknown function name", name)))
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut argument = || args.next().ok_or_else(|| ShellError::usage("bind", format!("{}: option requires an argument", arg)));
+            match arg.as_str() {
+                "-m" => {
+                    let name = argument()?;
+                    keymap = Keymap::named(name).ok_or_else(|| ShellError::builtin("bind", format!("{}: invalid keymap name", name)))?;
+                }
+                "-l" => {
+                    for (name, _) in keymap::FUNCTIONS {
+                        let _ = writeln!(out, "{}", name);
+                    }
+                }
+                "-p" | "-X" => {
+                    for (key, binding) in self.bindings.list(keymap) {
+                        let key = keymap::key_sequence(key);
+                        let _ = match binding {
+                            Binding::Action(action) if arg == "-p" => writeln!(out, "\"{}\": {}", key, keymap::function_name(action)),
+                            Binding::Command(command) if arg == "-X" => writeln!(out, "\"{}\": \"{}\"", key, command),
+                            _ => Ok(()),
+                        };
+                    }
+                }
+                "-q" => {
+                    let name = argument()?;
+                    let action = function(name)?;
+                    let keys: Vec<String> = self
+                        .bindings
+                        .list(keymap)
+                        .into_iter()
+                        .filter(|(_, binding)| *binding == Binding::Action(action))
+                        .map(|(key, _)| format!("\"{}\"", keymap::key_sequence(key)))
+                        .collect();
+                    let _ = match keys.is_empty() {
+                        true => writeln!(out, "{} is not bound to any keys.", name),
+                        false => writeln!(out, "{} can be invoked via {}.", name, keys.join(", ")),
+                    };
+                }
+                "-r" => {
+                    let sequence = argument()?;
+                    let sequence = sequence.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(sequence);
+                    let key = keymap::parse_key(sequence).map_err(|e| ShellError::builtin("bind", e))?;
+                    self.bindings.bind(keymap, key, Binding::Unbound);
+                }
+                "-x" => {
+                    let (key, command) = keymap::parse_binding(argument()?).map_err(|e| ShellError::builtin("bind", e))?;
+                    self.bindings.bind(keymap, key, Binding::Command(command.to_string()));
+                }
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(ShellError::usage("bind", format!("{}: invalid option", flag)));
+                }
+                text => {
+                    let (key, name) = keymap::parse_binding(text).map_err(|e| ShellError::builtin("bind", e))?;
+                    self.bindings.bind(keymap, key, Binding::Action(function(name)?));
+                }
+            }
+        }
+        Ok(0)
+    }
+
+    // `trap command signal...` runs `command` when one of the signals
+    // arrives, and for EXIT (or 0) when the shell exits. An empty command
+    // ignores the signals, and `-` puts back what the shell does without
+    // a trap, as does a signal number, or a lone signal, in place of the
+    // command. `-p` prints the traps set, for the signals named or else
+    // all of them, as the commands that would set them again, and so does
+    // `trap` on its own. `-l` lists the signals.
+    fn trap(&mut self, args: &[String], out: &mut dyn Write) -> Result<i32, ShellError> {
+        let (print, args) = match args.first().map(String::as_str) {
+            Some("-l") => {
+                for (i, name) in signals::NAMES.iter().enumerate() {
+                    let _ = writeln!(out, "{:2}) SIG{}", i + 1, name);
+                }
+                return Ok(0);
+            }
+            Some("-p") => (true, &args[1..]),
+            Some("--") => (false, &args[1..]),
+            Some(flag) if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(ShellError::usage("trap", format!("{}: invalid option", flag)));
+            }
+            Some(_) => (false, args),
+            None => (true, args),
+        };
+        let print = print || args.is_empty();
+        let name = |sig: i32| if sig == 0 { "EXIT".to_string() } else { signals::name(sig) };
+        let number = |spec: &str| match spec.eq_ignore_ascii_case("EXIT") || spec == "0" {
+            true => Ok(0),
+            false => signals::number(spec).ok_or_else(|| ShellError::builtin("trap", format!("{}: invalid signal specification", spec))),
+        };
+        let mut status = 0;
+
+        if print {
+            let sigs: Vec<i32> = match args.is_empty() {
+                true => self.traps.keys().copied().collect(),
+                false => args.iter().filter_map(|spec| number(spec).map_err(|e| status = self.report(&e)).ok()).collect(),
+            };
+            for sig in sigs {
+                if let Some(command) = self.traps.get(&sig) {
+                    let _ = writeln!(out, "trap -- '{}' {}", command.replace('\'', "'\\''"), name(sig));
+                }
+            }
+            return Ok(status);
+        }
+
+        let (command, specs) = match args {
+            [] | [_] => (None, args),
+            [first, rest @ ..] if first == "-" => (None, rest),
+            [first, ..] if first.parse::<u32>().is_ok() => (None, args),
+            [first, rest @ ..] => (Some(first), rest),
+        };
+        for spec in specs {
+            let sig = match number(spec) {
+                Ok(sig) => sig,
+                Err(e) => {
+                    status = self.report(&e);
+                    continue;
+                }
+            };
+            let trap = match command {
+                None => signals::Trap::Reset,
+                Some(command) if command.is_empty() => signals::Trap::Ignore,
+                Some(_) => signals::Trap::Catch,
+            };
+            if sig != 0 {
+                if let Err(e) = signals::trap(sig, trap) {
+                    status = self.report(&ShellError::builtin("trap", format!("{}: {}", spec, e)));
+                    continue;
+                }
+            }
+            match command {
+                Some(command) => self.traps.insert(sig, command.clone()),
+                None => self.traps.remove(&sig),
+            };
+        }
+        Ok(status)
+    }
+
+    // Runs the traps for the signals that arrived since the last time.
+    // `$?` is left as it was.
+    fn run_traps(&mut self) {
+        for sig in signals::take_trapped() {
+            let Some(command) = self.traps.get(&sig).filter(|command| !command.is_empty()).cloned() else {
+                continue;
+            };
+            let status = self.last_status;
+            self.eval(&command);
+            self.last_status = status;
+        }
+    }
+
+    // Runs the EXIT trap, if there is one, as the shell exits with
+    // `status`. It only runs once, and an `exit` in it changes the status.
+    fn run_exit_trap(&mut self, status: i32) -> i32 {
+        let Some(command) = self.traps.remove(&0) else {
+            return status;
+        };
+        self.last_status = status;
+        self.flow = None;
+        self.eval(&command);
+        match self.flow.take() {
+            Some(Flow::Exit(code)) => code,
+            _ => status,
+        }
+    }
+
+    // How many history entries to keep in the file: HISTFILESIZE, or as
+    // many as in memory.
+    fn history_file_size(&self) -> usize {
+        self.parameter("HISTFILESIZE").and_then(|size| size.parse().ok()).unwrap_or_else(|| self.history_size())
+    }
+
+    // Where the directories `cd` visits are kept for `j`: SHELLOB_JUMP_DB
+    // if set, or else under the XDG data directory.
+    fn frecency_file(&self) -> Option<PathBuf> {
+        if let Some(file) = self.parameter("SHELLOB_JUMP_DB").filter(|file| !file.is_empty()) {
+            return Some(PathBuf::from(file));
+        }
+        let data = match self.parameter("XDG_DATA_HOME").filter(|dir| Path::new(dir).is_absolute()) {
+            Some(dir) => PathBuf::from(dir),
+            None => Path::new(&self.parameter("HOME")?).join(".local/share"),
+        };
+        Some(data.join("shellob/dirs"))
+    }
+
+    // Counts a visit to the working directory in the `j` database. Not
+    // being able to save it shouldn't get in the way of `cd`, so errors
+    // are ignored.
+    fn record_visit(&self) {
+        let Some(file) = self.frecency_file() else {
+            return;
+        };
+        if !frecency::tracked(&self.cwd, self.parameter("HOME").as_deref()) {
+            return;
+        }
+        let mut database = frecency::Database::open(file);
+        database.visit(&self.cwd.to_string_lossy());
+        let _ = database.save();
+    }
+
+    // Relative PATH entries (including the empty entry, which means ".")
+    // are resolved against the current directory at lookup time. Since they
+    // let whoever controls the cwd shadow real commands, SHELLOB_PATHCHECK
+    // can be set to "warn" or "refuse" to flag commands found through a
+    // relative or world-writable directory. A name with a slash in it
+    // isn't searched for, or checked: it is the path to the command.
+    fn find_in_path(&self, command: &str) -> Option<String> {
+        if command.contains('/') {
+            return Path::new(command).is_file().then(|| command.to_string());
+        }
+        let cwd = env::current_dir().ok();
+
+        for entry in self.parameter("PATH")?.split(':') {
+            let dir = if entry.is_empty() { "." } else { entry };
+            let dir = match (&cwd, Path::new(dir).is_absolute()) {
+                (Some(cwd), false) => cwd.join(dir),
+                (None, false) => continue,
+                (_, true) => PathBuf::from(dir),
+            };
+
+            let path = dir.join(command);
+            if !path.is_file() {
+                continue;
+            }
+            let path = path.to_string_lossy().into_owned();
+            if self.trust_entry("PATH", entry, &dir, &path) {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    // Whether `path`, found through `entry` of `variable`, may be used.
+    // `dir` is the directory the entry names. One found through a
+    // relative or world-writable entry is reported if SHELLOB_PATHCHECK
+    // is "warn" or "refuse", and with "refuse" it is passed over so later
+    // entries get a chance.
+    fn trust_entry(&self, variable: &'static str, entry: &str, dir: &Path, path: &str) -> bool {
+        let check = self.parameter("SHELLOB_PATHCHECK").unwrap_or_default();
+        if check != "warn" && check != "refuse" {
+            return true;
+        }
+        let relative = !Path::new(entry).is_absolute();
+        let world_writable = fs::metadata(dir)
+            .map(|m| m.permissions().mode() & 0o002 != 0)
+            .unwrap_or(false);
+        if !relative && !world_writable {
+            return true;
+        }
+        let reason = if relative { "relative" } else { "world-writable" };
+        self.report(&ShellError::UntrustedEntry {
+            path: path.to_string(),
+            variable,
+            entry: entry.to_string(),
+            reason,
+        });
+        check != "refuse"
+    }
+
+    // Looks up a parameter by name: a special parameter, a variable the
+    // shell works out each time it's expanded, or a shell variable.
+    fn parameter(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "0" => Some(self.name.clone()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" => Some(self.positional.join(" ")),
+            // Joined with the first character of IFS, or nothing if it's
+            // set but empty
+            "*" => {
+                let separator = match self.parameter("IFS") {
+                    Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+                    None => " ".to_string(),
+                };
+                Some(self.positional.join(&separator))
+            }
+            n if n.starts_with(|c: char| c.is_ascii_digit()) => {
+                let n: usize = n.parse().ok()?;
+                self.positional.get(n.checked_sub(1)?).cloned()
+            }
+            "SECONDS" => {
+                let (set, value) = self.seconds;
+                Some((value + set.elapsed().as_secs()).to_string())
+            }
+            "RANDOM" => Some(self.random().to_string()),
+            "LINENO" => Some(self.line_number.to_string()),
+            _ => self.variables.get(name)?.value.clone(),
+        }
+    }
+
+    // Sets a shell variable, keeping it exported if it already was.
+    // Setting SECONDS starts it counting from the value given instead,
+    // and setting RANDOM seeds the numbers it gives.
+    fn set_variable(&mut self, name: &str, value: &str) {
+        match name {
+            "SECONDS" => self.seconds = (Instant::now(), value.parse().unwrap_or(0)),
+            "RANDOM" => self.random.set(value.parse().unwrap_or(0)),
+            _ => {}
+        }
+        if matches!(name, "SECONDS" | "RANDOM") {
+            return;
+        }
+        self.variables
+            .entry(name.to_string())
+            .or_insert(Variable { value: None, exported: false })
+            .value = Some(value.to_string());
+    }
+
+    // The next number from 0 to 32767 for `$RANDOM`, from a linear
+    // congruential generator. Good enough for scripts, and the same
+    // numbers every time for the same seed.
+    fn random(&self) -> u16 {
+        let state = self.random.get().wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        self.random.set(state);
+        (state >> 33) as u16 & 0x7fff
+    }
+
+    // A seed for `$RANDOM` that differs from one shell, and one moment,
+    // to the next.
+    fn random_seed() -> u64 {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+        nanos ^ (sys::getpid() as u64) << 32
+    }
+
+    // Sets a shell variable and exports it, for the variables the shell
+    // keeps up to date itself.
+    fn export_variable(&mut self, name: &str, value: &str) {
+        self.variables.insert(name.to_string(), Variable { value: Some(value.to_string()), exported: true });
+    }
+
+    // The exported variables that have a value, as passed to children.
+    fn environment(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.variables
+            .iter()
+            .filter(|(_, variable)| variable.exported)
+            .filter_map(|(name, variable)| Some((name, variable.value.as_ref()?)))
+    }
+
+    fn is_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    // Recognizes a `NAME=value` word.
+    fn assignment(word: &str) -> Option<(&str, &str)> {
+        word.split_once('=').filter(|(name, _)| Shell::is_name(name))
+    }
+
+    // Expands the `$NAME`, `${...}` or special parameter such as `$?`
+    // whose `$` was just read, leaving `chars` past it. Unset parameters
+    // expand to nothing, and a `$` that doesn't start a parameter stands
+    // for itself.
+    fn expand_parameter(&mut self, chars: &mut Peekable<Chars>) -> String {
+        let name = match chars.peek().copied() {
+            Some(c) if expand::is_special(c) => {
+                chars.next();
+                c.to_string()
+            }
+            Some('(') => {
+                chars.next();
+                let command = lexer::read_substitution(chars);
+                return self.substitute(&command);
+            }
+            Some('{') if lexer::starts_group_substitution(chars) => {
+                chars.next();
+                let command = lexer::read_group_substitution(chars);
+                return self.substitute_here(&command);
+            }
+            Some('{') => {
+                chars.next();
+                // The body may itself contain `${...}`
+                let mut body = String::new();
+                let mut depth = 0;
+                for c in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 0 => break,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    body.push(c);
+                }
+                return expand::parameter(&body, self).unwrap_or_else(|e| {
+                    self.fail_expansion(e);
+                    String::new()
+                });
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                name
+            }
+            _ => return "$".to_string(),
+        };
+        self.parameter(&name).unwrap_or_default()
+    }
+
+    fn assignment_prefix(word: &str) -> bool {
+        word.strip_suffix('=').is_some_and(Shell::is_name)
+    }
+
+    // Expands the `~` or `~user` whose `~` was just read, leaving `chars`
+    // past it. A directory named with `hash -d` takes precedence over a
+    // user's home. A user that doesn't exist, or a `~` with no HOME set,
+    // is left as it is.
+    fn expand_tilde(&self, chars: &mut Peekable<Chars>) -> String {
+        let mut user = String::new();
+        while let Some(&c) = chars.peek() {
+            if !c.is_ascii_alphanumeric() && !"._-".contains(c) {
+                break;
+            }
+            user.push(c);
+            chars.next();
+        }
+        let home = match user.as_str() {
+            "" => self.parameter("HOME"),
+            user => self.named_dirs.get(user).cloned().or_else(|| sys::home_dir(user)),
+        };
+        home.unwrap_or_else(|| format!("~{}", user))
+    }
+
+    // Reads the command of a backquoted substitution whose opening
+    // backquote was just read. Inside, a backslash only escapes `$`, a
+    // backquote or another backslash.
+    fn read_backquoted(chars: &mut Peekable<Chars>) -> String {
+        let mut command = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '`' => break,
+                '\\' if matches!(chars.peek(), Some('$' | '`' | '\\')) => command.extend(chars.next()),
+                c => command.push(c),
+            }
+        }
+        command
+    }
+
+    // A copy of the shell to run a command substitution in. It has no
+    // jobs and leaves job control to this shell.
+    fn subshell(&self) -> Shell {
+        Shell {
+            commands: self.commands.clone(),
+            options: self.options.clone(),
+            variables: self.variables.clone(),
+            jobs: Jobs::default(),
+            job_control: false,
+            tmodes: None,
+            last_status: self.last_status,
+            pid: self.pid,
+            last_background: self.last_background,
+            name: self.name.clone(),
+            positional: self.positional.clone(),
+            cwd: self.cwd.clone(),
+            dir_stack: self.dir_stack.clone(),
+            named_dirs: self.named_dirs.clone(),
+            login: false,
+            interactive: false,
+            history: self.history.detached(),
+            bindings: self.bindings.clone(),
+            substitution_status: Cell::new(None),
+            expansion_error: Cell::new(None),
+            process_substitutions: RefCell::new(Vec::new()),
+            streams: FdTable::default(),
+            stdout: None,
+            stderr: None,
+            flow: None,
+            sourcing: self.sourcing,
+            seconds: self.seconds,
+            // Not the same numbers as this shell will go on to draw
+            random: Cell::new(Shell::random_seed()),
+            lines_read: self.lines_read,
+            line_number: self.line_number,
+            // Signals ignored stay ignored; the others are caught by this
+            // shell, not the subshell
+            traps: self.traps.iter().filter(|(_, command)| command.is_empty()).map(|(&sig, command)| (sig, command.clone())).collect(),
+        }
+    }
+
+    // Forks a subshell to run `command` with `file` as its descriptor
+    // `fd`, keeping `other` (the far end of the pipe `file` is on) out of
+    // the child. Returns the child's pid.
+    fn fork_subshell(&self, command: &str, fd: i32, file: fs::File, other: &fs::File) -> Result<i32, ShellError> {
+        output::flush();
+        match sys::fork() {
+            Ok(0) => {
+                let _ = sys::close(other.as_raw_fd());
+                self.process_substitutions.borrow_mut().clear();
+                let mut shell = self.subshell();
+                let _ = signals::restore_defaults();
+                // The files standing in for this shell's descriptors take
+                // their place in the child, where they are its own
+                let installed = self.streams.install_here().and_then(|_| sys::dup2(file.as_raw_fd(), fd));
+                let status = match installed {
+                    Ok(_) => {
+                        drop(file);
+                        shell.handle_command(command.trim(), VecDeque::new());
+                        shell.run_exit_trap(shell.last_status)
+                    }
+                    Err(source) => shell.report(&ShellError::System { call: "dup2", source }),
+                };
+                output::flush();
+                std::process::exit(status);
+            }
+            Ok(pid) => Ok(pid),
+            Err(source) => Err(ShellError::System { call: "fork", source }),
+        }
+    }
+
+    // Runs `command` in a subshell and returns what it wrote to stdout,
+    // less any trailing newlines.
+    fn substitute(&self, command: &str) -> String {
+        let started = sys::pipe()
+            .map_err(|source| ShellError::System { call: "pipe", source })
+            .and_then(|(reader, writer)| Ok((self.fork_subshell(command, 1, writer, &reader)?, reader)));
+        let (pid, mut reader) = match started {
+            Ok(started) => started,
+            Err(e) => {
+                self.fail_expansion(e);
+                return String::new();
+            }
+        };
+
+        let mut output = Vec::new();
+        let _ = reader.read_to_end(&mut output);
+        let status = match sys::waitpid(pid, 0) {
+            Ok(Some(WaitStatus::Exited(code))) => code,
+            Ok(Some(WaitStatus::Signaled(sig))) => 128 + sig,
+            _ => 1,
+        };
+        self.substitution_status.set(Some(status));
+
+        let mut output = String::from_utf8_lossy(&output).into_owned();
+        expand::strip_trailing_newlines(&mut output);
+        output
+    }
+
+    // Runs `command` in this shell rather than a copy of it, so what it
+    // sets stays set, and returns what it wrote to stdout less any
+    // trailing newlines: `${ command; }`. Its output is collected in a
+    // temporary file, since nothing else would read a pipe while it runs.
+    fn substitute_here(&mut self, command: &str) -> String {
+        let file = match redirect::temp_file().and_then(|file| Ok((file.try_clone()?, file))) {
+            Ok(files) => files,
+            Err(source) => {
+                self.fail_expansion(ShellError::System { call: "open", source });
+                return String::new();
+            }
+        };
+        let (mut reader, writer) = file;
+        let mut fds = FdTable::default();
+        fds.insert(1, writer);
+        let saved = self.streams.overlay(fds);
+        // The command being expanded keeps its own expansion error, if it
+        // has one, rather than the commands run here taking it
+        let error = self.expansion_error.take();
+        self.handle_command(command.trim(), VecDeque::new());
+        self.expansion_error.set(error);
+        self.streams.restore(saved);
+        self.substitution_status.set(Some(self.last_status));
+
+        let mut output = Vec::new();
+        let _ = reader.rewind().and_then(|_| reader.read_to_end(&mut output));
+        let mut output = String::from_utf8_lossy(&output).into_owned();
+        expand::strip_trailing_newlines(&mut output);
+        output
+    }
+
+    // Starts `<(command)` or `>(command)`, whose `(` was just read,
+    // returning the `/dev/fd` path the command's output can be read from
+    // or its input written to.
+    fn substitute_process(&self, direction: char, chars: &mut Peekable<Chars>) -> String {
+        let command = lexer::read_substitution(chars);
+        let (reader, writer) = match sys::pipe() {
+            Ok(pipe) => pipe,
+            Err(source) => {
+                self.fail_expansion(ShellError::System { call: "pipe", source });
+                return String::new();
+            }
+        };
+        let (ours, theirs, fd) = match direction {
+            '<' => (reader, writer, 1),
+            _ => (writer, reader, 0),
+        };
+        let pid = match self.fork_subshell(&command, fd, theirs, &ours) {
+            Ok(pid) => pid,
+            Err(e) => {
+                self.fail_expansion(e);
+                return String::new();
+            }
+        };
+
+        // The command the path is passed to has to inherit our end
+        let raw = ours.as_raw_fd();
+        let _ = sys::set_cloexec(raw, false);
+        self.process_substitutions.borrow_mut().push((ours, pid));
+        format!("/dev/fd/{}", raw)
+    }
+
+    // Closes the process substitutions opened since there were `keep`,
+    // and waits for their commands.
+    fn finish_process_substitutions(&self, keep: usize) {
+        let finished: Vec<_> = self.process_substitutions.borrow_mut().drain(keep..).collect();
+        for (file, pid) in finished {
+            drop(file);
+            let _ = sys::waitpid(pid, 0);
+        }
+    }
+
+    // Expands the word an expansion operator takes, such as the default
+    // in `${VAR:-word}`: parameters are expanded and quotes removed, but
+    // the word is never split.
+    fn expand_word(&mut self, word: &str) -> String {
+        let mut result = String::new();
+        let mut chars = word.chars().peekable();
+        let mut quote = None;
+        while let Some(c) = chars.next() {
+            match (c, quote) {
+                ('\'', None) | ('"', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                ('\\', q) if q != Some('\'') => result.extend(chars.next()),
+                ('$', q) if q != Some('\'') => result.push_str(&self.expand_parameter(&mut chars)),
+                ('`', q) if q != Some('\'') => {
+                    result.push_str(&self.substitute(&Shell::read_backquoted(&mut chars)));
+                }
+                (c, _) => result.push(c),
+            }
+        }
+        result
+    }
+
+    // Expands the body of a here-document whose delimiter wasn't quoted.
+    // Parameters and commands are expanded as inside double quotes, but
+    // quotes stand for themselves and a backslash only escapes `$`, a
+    // backquote, another backslash or a newline.
+    fn expand_heredoc(&mut self, body: &str) -> String {
+        let mut result = String::new();
+        let mut chars = body.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if matches!(chars.peek(), Some('$' | '`' | '\\')) => result.extend(chars.next()),
+                '\\' if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                '$' => result.push_str(&self.expand_parameter(&mut chars)),
+                '`' => result.push_str(&self.substitute(&Shell::read_backquoted(&mut chars))),
+                c => result.push(c),
+            }
+        }
+        result
+    }
+
+    // Expands a `case` pattern like `expand_word`, but with the
+    // characters that were quoted escaped, so they only match themselves.
+    fn expand_pattern(&mut self, word: &str) -> String {
+        let literal = |result: &mut String, text: &str| {
+            for c in text.chars() {
+                if "*?[]\\".contains(c) {
+                    result.push('\\');
+                }
+                result.push(c);
+            }
+        };
+        let mut result = String::new();
+        let mut chars = word.chars().peekable();
+        let mut quote = None;
+        while let Some(c) = chars.next() {
+            match (c, quote) {
+                ('\'', None) | ('"', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                ('\\', q) if q != Some('\'') => literal(&mut result, &chars.next().map(String::from).unwrap_or_default()),
+                ('$' | '`', q) if q != Some('\'') => {
+                    let value = match c {
+                        '$' => self.expand_parameter(&mut chars),
+                        _ => self.substitute(&Shell::read_backquoted(&mut chars)),
+                    };
+                    // What an unquoted expansion gives is a pattern itself
+                    match q {
+                        Some(_) => literal(&mut result, &value),
+                        None => result.push_str(&value),
+                    }
+                }
+                (c, Some(_)) => literal(&mut result, &c.to_string()),
+                (c, None) => result.push(c),
+            }
+        }
+        result
+    }
+
+    // Expands `"$@"` into one word per positional parameter, the first
+    // joining the word being built and the last continuing it.
+    fn push_positional(&self, current: &mut String, tokens: &mut Vec<String>) {
+        for (i, arg) in self.positional.iter().enumerate() {
+            if i > 0 {
+                tokens.push(std::mem::take(current));
+            }
+            current.push_str(arg);
+        }
+    }
+
+    // Expands an unquoted `$@` or `$*`. Each positional parameter is
+    // split into fields like any other unquoted expansion and ends the
+    // field it's in; as an assignment's value they are joined instead.
+    fn push_positional_fields(
+        &self,
+        name: &str,
+        current: &mut String,
+        quoted: &mut bool,
+        globs: &mut Vec<usize>,
+        tokens: &mut Vec<String>,
+    ) {
+        if Shell::assigning(current, tokens) {
+            current.push_str(&self.parameter(name).unwrap_or_default());
+            return;
+        }
+        for (i, arg) in self.positional.iter().enumerate() {
+            if i > 0 && (!current.is_empty() || *quoted) {
+                *quoted = false;
+                self.push_word(std::mem::take(current), globs, tokens);
+            }
+            self.push_fields(arg, current, quoted, globs, tokens);
+        }
+    }
+
+    // Whether the word being built is the value of an assignment that
+    // comes before any command word.
+    fn assigning(current: &str, tokens: &[String]) -> bool {
+        Shell::assignment(current).is_some() && tokens.iter().all(|token| Shell::assignment(token).is_some())
+    }
+
+    // Adds a finished word to `tokens`. If it has unquoted pattern
+    // characters, at the offsets in `globs`, it is replaced by the paths
+    // it matches.
+    fn push_word(&self, word: String, globs: &mut Vec<usize>, tokens: &mut Vec<String>) {
+        let is_pattern = globs.iter().any(|&i| matches!(word.as_bytes().get(i), Some(b'*' | b'?' | b'[')));
+        if !is_pattern {
+            globs.clear();
+            tokens.push(word);
+            return;
+        }
+
+        let mut pattern = String::new();
+        for (i, c) in word.char_indices() {
+            if "*?[]\\".contains(c) && !globs.contains(&i) {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        globs.clear();
+
+        let paths = glob::expand(&pattern, self.options.globstar);
+        if !paths.is_empty() {
+            tokens.extend(paths);
+        } else if self.options.failglob {
+            self.fail_expansion(ShellError::NoMatch(word));
+        } else if !self.options.nullglob {
+            tokens.push(word);
+        }
+    }
+
+    // Adds the result of an unquoted expansion to the word being built,
+    // splitting it into fields at the characters in IFS. Whitespace in
+    // IFS separates fields however much of it there is; any other IFS
+    // character ends exactly one field, which may be empty. Pattern
+    // characters in the result are left unquoted for globbing. The value
+    // of an assignment is never split. A field that was partly quoted,
+    // as `""` is, is kept even if it's empty.
+    fn push_fields(
+        &self,
+        text: &str,
+        current: &mut String,
+        quoted: &mut bool,
+        globs: &mut Vec<usize>,
+        tokens: &mut Vec<String>,
+    ) {
+        let ifs = match self.parameter("IFS") {
+            _ if Shell::assigning(current, tokens) => String::new(),
+            Some(ifs) => ifs,
+            None => " \t\n".to_string(),
+        };
+
+        let mut after_whitespace = false;
+        for c in text.chars() {
+            if !ifs.contains(c) {
+                if "*?[]".contains(c) {
+                    globs.push(current.len());
+                }
+                current.push(c);
+                after_whitespace = false;
+            } else if c.is_whitespace() {
+                if !current.is_empty() || *quoted {
+                    self.push_word(std::mem::take(current), globs, tokens);
+                }
+                *quoted = false;
+                after_whitespace = true;
+            } else {
+                if !(after_whitespace && current.is_empty()) {
+                    self.push_word(std::mem::take(current), globs, tokens);
+                }
+                *quoted = false;
+                after_whitespace = false;
+            }
+        }
+    }
+
+    // Expands lexed words, each of which may become any number of
+    // words.
+    fn expand_words(&mut self, words: &[String]) -> Vec<String> {
+        let mut expanded = Vec::new();
+        for word in words {
+            // Brace expansion comes before any other
+            for word in expand::braces(word) {
+                expanded.extend(self.split_words(&word, true));
+            }
+        }
+        expanded
+    }
+
+    // Expands a lexed word into fields, removing quotes. Parameters are
+    // only expanded with `expand`, so a line can be looked over before it
+    // runs without expanding anything twice. A quoted empty string is a
+    // field of its own, but `"$@"` with no positional parameters is
+    // nothing at all.
+    fn split_words(&mut self, input: &str, expand: bool) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut current = String::new();
+        // Whether `current` has had quotes in it, which make it a field
+        // even if it's empty
+        let mut quoted = false;
+        let mut chars = input.chars().peekable();
+        // Offsets in `current` of the pattern characters that weren't
+        // quoted
+        let mut globs = Vec::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => {
+                    // Single quotes: preserve everything literally
+                    quoted = true;
+                    for c in chars.by_ref() {
+                        if c == '\'' {
+                            break;
+                        }
+                        current.push(c);
+                    }
+                }
+                '"' => {
+                    // Double quotes: handle escape sequences
+                    let before = std::mem::replace(&mut quoted, true);
+                    let mut only_empty_positional = None;
+                    while let Some(c) = chars.next() {
+                        if c != '"' && !(c == '$' && chars.peek() == Some(&'@')) {
+                            only_empty_positional = Some(false);
+                        }
+                        match c {
+                            '"' => break,
+                            '\\' => {
+                                if let Some(next) = chars.next() {
+                                    match next {
+                                        '\\' | '$' | '"' | '\n' => current.push(next),
+                                        _ => {
+                                            current.push('\\');
+                                            current.push(next);
+                                        }
+                                    }
+                                }
+                            }
+                            '$' if expand && chars.peek() == Some(&'@') => {
+                                chars.next();
+                                self.push_positional(&mut current, &mut tokens);
+                                globs.clear();
+                                let empty = self.positional.is_empty();
+                                only_empty_positional = Some(only_empty_positional.unwrap_or(true) && empty);
+                            }
+                            '$' if expand => current.push_str(&self.expand_parameter(&mut chars)),
+                            '`' if expand => {
+                                current.push_str(&self.substitute(&Shell::read_backquoted(&mut chars)));
+                            }
+                            _ => current.push(c),
+                        }
+                    }
+                    if only_empty_positional == Some(true) {
+                        quoted = before;
+                    }
+                }
+                // `~` and `~user` at the start of a word or of an
+                // assignment's value
+                '~' if expand && (current.is_empty() || Shell::assignment_prefix(&current)) => {
+                    current.push_str(&self.expand_tilde(&mut chars));
+                }
+                '<' | '>' if expand && current.is_empty() && chars.peek() == Some(&'(') => {
+                    chars.next();
+                    current.push_str(&self.substitute_process(c, &mut chars));
+                }
+                '$' if expand && matches!(chars.peek(), Some('@' | '*')) => {
+                    let name = chars.next().unwrap().to_string();
+                    self.push_positional_fields(&name, &mut current, &mut quoted, &mut globs, &mut tokens);
+                }
+                '$' if expand => {
+                    let expansion = self.expand_parameter(&mut chars);
+                    self.push_fields(&expansion, &mut current, &mut quoted, &mut globs, &mut tokens);
+                }
+                '`' if expand => {
+                    let expansion = self.substitute(&Shell::read_backquoted(&mut chars));
+                    self.push_fields(&expansion, &mut current, &mut quoted, &mut globs, &mut tokens);
+                }
+                '\\' => {
+                    // Backslash: escape the next character
+                    if let Some(next) = chars.next() {
+                        current.push(next);
+                    }
+                }
+                ' ' => {
+                    if !current.is_empty() || quoted {
+                        self.push_word(std::mem::take(&mut current), &mut globs, &mut tokens);
+                    }
+                    quoted = false;
+                }
+                '*' | '?' | '[' | ']' if expand => {
+                    globs.push(current.len());
+                    current.push(c);
+                }
+                _ => current.push(c),
+            }
+        }
+
+        if !current.is_empty() || quoted {
+            self.push_word(current, &mut globs, &mut tokens);
+        }
+
+        tokens
+    }
+
+    // Finds the here-documents started on `line`, returning each one's
+    // delimiter and whether it strips leading tabs.
+    fn read_heredoc_delimiters(&mut self, line: &str) -> Vec<(String, bool)> {
+        let mut delimiters = Vec::new();
+        let mut tokens = lexer::lex(line).into_iter();
+        while let Some(token) = tokens.next() {
+            let Token::Operator(operator) = token else {
+                continue;
+            };
+            let Some(redirects) = redirect::parse(&operator) else {
+                continue;
+            };
+            for redirect in redirects {
+                let RedirectOp::HereDoc { strip_tabs, .. } = redirect.op else {
+                    continue;
+                };
+                let Some(Token::Word(delimiter)) = tokens.next() else {
+                    return delimiters;
+                };
+                delimiters.push((self.split_words(&delimiter, false).concat(), strip_tabs));
+            }
+        }
+        delimiters
+    }
+
+    // Reads the bodies of any here-documents started on `line`, in order.
+    // Returns None if reading was interrupted.
+    fn read_heredocs(&mut self, line: &str, source: &mut Source) -> Option<VecDeque<String>> {
+        let mut bodies = VecDeque::new();
+        for (delimiter, strip_tabs) in self.read_heredoc_delimiters(line) {
+            let mut body = String::new();
+            loop {
+                let prompt = self.continuation_prompt();
+                let line = match self.next_line(source, &prompt, "") {
+                    Ok(Line::Text(line)) => line,
+                    Ok(Line::Interrupted) => {
+                        println!();
+                        return None;
+                    }
+                    Ok(Line::Eof) | Err(_) => {
+                        self.report(&ShellError::HereDocEof(delimiter.clone()));
+                        break;
+                    }
+                };
+                let line = if strip_tabs { line.trim_start_matches('\t') } else { &line };
+                if line == delimiter {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+            bodies.push_back(body);
+        }
+        Some(bodies)
+    }
+
+    // The prompt for the first line of a command, `$PS1` expanded.
+    fn primary_prompt(&mut self) -> String {
+        prompt::expand(&self.parameter("PS1").unwrap_or_else(|| "\\$ ".to_string()), self)
+    }
+
+    // What's shown at the right of the line a command is typed on,
+    // `$RPROMPT` expanded.
+    fn right_prompt(&mut self) -> String {
+        self.parameter("RPROMPT").map(|template| prompt::expand(&template, self)).unwrap_or_default()
+    }
+
+    // The prompt for the lines of a command after the first, `$PS2`
+    // expanded.
+    fn continuation_prompt(&mut self) -> String {
+        prompt::expand(&self.parameter("PS2").unwrap_or_else(|| "> ".to_string()), self)
+    }
+
+    // Keeps reading while the line ends with an unescaped backslash or
+    // inside an unterminated quote. A backslash-newline is dropped when
+    // joining the lines; a newline inside quotes is kept. Returns None
+    // if reading was interrupted, and an error if the input ended inside
+    // a quote.
+    fn read_continuation(&mut self, mut line: String, source: &mut Source) -> Result<Option<String>, ShellError> {
+        loop {
+            let nesting = Shell::scan(Shell::strip_comment(&line));
+            if nesting.escaped {
+                line.pop();
+            } else if nesting.quote.is_some() {
+                line.push('\n');
+            } else {
+                return Ok(Some(line));
+            }
+
+            let prompt = self.continuation_prompt();
+            match self.next_line(source, &prompt, "") {
+                Ok(Line::Text(more)) => line.push_str(&more),
+                Ok(Line::Interrupted) => {
+                    println!();
+                    return Ok(None);
+                }
+                Ok(Line::Eof) | Err(_) => {
+                    if let Some(quote) = nesting.quote {
+                        return Err(ShellError::UnmatchedQuote(quote));
+                    }
+                    return Ok(Some(line));
+                }
+            }
+        }
+    }
+
+    // Whether `input` has a newline that isn't quoted, which ends a
+    // command.
+    fn has_line_break(input: &str) -> bool {
+        let mut chars = input.chars().peekable();
+        let mut nesting = Nesting::new();
+        while let Some(c) = chars.next() {
+            if c == '\n' && nesting.quote.is_none() && !nesting.escaped {
+                return true;
+            }
+            nesting.feed(c, chars.peek().copied());
+        }
+        false
+    }
+
+    // Scans `line`, returning the quoting and nesting in effect at its
+    // end.
+    fn scan(line: &str) -> Nesting {
+        let mut chars = line.chars().peekable();
+        let mut nesting = Nesting::new();
+        while let Some(c) = chars.next() {
+            nesting.feed(c, chars.peek().copied());
+        }
+        nesting
+    }
+
+    // Cuts off the comment, if any, that an unquoted `#` at the start of a
+    // word begins.
+    fn strip_comment(line: &str) -> &str {
+        let mut chars = line.char_indices().peekable();
+        let mut nesting = Nesting::new();
+        let mut prev = None;
+        while let Some((i, c)) = chars.next() {
+            let word_start = prev.is_none_or(|p: char| p.is_whitespace() || ";&|(".contains(p));
+            if c == '#' && word_start && nesting.quote.is_none() && !nesting.escaped {
+                return &line[..i];
+            }
+            nesting.feed(c, chars.peek().map(|&(_, next)| next));
+            prev = Some(c);
+        }
+        line
+    }
+
+}
+
+impl expand::Context for Shell {
+    fn lookup(&self, name: &str) -> Option<String> {
+        self.parameter(name)
+    }
+
+    fn assign(&mut self, name: &str, value: &str) {
+        self.set_variable(name, value);
+    }
+
+    fn word(&mut self, word: &str) -> String {
+        self.expand_word(word)
+    }
+}
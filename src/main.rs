@@ -1,206 +1,129 @@
-use std::io::{self, Write};
-use std::collections::HashMap;
 use std::env;
-use std::path::Path;
-use std::process::{Command, Stdio};
-use std::fs::File;
-
-#[derive(Clone)]
-enum CommandType {
-    Builtin(fn(&str) -> ()),
-}
-
-struct Shell {
-    commands: HashMap<String, CommandType>,
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::process;
+
+use shellob::Shell;
+
+const USAGE: &str = "\
+usage: shellob [option ...] [script [arg ...]]
+       shellob [option ...] -c command [name [arg ...]]
+       shellob [option ...] -s [arg ...]
+
+options:
+  -c           run the commands given as the first argument, with the
+               next as $0 and the rest as $1, $2, ...
+  -i           show prompts even if stdin is not a terminal
+  -l, --login  run as a login shell
+  -s           read commands from stdin, taking the arguments as $1, $2, ...
+  --noprofile  don't run /etc/profile and ~/.profile in a login shell
+  --norc       don't run ~/.shellobrc in an interactive shell
+  --help       show this help and exit
+  --version    show the version and exit";
+
+fn invalid(option: &str) -> ! {
+    eprintln!("shellob: {}: invalid option", option);
+    eprintln!("{}", USAGE);
+    process::exit(2);
 }
 
-impl Shell {
-    fn new() -> Self {
-        let mut commands = HashMap::new();
-        
-        commands.insert("cd".to_string(), CommandType::Builtin(|arg| {
-            let new_dir = arg.split_whitespace().peekable().peek().map_or("/", |x| *x);
-            let root = Path::new(new_dir);
-            if let Err(e) = env::set_current_dir(&root) {
-                eprintln!("{}", e);
-            }
-        }));
-
-        commands.insert("echo".to_string(), CommandType::Builtin(|arg| {
-            println!("{}", arg);
-        }));
-        
-        commands.insert("exit".to_string(), CommandType::Builtin(|arg| {
-            match arg {
-                "0" => std::process::exit(0),
-                _ => println!("{}: invalid argument", arg),
-            }
-        }));
-        
-        commands.insert("type".to_string(), CommandType::Builtin(|arg| {
-            if arg.is_empty() {
-                println!("type: not enough arguments");
+fn main() {
+    let mut args = env::args().peekable();
+    // Like other shells, a login shell is started with a `-` in front
+    // of its name
+    let mut login = args.next().is_some_and(|name| name.starts_with('-'));
+    let mut interactive = false;
+    let mut stdin = false;
+    let mut command = false;
+    let mut profile = true;
+    let mut rc = true;
+    while let Some(arg) = args.next_if(|arg| arg.starts_with('-') && arg.len() > 1) {
+        match arg.as_str() {
+            "--" => break,
+            "--help" => {
+                println!("{}", USAGE);
                 return;
             }
-            match arg {
-                "cd" | "echo" | "exit" | "type" => println!("{} is a shellob builtin", arg),
-                cmd => {
-                    if let Some(path) = Shell::find_in_path(cmd) {
-                        println!("{} is {}", cmd, path);
-                    } else {
-                        println!("{}: not found", cmd);
-                    }
-                }
+            "--version" => {
+                println!("{}", Shell::version_info());
+                return;
             }
-        }));
-
-        Shell { commands }
-    }
-
-    fn find_in_path(command: &str) -> Option<String> {
-        env::var("PATH").ok()?.split(':')
-            .map(|dir| format!("{}/{}", dir, command))
-            .find(|path| Path::new(path).is_file())
-    }
-
-    fn tokenize(input: &str) -> Vec<String> {
-        let mut tokens = Vec::new();
-        let mut current = String::new();
-        let mut chars = input.chars().peekable();
-
-        while let Some(c) = chars.next() {
-            match c {
-                '\'' => {
-                    // Single quotes: preserve everything literally
-                    while let Some(c) = chars.next() {
-                        if c == '\'' {
-                            break;
-                        }
-                        current.push(c);
-                    }
-                }
-                '"' => {
-                    // Double quotes: handle escape sequences
-                    while let Some(c) = chars.next() {
-                        match c {
-                            '"' => break,
-                            '\\' => {
-                                if let Some(next) = chars.next() {
-                                    match next {
-                                        '\\' | '$' | '"' | '\n' => current.push(next),
-                                        _ => {
-                                            current.push('\\');
-                                            current.push(next);
-                                        }
-                                    }
-                                }
-                            }
-                            _ => current.push(c),
-                        }
+            "--login" => login = true,
+            "--noprofile" => profile = false,
+            "--norc" => rc = false,
+            long if long.starts_with("--") => invalid(long),
+            flags => {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'c' => command = true,
+                        'i' => interactive = true,
+                        'l' => login = true,
+                        's' => stdin = true,
+                        _ => invalid(&format!("-{}", flag)),
                     }
                 }
-                '\\' => {
-                    // Backslash: escape the next character
-                    if let Some(next) = chars.next() {
-                        current.push(next);
-                    }
-                }
-                ' ' => {
-                    if !current.is_empty() {
-                        tokens.push(current);
-                        current = String::new();
-                    }
-                }
-                _ => current.push(c),
             }
         }
+    }
 
-        if !current.is_empty() {
-            tokens.push(current);
+    // With -c the first argument left is the commands to run and the
+    // one after it the name `$0` expands to. Otherwise it's a script to
+    // run, unless commands come from stdin
+    let mut operands: Vec<String> = args.collect();
+    let command = match command {
+        true if operands.is_empty() => {
+            eprintln!("shellob: -c: option requires an argument");
+            process::exit(2);
         }
-
-        tokens
+        true => Some(operands.remove(0)),
+        false => None,
+    };
+    let name = match command.is_some() && !operands.is_empty() {
+        true => Some(operands.remove(0)),
+        false => None,
+    };
+    let script = match stdin || command.is_some() || operands.is_empty() {
+        true => None,
+        false => Some(operands.remove(0)),
+    };
+
+    let mut shell = Shell::new();
+    shell.set_positional(operands);
+    // Nobody reads a script's output a line at a time, so it can be
+    // written in blocks. At the prompt each line goes out as it's written.
+    if script.is_some() || command.is_some() || !(interactive || io::stdin().is_terminal()) {
+        shell.set_buffered_output(true);
     }
-
-    fn handle_command(&self, input: &str) {
-        let tokens = Shell::tokenize(input);
-        if tokens.is_empty() {
-            return;
+    if login {
+        shell.login();
+        if profile {
+            shell.source_profile();
         }
-
-        // Find redirection operator and output file
-        let mut cmd_end = tokens.len();
-        let mut output_file = None;
-
-        for i in 0..tokens.len() {
-            if tokens[i] == ">" || tokens[i] == "1>" {
-                if i + 1 < tokens.len() {
-                    cmd_end = i;
-                    output_file = Some(&tokens[i + 1]);
-                }
-                break;
-            }
+    }
+    if let Some(command) = command {
+        if let Some(name) = name {
+            shell.set_name(&name);
         }
-
-        let command = &tokens[0];
-        let arguments = &tokens[1..cmd_end];
-
-        if let Some(cmd_type) = self.commands.get(command) {
-            // Handle builtin commands
-            match cmd_type {
-                CommandType::Builtin(func) => {
-                    if let Some(file) = output_file {
-                        if let Ok(mut file) = File::create(file) {
-                            let output = arguments.join(" ");
-                            writeln!(file, "{}", output).unwrap_or_else(|e| eprintln!("Error writing to file: {}", e));
-                        }
-                    } else {
-                        func(&arguments.join(" "))
-                    }
-                }
-            }
-        } else if let Some(path) = Shell::find_in_path(command) {
-            // Execute the external command
-            let path_clone = path.clone();
-            let mut cmd = Command::new(path);
-            cmd.args(arguments);
-
-            if let Some(file) = output_file {
-                if let Ok(file) = File::create(file) {
-                    cmd.stdout(Stdio::from(file));
-                } else {
-                    eprintln!("Error: Could not create output file");
-                    return;
-                }
-            }
-
-            match cmd.output() {
-                Ok(output) => {
-                    if output_file.is_none() {
-                        print!("{}", String::from_utf8_lossy(&output.stdout));
-                    }
-                    let stderr = String::from_utf8_lossy(&output.stderr)
-                        .replace(&format!("{}: ", path_clone), &format!("{}: ", command));
-                    eprint!("{}", stderr);
-                }
-                Err(e) => eprintln!("Error executing command: {}", e),
+        let status = shell.eval(&command);
+        shell.exit(status.code());
+    }
+    if let Some(script) = script {
+        let text = match fs::read_to_string(&script) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("shellob: {}: {}", script, e);
+                process::exit(if e.kind() == io::ErrorKind::NotFound { 127 } else { 126 });
             }
-        } else {
-            println!("{}: command not found", command);
-        }
+        };
+        shell.set_name(&script);
+        let status = shell.eval(&text);
+        shell.exit(status.code());
     }
-}
-
-fn main() {
-    let shell = Shell::new();
-    let stdin = io::stdin();
-    
-    loop {
-        print!("$ ");
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        stdin.read_line(&mut input).unwrap();
-        shell.handle_command(input.trim());
+    if interactive {
+        shell.set_interactive();
+    }
+    if rc && (interactive || io::stdin().is_terminal()) {
+        shell.source_rc();
     }
+    shell.repl();
 }
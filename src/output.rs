@@ -0,0 +1,57 @@
+//! The process's stdout as the shell itself writes to it.
+//!
+//! At the prompt each line should show up as soon as it's written, which
+//! `io::Stdout` already sees to. A script's output is usually read in
+//! bulk, so with the `bufferoutput` option it's collected in a buffer and
+//! written a block at a time instead of with a system call per line. The
+//! buffer has to be flushed before anything else writes to the same
+//! place: before a child is started, before the shell writes to stderr,
+//! which may well be the same file, and before the shell exits.
+
+use std::io::{self, BufWriter, Write};
+use std::sync::Mutex;
+
+/// Like stdout itself, the buffer is the process's rather than any one
+/// shell's.
+static BUFFER: Mutex<Option<BufWriter<io::Stdout>>> = Mutex::new(None);
+
+/// A handle to stdout that goes through the buffer while there is one.
+pub struct Stdout;
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *lock() {
+            Some(buffer) => buffer.write(buf),
+            None => io::stdout().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(buffer) = &mut *lock() {
+            buffer.flush()?;
+        }
+        io::stdout().flush()
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<BufWriter<io::Stdout>>> {
+    BUFFER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Starts or stops buffering stdout. Whatever was buffered is written
+/// out when it stops.
+pub fn set_buffered(buffered: bool) {
+    let mut buffer = lock();
+    match (buffered, buffer.take()) {
+        (true, existing) => *buffer = Some(existing.unwrap_or_else(|| BufWriter::new(io::stdout()))),
+        (false, Some(mut existing)) => {
+            let _ = existing.flush();
+        }
+        (false, None) => {}
+    }
+}
+
+/// Writes out whatever is buffered.
+pub fn flush() {
+    let _ = Stdout.flush();
+}
@@ -0,0 +1,497 @@
+//! Parsing the tokens of a command line into a tree of lists, and-or
+//! lists, pipelines and commands. Words are kept as the lexer produced
+//! them; they are only expanded when the command they belong to runs.
+
+use std::collections::VecDeque;
+
+use crate::error::ShellError;
+use crate::lexer::Token;
+use crate::redirect::{self, Redirect, RedirectOp};
+
+/// How a pipeline in an and-or list is joined to the one before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    /// `&&`: run only if the previous pipeline succeeded
+    And,
+    /// `||`: run only if the previous pipeline failed
+    Or,
+}
+
+/// And-or lists separated by `;` or `&`, run one after another.
+#[derive(Debug, Default)]
+pub struct List {
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug)]
+pub struct Item {
+    pub and_or: AndOr,
+    /// Whether the and-or list ended in `&`
+    pub background: bool,
+}
+
+/// Pipelines joined by `&&` and `||`, which are evaluated left to right
+/// with equal precedence.
+#[derive(Debug)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+impl AndOr {
+    /// The source text of the whole list, as shown in the jobs table.
+    pub fn text(&self) -> String {
+        let mut text = self.first.text.clone();
+        for (connector, pipeline) in &self.rest {
+            let operator = match connector {
+                Connector::And => "&&",
+                Connector::Or => "||",
+            };
+            text = format!("{} {} {}", text, operator, pipeline.text);
+        }
+        text
+    }
+}
+
+/// Commands joined by `|`, each one's output feeding the next one's
+/// input. A leading `!` inverts the status.
+#[derive(Debug)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+    /// The tokens the pipeline was parsed from, joined by spaces
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub struct Command {
+    pub kind: CommandKind,
+    /// Redirections in the order they are applied. The targets of
+    /// here-documents already hold their bodies.
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug)]
+pub enum CommandKind {
+    /// Words, assignments included, still quoted and unexpanded
+    Simple(Vec<String>),
+    /// `( list )`, run in a copy of the shell
+    Subshell(List),
+    /// `{ list; }`, run in the shell itself
+    Group(List),
+    /// `case word in pattern) list ;; ... esac`, with the word still
+    /// quoted and unexpanded
+    Case(String, Vec<CaseArm>),
+}
+
+/// One arm of a `case`: its patterns, still quoted and unexpanded, and
+/// the commands run when one of them matches.
+#[derive(Debug)]
+pub struct CaseArm {
+    pub patterns: Vec<String>,
+    pub body: List,
+    pub end: CaseEnd,
+}
+
+/// How a `case` arm ends, which decides what runs after its commands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaseEnd {
+    /// `;;`, or nothing before `esac`: the `case` is done
+    Break,
+    /// `;&`: the next arm's commands run too, whatever its patterns
+    FallThrough,
+    /// `;;&`: the patterns of the arms after it are tried too
+    Continue,
+}
+
+/// Parses a lexed command line. The bodies of the here-documents it
+/// starts are given in order.
+pub fn parse(tokens: &[Token], heredocs: VecDeque<String>) -> Result<List, ShellError> {
+    let mut parser = Parser { tokens, pos: 0, heredocs };
+    let list = parser.list()?;
+    match parser.peek() {
+        None => Ok(list),
+        token => Err(unexpected(token)),
+    }
+}
+
+fn unexpected(token: Option<&Token>) -> ShellError {
+    match token {
+        Some(token) => ShellError::UnexpectedToken(token.to_string()),
+        None => ShellError::UnexpectedEof,
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    heredocs: VecDeque<String>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_operator(&self, operator: &str) -> bool {
+        matches!(self.peek(), Some(Token::Operator(op)) if op == operator)
+    }
+
+    /// Whether the next token is the reserved word `word`. Reserved
+    /// words are only recognized where a command could start.
+    fn peek_reserved(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn peek_redirect(&self) -> Option<Vec<Redirect>> {
+        match self.peek() {
+            Some(Token::Operator(op)) => redirect::parse(op),
+            _ => None,
+        }
+    }
+
+    /// Consumes `expected`, a closing `)`, `}` or `esac`.
+    fn expect(&mut self, expected: Token) -> Result<(), ShellError> {
+        if self.peek() != Some(&expected) {
+            return Err(unexpected(self.peek()));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn starts_command(&self) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) => word != "}" && word != "esac",
+            Some(Token::Operator(op)) => op == "(" || redirect::parse(op).is_some(),
+            None => false,
+        }
+    }
+
+    fn list(&mut self) -> Result<List, ShellError> {
+        let mut items = Vec::new();
+        while self.starts_command() {
+            let and_or = self.and_or()?;
+            let background = self.peek_operator("&");
+            let separated = background || self.peek_operator(";");
+            items.push(Item { and_or, background });
+            if !separated {
+                break;
+            }
+            self.pos += 1;
+        }
+        Ok(List { items })
+    }
+
+    /// The list inside `( ... )` or `{ ...; }`, which can't be empty.
+    fn body(&mut self) -> Result<List, ShellError> {
+        let list = self.list()?;
+        if list.items.is_empty() {
+            return Err(unexpected(self.peek()));
+        }
+        Ok(list)
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, ShellError> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = if self.peek_operator("&&") {
+                Connector::And
+            } else if self.peek_operator("||") {
+                Connector::Or
+            } else {
+                break;
+            };
+            self.pos += 1;
+            rest.push((connector, self.pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ShellError> {
+        let start = self.pos;
+        let negated = self.peek_reserved("!");
+        if negated {
+            self.pos += 1;
+        }
+        let mut commands = vec![self.command()?];
+        while self.peek_operator("|") {
+            self.pos += 1;
+            commands.push(self.command()?);
+        }
+        let text = self.tokens[start..self.pos]
+            .iter()
+            .map(Token::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(Pipeline { negated, commands, text })
+    }
+
+    fn command(&mut self) -> Result<Command, ShellError> {
+        let kind = if self.peek_operator("(") {
+            self.pos += 1;
+            let body = self.body()?;
+            self.expect(Token::Operator(")".to_string()))?;
+            CommandKind::Subshell(body)
+        } else if self.peek_reserved("{") {
+            self.pos += 1;
+            let body = self.body()?;
+            self.expect(Token::Word("}".to_string()))?;
+            CommandKind::Group(body)
+        } else if self.peek_reserved("case") {
+            self.pos += 1;
+            self.case()?
+        } else {
+            return self.simple_command();
+        };
+
+        // Redirections after the closing `)` or `}` apply to the group as
+        // a whole
+        let mut redirects = Vec::new();
+        while self.peek_redirect().is_some() {
+            redirects.extend(self.redirect()?);
+        }
+        Ok(Command { kind, redirects })
+    }
+
+    /// The rest of a `case` after the reserved word: the word to match,
+    /// `in`, and the arms up to `esac`. The last arm needn't end in `;;`.
+    fn case(&mut self) -> Result<CommandKind, ShellError> {
+        let word = self.word()?;
+        if !self.peek_reserved("in") {
+            return Err(unexpected(self.peek()));
+        }
+        self.pos += 1;
+        let mut arms = Vec::new();
+        while !self.peek_reserved("esac") {
+            // Each pattern list can have a `(` before it too
+            if self.peek_operator("(") {
+                self.pos += 1;
+            }
+            let mut patterns = vec![self.word()?];
+            while self.peek_operator("|") {
+                self.pos += 1;
+                patterns.push(self.word()?);
+            }
+            self.expect(Token::Operator(")".to_string()))?;
+            let body = self.list()?;
+            let end = match self.peek() {
+                Some(Token::Operator(op)) if op == ";;" => CaseEnd::Break,
+                Some(Token::Operator(op)) if op == ";&" => CaseEnd::FallThrough,
+                Some(Token::Operator(op)) if op == ";;&" => CaseEnd::Continue,
+                _ => {
+                    arms.push(CaseArm { patterns, body, end: CaseEnd::Break });
+                    break;
+                }
+            };
+            self.pos += 1;
+            arms.push(CaseArm { patterns, body, end });
+        }
+        self.expect(Token::Word("esac".to_string()))?;
+        Ok(CommandKind::Case(word, arms))
+    }
+
+    /// Consumes a word, such as a `case` pattern.
+    fn word(&mut self) -> Result<String, ShellError> {
+        match self.peek() {
+            Some(Token::Word(word)) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            }
+            token => Err(unexpected(token)),
+        }
+    }
+
+    fn simple_command(&mut self) -> Result<Command, ShellError> {
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    words.push(word.clone());
+                    self.pos += 1;
+                }
+                _ if self.peek_redirect().is_some() => redirects.extend(self.redirect()?),
+                _ => break,
+            }
+        }
+        if words.is_empty() && redirects.is_empty() {
+            return Err(unexpected(self.peek()));
+        }
+        Ok(Command { kind: CommandKind::Simple(words), redirects })
+    }
+
+    /// Reads a redirection operator and its target word, if the target
+    /// isn't part of the operator.
+    fn redirect(&mut self) -> Result<Vec<Redirect>, ShellError> {
+        let mut redirects = self.peek_redirect().unwrap_or_default();
+        self.pos += 1;
+        for redirect in &mut redirects {
+            if redirect.target.is_empty() {
+                match self.peek() {
+                    Some(Token::Word(target)) => redirect.target = target.clone(),
+                    Some(token) => return Err(unexpected(Some(token))),
+                    None => return Err(ShellError::UnexpectedToken("newline".to_string())),
+                }
+                self.pos += 1;
+            }
+            if let RedirectOp::HereDoc { ref mut expand, .. } = redirect.op {
+                *expand = !redirect.target.contains(['\'', '"', '\\']);
+                redirect.target = self.heredocs.pop_front().unwrap_or_default();
+            }
+        }
+        Ok(redirects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    fn parse_line(line: &str) -> List {
+        parse(&lex(line), VecDeque::new()).unwrap()
+    }
+
+    fn parse_error(line: &str) -> String {
+        parse(&lex(line), VecDeque::new()).unwrap_err().to_string()
+    }
+
+    fn words(command: &Command) -> &[String] {
+        match &command.kind {
+            CommandKind::Simple(words) => words,
+            kind => panic!("not a simple command: {:?}", kind),
+        }
+    }
+
+    fn body(command: &Command) -> &List {
+        match &command.kind {
+            CommandKind::Subshell(body) | CommandKind::Group(body) => body,
+            kind => panic!("not a group: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parses_lists() {
+        let list = parse_line("a; b & c");
+        assert_eq!(list.items.len(), 3);
+        assert_eq!(list.items.iter().map(|item| item.background).collect::<Vec<_>>(), [false, true, false]);
+        assert_eq!(words(&list.items[2].and_or.first.commands[0]), ["c"]);
+
+        // A separator can end the line, but not start it
+        assert_eq!(parse_line("a;").items.len(), 1);
+        assert!(parse_line("a &").items[0].background);
+        assert!(parse_line("").items.is_empty());
+        assert_eq!(parse_error("; a"), "syntax error near unexpected token `;'");
+        assert_eq!(parse_error("a;;"), "syntax error near unexpected token `;;'");
+    }
+
+    #[test]
+    fn parses_and_or_lists() {
+        let list = parse_line("a && b || c");
+        let and_or = &list.items[0].and_or;
+        assert_eq!(words(&and_or.first.commands[0]), ["a"]);
+        let connectors: Vec<Connector> = and_or.rest.iter().map(|(connector, _)| *connector).collect();
+        assert_eq!(connectors, [Connector::And, Connector::Or]);
+        assert_eq!(words(&and_or.rest[1].1.commands[0]), ["c"]);
+        assert_eq!(and_or.text(), "a && b || c");
+        assert_eq!(parse_error("a &&"), "syntax error: unexpected end of file");
+        assert_eq!(parse_error("|| a"), "syntax error near unexpected token `||'");
+    }
+
+    #[test]
+    fn parses_pipelines() {
+        let list = parse_line("! a x | b | c y");
+        let pipeline = &list.items[0].and_or.first;
+        assert!(pipeline.negated);
+        assert_eq!(pipeline.commands.len(), 3);
+        assert_eq!(words(&pipeline.commands[0]), ["a", "x"]);
+        assert_eq!(words(&pipeline.commands[2]), ["c", "y"]);
+        assert_eq!(pipeline.text, "! a x | b | c y");
+
+        // `!` is only special where a command starts
+        assert_eq!(words(&parse_line("echo !").items[0].and_or.first.commands[0]), ["echo", "!"]);
+        assert_eq!(parse_error("a |"), "syntax error: unexpected end of file");
+        assert_eq!(parse_error("a | | b"), "syntax error near unexpected token `|'");
+    }
+
+    #[test]
+    fn parses_groups() {
+        let list = parse_line("(a; b) && { c; } > out");
+        let and_or = &list.items[0].and_or;
+        let subshell = &and_or.first.commands[0];
+        assert!(matches!(subshell.kind, CommandKind::Subshell(_)));
+        assert_eq!(body(subshell).items.len(), 2);
+        let group = &and_or.rest[0].1.commands[0];
+        assert!(matches!(group.kind, CommandKind::Group(_)));
+        assert_eq!(words(&body(group).items[0].and_or.first.commands[0]), ["c"]);
+        assert_eq!(group.redirects.len(), 1);
+        assert_eq!(group.redirects[0].target, "out");
+
+        assert_eq!(parse_error("(a"), "syntax error: unexpected end of file");
+        assert_eq!(parse_error("()"), "syntax error near unexpected token `)'");
+        assert_eq!(parse_error("{ a; } b"), "syntax error near unexpected token `b'");
+        assert_eq!(parse_error("a )"), "syntax error near unexpected token `)'");
+    }
+
+    #[test]
+    fn parses_case() {
+        let list = parse_line("case $x in (a|b) one;; c) two; three;& d) ;;& *) four; esac > out");
+        let command = &list.items[0].and_or.first.commands[0];
+        let CommandKind::Case(word, arms) = &command.kind else {
+            panic!("not a case: {:?}", command.kind);
+        };
+        assert_eq!(word, "$x");
+        let patterns: Vec<&[String]> = arms.iter().map(|arm| arm.patterns.as_slice()).collect();
+        assert_eq!(patterns, [&["a", "b"][..], &["c"], &["d"], &["*"]]);
+        let ends: Vec<CaseEnd> = arms.iter().map(|arm| arm.end).collect();
+        assert_eq!(ends, [CaseEnd::Break, CaseEnd::FallThrough, CaseEnd::Continue, CaseEnd::Break]);
+        assert_eq!(arms[1].body.items.len(), 2);
+        assert!(arms[2].body.items.is_empty());
+        assert_eq!(command.redirects.len(), 1);
+
+        assert_eq!(parse_line("case x in esac").items.len(), 1);
+        assert_eq!(parse_error("case x a) b;; esac"), "syntax error near unexpected token `a'");
+        assert_eq!(parse_error("case x in a) b;;"), "syntax error: unexpected end of file");
+        assert_eq!(parse_error("case x in a b;; esac"), "syntax error near unexpected token `b'");
+    }
+
+    #[test]
+    fn parses_redirections() {
+        let list = parse_line("cmd <in arg 2>>log >&2 &>all");
+        let command = &list.items[0].and_or.first.commands[0];
+        assert_eq!(words(command), ["cmd", "arg"]);
+        let redirects: Vec<(i32, RedirectOp, &str)> = command
+            .redirects
+            .iter()
+            .map(|redirect| (redirect.fd, redirect.op.clone(), redirect.target.as_str()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                (0, RedirectOp::Read, "in"),
+                (2, RedirectOp::Append, "log"),
+                (1, RedirectOp::Duplicate, "2"),
+                (1, RedirectOp::Write, "all"),
+                (2, RedirectOp::Duplicate, "1"),
+            ]
+        );
+
+        // A command can be nothing but redirections
+        assert!(words(&parse_line("> f").items[0].and_or.first.commands[0]).is_empty());
+        assert_eq!(parse_error("a >"), "syntax error near unexpected token `newline'");
+        assert_eq!(parse_error("a > ;"), "syntax error near unexpected token `;'");
+    }
+
+    #[test]
+    fn fills_in_here_document_bodies() {
+        let bodies = VecDeque::from(["one\n".to_string(), "two\n".to_string()]);
+        let list = parse(&lex("a <<A; b <<-'B'"), bodies).unwrap();
+        let redirect = |i: usize| list.items[i].and_or.first.commands[0].redirects[0].clone();
+        assert_eq!(redirect(0).target, "one\n");
+        assert_eq!(redirect(1).target, "two\n");
+        assert_eq!(redirect(0).op, RedirectOp::HereDoc { strip_tabs: false, expand: true });
+        assert_eq!(redirect(1).op, RedirectOp::HereDoc { strip_tabs: true, expand: false });
+    }
+}
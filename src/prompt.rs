@@ -0,0 +1,174 @@
+//! Prompts, made from PS1 and PS2 each time one is shown. Backslash
+//! escapes in them stand for things about the shell and where it is, as
+//! in bash:
+//!
+//! - `\u` the user, `\h` the host up to its first dot and `\H` all of it
+//! - `\w` the working directory, with the home directory as `~`, and
+//!   `\W` its last component
+//! - `\$` a `#` for root and a `$` for anyone else
+//! - `\t` the time as 24-hour HH:MM:SS, `\T` 12-hour, `\@` 12-hour
+//!   HH:MM with am or pm, `\A` 24-hour HH:MM, `\d` the date as in
+//!   "Tue May 26" and `\D{format}` the time in a `strftime` format
+//! - `\s` the shell's name, `\v` its version and `\V` its release
+//! - `\g` the state of the git repository the shell is in, if it's in
+//!   one: the branch, a `*` if files have changed and how far it's ahead
+//!   of and behind its upstream, as in `main* ↑1↓2`. Empty if the shell
+//!   was built without the `git-prompt` feature
+//! - `\j` how many jobs there are and `\!` the number the command will
+//!   have in history
+//! - `\n` a newline, `\r` a carriage return, `\a` a bell, `\e` an escape,
+//!   `\nnn` the character with octal code nnn and `\\` a backslash
+//! - `\[` and `\]` around text that takes up no room on the screen, such
+//!   as the escape sequences that change colours, so the line editor can
+//!   tell where the cursor is
+//!
+//! Parameters and command substitutions in a prompt are expanded too,
+//! after the escapes, so `$?` shows the last command's status.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "git-prompt")]
+use crate::git;
+use crate::sys;
+use crate::Shell;
+
+/// What `\[` and `\]` become, for the line editor to find. They are the
+/// characters readline uses.
+pub const START_IGNORE: char = '\x01';
+pub const END_IGNORE: char = '\x02';
+
+/// `template` with its escapes and parameters expanded.
+pub fn expand(template: &str, shell: &mut Shell) -> String {
+    let mut prompt = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '$' {
+            prompt.push_str(&shell.expand_parameter(&mut chars));
+            continue;
+        }
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        let Some(c) = chars.next() else {
+            prompt.push('\\');
+            break;
+        };
+        match c {
+            'u' => prompt.push_str(&user(shell)),
+            'h' => prompt.push_str(host().split('.').next().unwrap_or("")),
+            'H' => prompt.push_str(&host()),
+            'w' => prompt.push_str(&working_dir(shell, false)),
+            'W' => prompt.push_str(&working_dir(shell, true)),
+            '$' => prompt.push(if sys::geteuid() == 0 { '#' } else { '$' }),
+            't' => prompt.push_str(&time("%H:%M:%S")),
+            'T' => prompt.push_str(&time("%I:%M:%S")),
+            '@' => prompt.push_str(&time("%I:%M %p")),
+            'A' => prompt.push_str(&time("%H:%M")),
+            'd' => prompt.push_str(&time("%a %b %d")),
+            'D' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let format: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                prompt.push_str(&time(if format.is_empty() { "%X" } else { &format }));
+            }
+            's' => prompt.push_str(Path::new(&shell.name).file_name().and_then(|name| name.to_str()).unwrap_or(&shell.name)),
+            'v' => prompt.push_str(env!("CARGO_PKG_VERSION").rsplit_once('.').map_or(env!("CARGO_PKG_VERSION"), |(version, _)| version)),
+            'V' => prompt.push_str(env!("CARGO_PKG_VERSION")),
+            #[cfg(feature = "git-prompt")]
+            'g' => prompt.push_str(&git::status(&shell.cwd).unwrap_or_default()),
+            #[cfg(not(feature = "git-prompt"))]
+            'g' => {}
+            'j' => prompt.push_str(&shell.jobs.iter().count().to_string()),
+            '!' => prompt.push_str(&(shell.history.entries().len() + 1).to_string()),
+            'n' => prompt.push('\n'),
+            'r' => prompt.push('\r'),
+            'a' => prompt.push('\x07'),
+            'e' => prompt.push('\x1b'),
+            '\\' => prompt.push('\\'),
+            '[' => prompt.push(START_IGNORE),
+            ']' => prompt.push(END_IGNORE),
+            '0'..='7' => {
+                let mut code = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => code = code * 8 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                }
+                prompt.extend(char::from_u32(code));
+            }
+            c => {
+                prompt.push('\\');
+                prompt.push(c);
+            }
+        }
+    }
+    if let Some(error) = shell.expansion_error.take() {
+        shell.report(&error);
+    }
+    prompt
+}
+
+/// `prompt` as it's written to the terminal, without the marks around
+/// what takes up no room.
+pub fn printed(prompt: &str) -> String {
+    prompt.chars().filter(|&c| c != START_IGNORE && c != END_IGNORE).collect()
+}
+
+/// The characters of `prompt` that take up room on the screen.
+pub fn visible(prompt: &str) -> String {
+    let mut ignoring = false;
+    prompt
+        .chars()
+        .filter(|&c| match c {
+            START_IGNORE => {
+                ignoring = true;
+                false
+            }
+            END_IGNORE => {
+                ignoring = false;
+                false
+            }
+            _ => !ignoring,
+        })
+        .collect()
+}
+
+fn user(shell: &Shell) -> String {
+    shell
+        .parameter("USER")
+        .filter(|user| !user.is_empty())
+        .or_else(|| sys::user_name(sys::geteuid()))
+        .unwrap_or_default()
+}
+
+fn host() -> String {
+    sys::host_name().unwrap_or_default()
+}
+
+/// The working directory with the home directory as `~`, or with `last`
+/// only its last component.
+fn working_dir(shell: &Shell, last: bool) -> String {
+    let cwd = &shell.cwd;
+    let home = shell.parameter("HOME").filter(|home| !home.is_empty() && home != "/");
+    if let Some(rest) = home.as_ref().and_then(|home| cwd.strip_prefix(home).ok()) {
+        if rest.as_os_str().is_empty() {
+            return "~".to_string();
+        }
+        if !last {
+            return format!("~/{}", rest.display());
+        }
+    }
+    match (last, cwd.file_name()) {
+        (true, Some(name)) => name.to_string_lossy().into_owned(),
+        _ => cwd.display().to_string(),
+    }
+}
+
+/// The time now in a `strftime` format.
+fn time(format: &str) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    sys::format_time(now, format)
+}
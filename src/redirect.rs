@@ -0,0 +1,269 @@
+//! Parsing of redirection operators and the per-command fd table they
+//! build up.
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::output;
+use crate::sys;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedirectOp {
+    /// `>`: truncate and write, unless noclobber forbids it
+    Write,
+    /// `>|`: truncate and write even with noclobber set
+    Clobber,
+    /// `>>`: append
+    Append,
+    /// `>&` and `<&`: duplicate another descriptor
+    Duplicate,
+    /// `<`: read from a file
+    Read,
+    /// `<<` and `<<-`: here-document. The target starts out as the
+    /// delimiter and is replaced by the collected body before the
+    /// redirection is applied. The body is expanded unless the delimiter
+    /// had quotes in it.
+    HereDoc { strip_tabs: bool, expand: bool },
+    /// `<<<`: here-string
+    HereString,
+}
+
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub fd: i32,
+    pub op: RedirectOp,
+    pub target: String,
+}
+
+/// Operators in the order they must be tried, with the descriptor each
+/// applies to when no number is given.
+const OPERATORS: &[(&str, RedirectOp, i32)] = &[
+    ("<<<", RedirectOp::HereString, 0),
+    ("<<-", RedirectOp::HereDoc { strip_tabs: true, expand: true }, 0),
+    ("<<", RedirectOp::HereDoc { strip_tabs: false, expand: true }, 0),
+    ("<&", RedirectOp::Duplicate, 0),
+    ("<", RedirectOp::Read, 0),
+    (">>", RedirectOp::Append, 1),
+    (">&", RedirectOp::Duplicate, 1),
+    (">|", RedirectOp::Clobber, 1),
+    (">", RedirectOp::Write, 1),
+];
+
+/// Recognizes a redirection operator token such as `>`, `2>>`, `&>`,
+/// `3>&1`, `4<`, `<<` or `<<<`. Returns the redirections it stands for;
+/// when the target is not part of the token it is left empty and must be
+/// taken from the next word.
+pub fn parse(token: &str) -> Option<Vec<Redirect>> {
+    if let Some(rest) = token.strip_prefix('&') {
+        // `&> file` is `> file 2>&1`
+        let op = match rest {
+            ">" => RedirectOp::Write,
+            ">>" => RedirectOp::Append,
+            _ => return None,
+        };
+        return Some(vec![
+            Redirect { fd: 1, op, target: String::new() },
+            Redirect { fd: 2, op: RedirectOp::Duplicate, target: "1".to_string() },
+        ]);
+    }
+
+    let digits = token.chars().take_while(|c| c.is_ascii_digit()).count();
+    let (number, rest) = token.split_at(digits);
+    let (op, default_fd, target) = OPERATORS.iter().find_map(|(symbol, op, fd)| {
+        rest.strip_prefix(symbol).map(|target| (op.clone(), *fd, target))
+    })?;
+
+    let inline_target = matches!(
+        op,
+        RedirectOp::Duplicate | RedirectOp::HereDoc { .. } | RedirectOp::HereString
+    );
+    if !inline_target && !target.is_empty() {
+        return None;
+    }
+
+    let fd = match number {
+        "" => default_fd,
+        number => number.parse().ok()?,
+    };
+    Some(vec![Redirect { fd, op, target: target.to_string() }])
+}
+
+/// Opens a new temporary file for reading and writing. It is unlinked
+/// straight away, so it goes away once closed.
+pub fn temp_file() -> io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!("shellob-{}-{}", sys::getpid(), COUNT.fetch_add(1, Ordering::Relaxed));
+    let path = env::temp_dir().join(name);
+    let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+    let _ = fs::remove_file(&path);
+    Ok(file)
+}
+
+/// Returns a file that yields `data`. The data goes in a temporary file
+/// rather than a pipe, so nothing has to stay behind to write it: a
+/// command may be exec'd in place of the process that opened it.
+fn feed(data: Vec<u8>) -> io::Result<File> {
+    let mut file = temp_file()?;
+    file.write_all(&data)?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// The files a command's descriptors point at. Descriptors missing from
+/// the table are inherited from the shell unchanged.
+#[derive(Default)]
+pub struct FdTable {
+    fds: BTreeMap<i32, File>,
+}
+
+impl FdTable {
+    /// Opens the target of `redirect` and records it in the table. With
+    /// `noclobber`, `>` refuses to truncate an existing regular file. A
+    /// descriptor duplicated from one this table doesn't have comes from
+    /// `inherited`, the shell's own, if it's there.
+    pub fn apply(&mut self, redirect: &Redirect, noclobber: bool, inherited: &FdTable) -> io::Result<()> {
+        let file = match redirect.op {
+            RedirectOp::Write if noclobber && Path::new(&redirect.target).is_file() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "cannot overwrite existing file",
+                ));
+            }
+            RedirectOp::Write | RedirectOp::Clobber => File::create(&redirect.target)?,
+            RedirectOp::Append => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&redirect.target)?,
+            RedirectOp::Read => File::open(&redirect.target)?,
+            RedirectOp::HereDoc { .. } => feed(redirect.target.clone().into_bytes())?,
+            RedirectOp::HereString => feed(format!("{}\n", redirect.target).into_bytes())?,
+            RedirectOp::Duplicate => {
+                let source: i32 = redirect.target.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "ambiguous redirect")
+                })?;
+                self.duplicate(source, inherited)?
+            }
+        };
+        self.fds.insert(redirect.fd, file);
+        Ok(())
+    }
+
+    fn duplicate(&self, source: i32, inherited: &FdTable) -> io::Result<File> {
+        match self.fds.get(&source).or_else(|| inherited.fds.get(&source)) {
+            Some(file) => file.try_clone(),
+            None => {
+                let fd = sys::dup(source)?;
+                Ok(unsafe { File::from_raw_fd(fd) })
+            }
+        }
+    }
+
+    pub fn get(&self, fd: i32) -> Option<&File> {
+        self.fds.get(&fd)
+    }
+
+    pub fn insert(&mut self, fd: i32, file: File) {
+        self.fds.insert(fd, file);
+    }
+
+    pub fn take(&mut self, fd: i32) -> Option<File> {
+        self.fds.remove(&fd)
+    }
+
+    /// Puts `other`'s entries in place of this table's, returning what
+    /// they replaced for `restore` to put back.
+    pub fn overlay(&mut self, other: FdTable) -> Vec<(i32, Option<File>)> {
+        other.fds.into_iter().map(|(fd, file)| (fd, self.fds.insert(fd, file))).collect()
+    }
+
+    /// Undoes an `overlay`.
+    pub fn restore(&mut self, saved: Vec<(i32, Option<File>)>) {
+        for (fd, file) in saved {
+            match file {
+                Some(file) => self.fds.insert(fd, file),
+                None => self.fds.remove(&fd),
+            };
+        }
+    }
+
+    /// The descriptors of a command with `redirections` run by a shell
+    /// whose own are in this table: `redirections`' entries, and copies
+    /// of this table's that they leave alone.
+    pub fn inherited_by(&self, mut redirections: FdTable) -> io::Result<FdTable> {
+        for (&fd, file) in &self.fds {
+            if let Entry::Vacant(entry) = redirections.fds.entry(fd) {
+                entry.insert(file.try_clone()?);
+            }
+        }
+        Ok(redirections)
+    }
+
+    /// A writer for the shell's stdout or stderr, `fd` 1 or 2: the file
+    /// standing in for it, or else the process's own. Buffered stdout is
+    /// flushed before anything goes to stderr, which is often the same
+    /// file.
+    pub fn writer(&self, fd: i32) -> Box<dyn Write + '_> {
+        match (self.fds.get(&fd), fd) {
+            (Some(file), _) => Box::new(file),
+            (None, 2) => {
+                output::flush();
+                Box::new(io::stderr())
+            }
+            (None, _) => Box::new(output::Stdout),
+        }
+    }
+
+    /// Copies every table entry onto a descriptor above the range user
+    /// redirections use, so installing one entry can't clobber the file
+    /// behind another.
+    fn sources(&self) -> io::Result<Vec<(i32, File)>> {
+        self.fds
+            .iter()
+            .map(|(&fd, file)| {
+                let copy = sys::dup(file.as_raw_fd())?;
+                Ok((fd, unsafe { File::from_raw_fd(copy) }))
+            })
+            .collect()
+    }
+
+    /// Arranges for a child to get the remaining table entries on their
+    /// descriptor numbers. Standard streams should already have been
+    /// taken and handed to the `Command` directly.
+    pub fn install(&self, cmd: &mut Command) -> io::Result<()> {
+        let sources = self.sources()?;
+        if sources.is_empty() {
+            return Ok(());
+        }
+        unsafe {
+            cmd.pre_exec(move || {
+                for (fd, file) in &sources {
+                    let raw = file.as_raw_fd();
+                    if raw == *fd {
+                        sys::set_cloexec(raw, false)?;
+                    } else {
+                        sys::dup2(raw, *fd)?;
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Points the process's own descriptors at the table's files for
+    /// good, in a forked copy of the shell about to run a command.
+    pub fn install_here(&self) -> io::Result<()> {
+        for (fd, file) in self.sources()? {
+            sys::dup2(file.as_raw_fd(), fd)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! Signal handling for the interactive shell.
+//!
+//! The shell catches SIGINT so Ctrl-C can abandon the line being typed,
+//! catches SIGCHLD to learn when background jobs need reaping, and
+//! ignores the job-control signals so it can't be suspended or
+//! stopped for touching the terminal while it hands the terminal between
+//! jobs. Children get the default dispositions back before exec.
+//!
+//! `trap` can catch other signals too, or ignore them. Caught ones are
+//! only noted here, for the shell to run their commands between
+//! commands of its own.
+
+use std::io;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::sys::{self, SigHandler};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static CHILD_CHANGED: AtomicBool = AtomicBool::new(false);
+/// The signals `trap` set something for, a bit for each
+static TRAPS: AtomicU64 = AtomicU64::new(0);
+/// The ones of those it catches rather than ignores
+static CAUGHT: AtomicU64 = AtomicU64::new(0);
+/// The caught signals that have arrived since they were last taken
+static PENDING: AtomicU64 = AtomicU64::new(0);
+/// Whether the shell has put its own handlers in place
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// The signals' names without `SIG`, the first for signal 1
+pub const NAMES: [&str; 31] = [
+    "HUP", "INT", "QUIT", "ILL", "TRAP", "ABRT", "BUS", "FPE", "KILL", "USR1", "SEGV", "USR2", "PIPE", "ALRM", "TERM",
+    "STKFLT", "CHLD", "CONT", "STOP", "TSTP", "TTIN", "TTOU", "URG", "XCPU", "XFSZ", "VTALRM", "PROF", "WINCH", "IO",
+    "PWR", "SYS",
+];
+
+/// Signals the shell ignores for itself
+const JOB_CONTROL_SIGNALS: [c_int; 4] = [sys::SIGQUIT, sys::SIGTSTP, sys::SIGTTIN, sys::SIGTTOU];
+
+extern "C" fn on_sigint(_: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_sigchld(_: c_int) {
+    CHILD_CHANGED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_trapped(sig: c_int) {
+    PENDING.fetch_or(1 << sig, Ordering::SeqCst);
+    // The shell still needs to know about these itself
+    match sig {
+        sys::SIGINT => on_sigint(sig),
+        sys::SIGCHLD => on_sigchld(sig),
+        _ => {}
+    }
+}
+
+/// The shell's own handling of `sig`, once `install` has put it in place.
+fn own_handler(sig: c_int) -> SigHandler {
+    match sig {
+        _ if !INSTALLED.load(Ordering::SeqCst) => SigHandler::Default,
+        sys::SIGINT => SigHandler::Catch(on_sigint),
+        sys::SIGCHLD => SigHandler::Catch(on_sigchld),
+        _ if JOB_CONTROL_SIGNALS.contains(&sig) => SigHandler::Ignore,
+        _ => SigHandler::Default,
+    }
+}
+
+/// Puts the shell's own handlers in place, leaving alone the signals
+/// `trap` already set something for.
+pub fn install() -> io::Result<()> {
+    INSTALLED.store(true, Ordering::SeqCst);
+    let traps = TRAPS.load(Ordering::SeqCst);
+    for sig in [sys::SIGINT, sys::SIGCHLD].into_iter().chain(JOB_CONTROL_SIGNALS) {
+        if traps & 1 << sig == 0 {
+            sys::signal(sig, own_handler(sig))?;
+        }
+    }
+    Ok(())
+}
+
+/// Undoes `install`, and the catching of signals by `trap`, in a freshly
+/// forked child. Signals `trap` ignores stay ignored. Only makes
+/// async-signal-safe calls, so it can run between fork and exec.
+pub fn restore_defaults() -> io::Result<()> {
+    let (traps, caught) = (TRAPS.load(Ordering::SeqCst), CAUGHT.load(Ordering::SeqCst));
+    for sig in 1..=NAMES.len() as c_int {
+        let own = matches!(sig, sys::SIGINT | sys::SIGCHLD) || JOB_CONTROL_SIGNALS.contains(&sig);
+        if caught & 1 << sig != 0 || own && traps & 1 << sig == 0 {
+            sys::signal(sig, SigHandler::Default)?;
+        }
+    }
+    Ok(())
+}
+
+/// What `trap` does with a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    /// Catches it, for the shell to run the trap's commands
+    Catch,
+    Ignore,
+    /// Goes back to what the shell does with it without a trap
+    Reset,
+}
+
+/// Sets what happens when `sig` arrives.
+pub fn trap(sig: c_int, trap: Trap) -> io::Result<()> {
+    let handler = match trap {
+        Trap::Catch => SigHandler::Catch(on_trapped),
+        Trap::Ignore => SigHandler::Ignore,
+        Trap::Reset => own_handler(sig),
+    };
+    sys::signal(sig, handler)?;
+    let set = |mask: &AtomicU64, on: bool| match on {
+        true => mask.fetch_or(1 << sig, Ordering::SeqCst),
+        false => mask.fetch_and(!(1 << sig), Ordering::SeqCst),
+    };
+    set(&TRAPS, trap != Trap::Reset);
+    set(&CAUGHT, trap == Trap::Catch);
+    Ok(())
+}
+
+/// The caught signals that arrived since the last call, lowest first,
+/// clearing them.
+pub fn take_trapped() -> Vec<c_int> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    (1..=NAMES.len() as c_int).filter(|sig| pending & 1 << sig != 0).collect()
+}
+
+/// The number of the signal named `name`, with or without `SIG` and in
+/// any case, or given by its number.
+pub fn number(name: &str) -> Option<c_int> {
+    if let Ok(number) = name.parse::<c_int>() {
+        return (1..=NAMES.len() as c_int).contains(&number).then_some(number);
+    }
+    let name = name.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    NAMES.iter().position(|known| *known == name).map(|i| i as c_int + 1)
+}
+
+/// The name of signal `sig`, with `SIG` in front.
+pub fn name(sig: c_int) -> String {
+    format!("SIG{}", NAMES[sig as usize - 1])
+}
+
+/// Returns whether SIGINT arrived since the last call, clearing the flag.
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+/// Returns whether a child changed state since the last call, clearing
+/// the flag.
+pub fn take_child_changed() -> bool {
+    CHILD_CHANGED.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,462 @@
+//! Thin bindings to the handful of libc calls the standard library doesn't
+//! expose.
+//!
+//! The struct layouts and constants below are glibc's on 64-bit x86 and
+//! ARM Linux, copied by hand rather than taken from a libc crate. Other
+//! targets number things differently (SIGCHLD is 18 on MIPS, TIOCGWINSZ
+//! isn't 0x5413 on PowerPC), so building for one is refused outright
+//! instead of compiling into a shell that misreads every wait status.
+
+#[cfg(not(all(
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+compile_error!("shellob's system bindings only describe glibc on x86_64 and aarch64 Linux");
+
+use std::fs::File;
+use std::io;
+use std::time::Duration;
+use std::os::unix::io::FromRawFd;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_ulong, c_void};
+
+extern "C" {
+    #[link_name = "fcntl"]
+    fn c_fcntl(fd: c_int, cmd: c_int, arg: c_int) -> c_int;
+    #[link_name = "dup2"]
+    fn c_dup2(old: c_int, new: c_int) -> c_int;
+    #[link_name = "close"]
+    fn c_close(fd: c_int) -> c_int;
+    #[link_name = "pipe2"]
+    fn c_pipe2(fds: *mut c_int, flags: c_int) -> c_int;
+    #[link_name = "read"]
+    fn c_read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+    #[link_name = "isatty"]
+    fn c_isatty(fd: c_int) -> c_int;
+    #[link_name = "getpgrp"]
+    fn c_getpgrp() -> c_int;
+    #[link_name = "setpgid"]
+    fn c_setpgid(pid: c_int, pgid: c_int) -> c_int;
+    #[link_name = "tcgetpgrp"]
+    fn c_tcgetpgrp(fd: c_int) -> c_int;
+    #[link_name = "tcgetattr"]
+    fn c_tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+    #[link_name = "tcsetattr"]
+    fn c_tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+    #[link_name = "tcsetpgrp"]
+    fn c_tcsetpgrp(fd: c_int, pgid: c_int) -> c_int;
+    #[link_name = "fork"]
+    fn c_fork() -> c_int;
+    #[link_name = "kill"]
+    fn c_kill(pid: c_int, sig: c_int) -> c_int;
+    #[link_name = "waitpid"]
+    fn c_waitpid(pid: c_int, status: *mut c_int, options: c_int) -> c_int;
+    #[link_name = "getpwnam"]
+    fn c_getpwnam(name: *const c_char) -> *const Passwd;
+    #[link_name = "getpwuid"]
+    fn c_getpwuid(uid: u32) -> *const Passwd;
+    #[link_name = "geteuid"]
+    fn c_geteuid() -> u32;
+    #[link_name = "gethostname"]
+    fn c_gethostname(name: *mut c_char, len: usize) -> c_int;
+    #[link_name = "sigaction"]
+    fn c_sigaction(sig: c_int, act: *const SigAction, old: *mut SigAction) -> c_int;
+    #[link_name = "flock"]
+    fn c_flock(fd: c_int, operation: c_int) -> c_int;
+    #[link_name = "poll"]
+    fn c_poll(fds: *mut PollFd, count: c_ulong, timeout: c_int) -> c_int;
+    #[link_name = "localtime_r"]
+    fn c_localtime_r(time: *const i64, tm: *mut Tm) -> *mut Tm;
+    #[link_name = "strftime"]
+    fn c_strftime(buf: *mut c_char, max: usize, format: *const c_char, tm: *const Tm) -> usize;
+    #[link_name = "getrusage"]
+    fn c_getrusage(who: c_int, usage: *mut Rusage) -> c_int;
+    #[link_name = "ioctl"]
+    fn c_ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+// glibc's `struct sigaction` layout
+#[repr(C)]
+struct SigAction {
+    sa_handler: usize,
+    sa_mask: [u64; 16],
+    sa_flags: c_int,
+    sa_restorer: usize,
+}
+
+// glibc's `struct passwd` layout
+#[repr(C)]
+struct Passwd {
+    pw_name: *const c_char,
+    pw_passwd: *const c_char,
+    pw_uid: u32,
+    pw_gid: u32,
+    pw_gecos: *const c_char,
+    pw_dir: *const c_char,
+    pw_shell: *const c_char,
+}
+
+// glibc's `struct termios` layout
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; 32],
+    pub c_ispeed: u32,
+    pub c_ospeed: u32,
+}
+
+// glibc's `struct rusage` layout: the two times, then fourteen counters
+#[repr(C)]
+struct Rusage {
+    ru_utime: Timeval,
+    ru_stime: Timeval,
+    counters: [std::os::raw::c_long; 14],
+}
+
+#[repr(C)]
+struct Timeval {
+    tv_sec: i64,
+    tv_usec: i64,
+}
+
+const RUSAGE_SELF: c_int = 0;
+const RUSAGE_CHILDREN: c_int = -1;
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: i16,
+    revents: i16,
+}
+
+const POLLIN: i16 = 1;
+
+// The `struct winsize` TIOCGWINSZ fills in
+#[repr(C)]
+#[derive(Default)]
+struct WinSize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+// glibc's `struct tm` layout
+#[repr(C)]
+struct Tm {
+    tm_sec: c_int,
+    tm_min: c_int,
+    tm_hour: c_int,
+    tm_mday: c_int,
+    tm_mon: c_int,
+    tm_year: c_int,
+    tm_wday: c_int,
+    tm_yday: c_int,
+    tm_isdst: c_int,
+    tm_gmtoff: i64,
+    tm_zone: *const c_char,
+}
+
+// Catch a layout that has drifted from the C definition
+const _: () = {
+    use std::mem::size_of;
+    assert!(size_of::<SigAction>() == 152);
+    assert!(size_of::<Passwd>() == 48);
+    assert!(size_of::<Termios>() == 60);
+    assert!(size_of::<Rusage>() == 144);
+    assert!(size_of::<Tm>() == 56);
+    assert!(size_of::<WinSize>() == 8);
+};
+
+const TCSADRAIN: c_int = 1;
+const TIOCGWINSZ: c_ulong = 0x5413;
+
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const IXON: u32 = 0o2000;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+pub const IEXTEN: u32 = 0o100000;
+
+pub const SIGHUP: c_int = 1;
+pub const SIGINT: c_int = 2;
+pub const SIGKILL: c_int = 9;
+pub const SIGSEGV: c_int = 11;
+pub const SIGPIPE: c_int = 13;
+pub const SIGTERM: c_int = 15;
+pub const SIGCHLD: c_int = 17;
+pub const SIGCONT: c_int = 18;
+pub const SIGQUIT: c_int = 3;
+pub const SIGTSTP: c_int = 20;
+pub const SIGTTIN: c_int = 21;
+pub const SIGTTOU: c_int = 22;
+
+pub const LOCK_SH: c_int = 1;
+pub const LOCK_EX: c_int = 2;
+
+pub const WNOHANG: c_int = 1;
+pub const WUNTRACED: c_int = 2;
+pub const WCONTINUED: c_int = 8;
+
+/// How a child changed state, decoded from a `waitpid` status word.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WaitStatus {
+    Exited(i32),
+    Signaled(i32),
+    Stopped(i32),
+    Continued,
+}
+
+fn check(ret: c_int) -> io::Result<c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+const F_SETFD: c_int = 2;
+const F_DUPFD_CLOEXEC: c_int = 1030;
+const FD_CLOEXEC: c_int = 1;
+const O_CLOEXEC: c_int = 0o2000000;
+
+/// Lowest descriptor the shell uses for its own copies, leaving 0-9 free
+/// for user redirections.
+const FIRST_INTERNAL_FD: c_int = 10;
+
+/// Duplicates `fd` onto a descriptor of at least 10. The copy is
+/// close-on-exec so it doesn't leak into spawned children.
+pub fn dup(fd: c_int) -> io::Result<c_int> {
+    check(unsafe { c_fcntl(fd, F_DUPFD_CLOEXEC, FIRST_INTERNAL_FD) })
+}
+
+pub fn set_cloexec(fd: c_int, cloexec: bool) -> io::Result<()> {
+    let flags = if cloexec { FD_CLOEXEC } else { 0 };
+    check(unsafe { c_fcntl(fd, F_SETFD, flags) }).map(|_| ())
+}
+
+pub fn dup2(old: c_int, new: c_int) -> io::Result<c_int> {
+    check(unsafe { c_dup2(old, new) })
+}
+
+pub fn close(fd: c_int) -> io::Result<()> {
+    check(unsafe { c_close(fd) }).map(|_| ())
+}
+
+/// Creates a close-on-exec pipe, returning its (read, write) ends.
+pub fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0 as c_int; 2];
+    check(unsafe { c_pipe2(fds.as_mut_ptr(), O_CLOEXEC) })?;
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// Reads from `fd` once, without retrying on EINTR so callers can react
+/// to signals.
+pub fn read(fd: c_int, buf: &mut [u8]) -> io::Result<usize> {
+    let ret = unsafe { c_read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+pub enum SigHandler {
+    Default,
+    Ignore,
+    Catch(extern "C" fn(c_int)),
+}
+
+/// Sets the disposition of `sig`. SA_RESTART is deliberately left off so
+/// blocking reads return EINTR when a caught signal arrives.
+pub fn signal(sig: c_int, handler: SigHandler) -> io::Result<()> {
+    let action = SigAction {
+        sa_handler: match handler {
+            SigHandler::Default => 0,
+            SigHandler::Ignore => 1,
+            SigHandler::Catch(handler) => handler as usize,
+        },
+        sa_mask: [0; 16],
+        sa_flags: 0,
+        sa_restorer: 0,
+    };
+    check(unsafe { c_sigaction(sig, &action, std::ptr::null_mut()) }).map(|_| ())
+}
+
+pub fn isatty(fd: c_int) -> bool {
+    unsafe { c_isatty(fd) == 1 }
+}
+
+pub fn getpgrp() -> i32 {
+    unsafe { c_getpgrp() }
+}
+
+pub fn getpid() -> i32 {
+    std::process::id() as i32
+}
+
+pub fn geteuid() -> u32 {
+    unsafe { c_geteuid() }
+}
+
+/// The machine's name on the network.
+pub fn host_name() -> io::Result<String> {
+    let mut buf = [0u8; 256];
+    check(unsafe { c_gethostname(buf.as_mut_ptr().cast(), buf.len()) })?;
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// Forks the shell. Returns 0 in the child and the child's pid in the
+/// parent. Only the calling thread is carried over into the child.
+pub fn fork() -> io::Result<i32> {
+    check(unsafe { c_fork() })
+}
+
+pub fn setpgid(pid: i32, pgid: i32) -> io::Result<()> {
+    check(unsafe { c_setpgid(pid, pgid) }).map(|_| ())
+}
+
+pub fn tcgetpgrp(fd: c_int) -> io::Result<i32> {
+    check(unsafe { c_tcgetpgrp(fd) })
+}
+
+pub fn tcgetattr(fd: c_int) -> io::Result<Termios> {
+    let mut termios = std::mem::MaybeUninit::<Termios>::uninit();
+    check(unsafe { c_tcgetattr(fd, termios.as_mut_ptr()) })?;
+    Ok(unsafe { termios.assume_init() })
+}
+
+/// Applies terminal modes once pending output has been written.
+pub fn tcsetattr(fd: c_int, termios: &Termios) -> io::Result<()> {
+    check(unsafe { c_tcsetattr(fd, TCSADRAIN, termios) }).map(|_| ())
+}
+
+/// Locks the file open on `fd`, waiting for other processes' locks to go.
+/// The lock goes when the file is closed.
+pub fn flock(fd: c_int, operation: c_int) -> io::Result<()> {
+    loop {
+        match check(unsafe { c_flock(fd, operation) }) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result.map(|_| ()),
+        }
+    }
+}
+
+/// Waits up to `timeout` milliseconds for input on `fd`, returning
+/// whether there is some. Like `read`, doesn't retry on EINTR.
+pub fn poll_input(fd: c_int, timeout: c_int) -> io::Result<bool> {
+    let mut poll = PollFd { fd, events: POLLIN, revents: 0 };
+    check(unsafe { c_poll(&mut poll, 1, timeout) }).map(|ready| ready > 0)
+}
+
+/// The width of the terminal on `fd` in columns, if it is one and the
+/// kernel knows.
+pub fn columns(fd: c_int) -> Option<usize> {
+    let mut size = WinSize::default();
+    check(unsafe { c_ioctl(fd, TIOCGWINSZ, &mut size as *mut WinSize) }).ok()?;
+    (size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+/// Formats `time`, in seconds since the epoch, as local time with the
+/// `strftime` conversions in `format`. Empty if it can't be formatted.
+pub fn format_time(time: u64, format: &str) -> String {
+    let Ok(format) = CString::new(format) else {
+        return String::new();
+    };
+    let time = time as i64;
+    let mut tm = std::mem::MaybeUninit::<Tm>::uninit();
+    if unsafe { c_localtime_r(&time, tm.as_mut_ptr()) }.is_null() {
+        return String::new();
+    }
+    let mut buf = [0u8; 256];
+    let len = unsafe { c_strftime(buf.as_mut_ptr().cast(), buf.len(), format.as_ptr(), tm.as_ptr()) };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Makes `pgid` the foreground process group of the terminal on `fd`.
+pub fn tcsetpgrp(fd: c_int, pgid: i32) -> io::Result<()> {
+    check(unsafe { c_tcsetpgrp(fd, pgid) }).map(|_| ())
+}
+
+pub fn kill(pid: i32, sig: c_int) -> io::Result<()> {
+    check(unsafe { c_kill(pid, sig) }).map(|_| ())
+}
+
+/// Waits for `pid` to change state, retrying on EINTR. Returns None if
+/// WNOHANG was given and nothing has changed yet.
+pub fn waitpid(pid: i32, options: c_int) -> io::Result<Option<WaitStatus>> {
+    let mut status: c_int = 0;
+    loop {
+        let ret = unsafe { c_waitpid(pid, &mut status, options) };
+        if ret == 0 {
+            return Ok(None);
+        }
+        if ret > 0 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    Ok(Some(if status == 0xffff {
+        WaitStatus::Continued
+    } else if status & 0xff == 0x7f {
+        WaitStatus::Stopped((status >> 8) & 0xff)
+    } else if status & 0x7f == 0 {
+        WaitStatus::Exited((status >> 8) & 0xff)
+    } else {
+        WaitStatus::Signaled(status & 0x7f)
+    }))
+}
+
+/// CPU time spent in user code and in the kernel.
+#[derive(Clone, Copy)]
+pub struct CpuTimes {
+    pub user: Duration,
+    pub system: Duration,
+}
+
+/// The CPU time used so far by the shell and the children it has waited
+/// for.
+pub fn cpu_times() -> CpuTimes {
+    let mut times = CpuTimes { user: Duration::ZERO, system: Duration::ZERO };
+    let duration = |time: &Timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    for who in [RUSAGE_SELF, RUSAGE_CHILDREN] {
+        let mut usage = std::mem::MaybeUninit::<Rusage>::uninit();
+        if unsafe { c_getrusage(who, usage.as_mut_ptr()) } == 0 {
+            let usage = unsafe { usage.assume_init() };
+            times.user += duration(&usage.ru_utime);
+            times.system += duration(&usage.ru_stime);
+        }
+    }
+    times
+}
+
+/// Looks up the name of the user with `uid` in the password database.
+pub fn user_name(uid: u32) -> Option<String> {
+    let entry = unsafe { c_getpwuid(uid) };
+    if entry.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr((*entry).pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Looks up the home directory of `user` in the password database.
+pub fn home_dir(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    let entry = unsafe { c_getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        return None;
+    }
+    let dir = unsafe { CStr::from_ptr((*entry).pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
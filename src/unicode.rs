@@ -0,0 +1,184 @@
+//! Character widths and grapheme clusters, enough for the line editor to
+//! move over what's typed a whole character at a time as the user sees
+//! it, and to know where a terminal puts it. The tables are rough cuts
+//! of Unicode's, covering the scripts and emoji likely to turn up on a
+//! command line.
+
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
+
+/// Marks that combine with the character before them, and other
+/// characters that take up no room.
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036f),
+    (0x0483, 0x0489),
+    (0x0591, 0x05bd),
+    (0x05bf, 0x05bf),
+    (0x05c1, 0x05c2),
+    (0x05c4, 0x05c5),
+    (0x05c7, 0x05c7),
+    (0x0610, 0x061a),
+    (0x064b, 0x065f),
+    (0x0670, 0x0670),
+    (0x06d6, 0x06dc),
+    (0x06df, 0x06e4),
+    (0x06e7, 0x06e8),
+    (0x06ea, 0x06ed),
+    (0x0900, 0x0902),
+    (0x093a, 0x093a),
+    (0x093c, 0x093c),
+    (0x0941, 0x0948),
+    (0x094d, 0x094d),
+    (0x0951, 0x0957),
+    (0x0962, 0x0963),
+    (0x0e31, 0x0e31),
+    (0x0e34, 0x0e3a),
+    (0x0e47, 0x0e4e),
+    (0x1160, 0x11ff),
+    (0x1ab0, 0x1aff),
+    (0x1dc0, 0x1dff),
+    (0x200b, 0x200f),
+    (0x202a, 0x202e),
+    (0x2060, 0x2064),
+    (0x20d0, 0x20ff),
+    (0xfe00, 0xfe0f),
+    (0xfe20, 0xfe2f),
+    (0xfeff, 0xfeff),
+    // Skin tone modifiers, which join the emoji before them
+    (0x1f3fb, 0x1f3ff),
+    (0xe0020, 0xe007f),
+    (0xe0100, 0xe01ef),
+];
+
+/// East Asian wide and fullwidth characters, and emoji, which take up two
+/// columns.
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115f),
+    (0x231a, 0x231b),
+    (0x2329, 0x232a),
+    (0x23e9, 0x23ec),
+    (0x23f0, 0x23f0),
+    (0x23f3, 0x23f3),
+    (0x25fd, 0x25fe),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267f, 0x267f),
+    (0x2693, 0x2693),
+    (0x26a1, 0x26a1),
+    (0x26aa, 0x26ab),
+    (0x26bd, 0x26be),
+    (0x26c4, 0x26c5),
+    (0x26ce, 0x26ce),
+    (0x26d4, 0x26d4),
+    (0x26ea, 0x26ea),
+    (0x26f2, 0x26f3),
+    (0x26f5, 0x26f5),
+    (0x26fa, 0x26fa),
+    (0x26fd, 0x26fd),
+    (0x2705, 0x2705),
+    (0x270a, 0x270b),
+    (0x2728, 0x2728),
+    (0x274c, 0x274c),
+    (0x274e, 0x274e),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27b0, 0x27b0),
+    (0x27bf, 0x27bf),
+    (0x2b1b, 0x2b1c),
+    (0x2b50, 0x2b50),
+    (0x2b55, 0x2b55),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xa960, 0xa97f),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe10, 0xfe19),
+    (0xfe30, 0xfe6f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x16fe0, 0x16fe4),
+    (0x17000, 0x18aff),
+    (0x1b000, 0x1b16f),
+    (0x1f004, 0x1f004),
+    (0x1f0cf, 0x1f0cf),
+    (0x1f18e, 0x1f18e),
+    (0x1f191, 0x1f19a),
+    (0x1f1e6, 0x1f1ff),
+    (0x1f200, 0x1f251),
+    (0x1f300, 0x1f64f),
+    (0x1f680, 0x1f6ff),
+    (0x1f7e0, 0x1f7eb),
+    (0x1f90c, 0x1f9ff),
+    (0x1fa70, 0x1faff),
+    (0x20000, 0x2fffd),
+    (0x30000, 0x3fffd),
+];
+
+fn in_table(c: char, table: &[(u32, u32)]) -> bool {
+    let c = c as u32;
+    table
+        .binary_search_by(|&(first, last)| match () {
+            _ if last < c => std::cmp::Ordering::Less,
+            _ if first > c => std::cmp::Ordering::Greater,
+            _ => std::cmp::Ordering::Equal,
+        })
+        .is_ok()
+}
+
+/// Whether `c` goes with the character before it rather than standing
+/// on its own.
+pub fn combining(c: char) -> bool {
+    in_table(c, ZERO_WIDTH)
+}
+
+/// How many columns a terminal gives `c`.
+pub fn width(c: char) -> usize {
+    if combining(c) {
+        0
+    } else if in_table(c, WIDE) {
+        2
+    } else {
+        1
+    }
+}
+
+/// How many columns a terminal gives `text`.
+pub fn text_width(text: &str) -> usize {
+    text.chars().map(width).sum()
+}
+
+fn regional_indicator(c: char) -> bool {
+    ('\u{1f1e6}'..='\u{1f1ff}').contains(&c)
+}
+
+/// Where the grapheme cluster starting at `pos` ends: past the marks that
+/// combine with it, the rest of an emoji sequence joined with zero width
+/// joiners, or the second of a pair of regional indicators (a flag).
+pub fn next_boundary(chars: &[char], pos: usize) -> usize {
+    if pos >= chars.len() {
+        return chars.len();
+    }
+    let mut end = pos + 1;
+    if regional_indicator(chars[pos]) && chars.get(end).copied().is_some_and(regional_indicator) {
+        end += 1;
+    }
+    while end < chars.len() && (combining(chars[end]) || chars[end - 1] == ZERO_WIDTH_JOINER) {
+        end += 1;
+    }
+    end
+}
+
+/// Where the grapheme cluster that ends at `pos` starts.
+pub fn previous_boundary(chars: &[char], pos: usize) -> usize {
+    let mut start = 0;
+    loop {
+        let next = next_boundary(chars, start);
+        if next >= pos {
+            return start;
+        }
+        start = next;
+    }
+}
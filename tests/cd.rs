@@ -0,0 +1,27 @@
+//! The logical working directory `cd` keeps, with the symlinks it went
+//! through, and what `pwd` and `$PWD` say it is.
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+
+use common::run;
+
+#[test]
+fn tracks_the_path_through_symlinks() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cd");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("real").join("sub")).unwrap();
+    symlink("real", dir.join("link")).unwrap();
+    let (link, real) = (dir.join("link"), fs::canonicalize(dir.join("real")).unwrap());
+
+    // In a subshell, so the tests' own working directory stays put
+    let script = format!("(cd {}/sub; pwd; echo $PWD; pwd -P; cd ..; pwd -L; cd -P {}; pwd)", link.display(), link.display());
+    let (status, stdout, _) = run(&script);
+    let expected = [link.join("sub"), link.join("sub"), real.join("sub"), link, real];
+    let expected: Vec<String> = expected.iter().map(|path| path.display().to_string()).collect();
+    assert_eq!(status, 0);
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+}
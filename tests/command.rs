@@ -0,0 +1,37 @@
+//! Running commands given with `-c` and the status the shell exits with.
+
+use std::process::{Command, Output, Stdio};
+
+/// Runs the `shellob` binary with `args` and nothing on stdin.
+fn shellob(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_shellob"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn runs_the_commands_given_with_c() {
+    let output = shellob(&["-c", "echo \"$0 $# $1 $2\"; echo two", "name", "a", "b c"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "name 2 a b c\ntwo\n");
+    assert_eq!(output.status.code(), Some(0));
+    // Nor any arguments without a name
+    let output = shellob(&["-c", "echo $#"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0\n");
+}
+
+#[test]
+fn exits_with_the_last_commands_status() {
+    assert_eq!(shellob(&["-c", "true; sh -c 'exit 3'"]).status.code(), Some(3));
+    assert_eq!(shellob(&["-c", "false; exit"]).status.code(), Some(1));
+    assert_eq!(shellob(&["-c", "exit 4; true"]).status.code(), Some(4));
+    assert_eq!(shellob(&["-c", "false; true"]).status.code(), Some(0));
+}
+
+#[test]
+fn needs_the_commands_to_run() {
+    let output = shellob(&["-c"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("shellob: -c: option requires an argument\n"));
+}
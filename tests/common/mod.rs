@@ -0,0 +1,37 @@
+//! Helpers shared by the integration tests.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use shellob::Shell;
+
+/// A writer tests can read back after handing a clone of it to a shell.
+#[derive(Clone, Default)]
+pub struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `script` in a fresh shell, returning its status, stdout and
+/// stderr.
+pub fn run(script: &str) -> (i32, String, String) {
+    let (stdout, stderr) = (Output::default(), Output::default());
+    let mut shell = Shell::new();
+    shell.set_stdout(stdout.clone());
+    shell.set_stderr(stderr.clone());
+    let status = shell.eval(script);
+    (status.code(), stdout.text(), stderr.text())
+}
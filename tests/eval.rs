@@ -0,0 +1,323 @@
+//! Running scripts through `Shell::eval` and checking what they write
+//! and the status they finish with.
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use common::{run, Output};
+use shellob::Shell;
+
+#[test]
+fn captures_builtin_output() {
+    assert_eq!(run("echo hello world"), (0, "hello world\n".to_string(), String::new()));
+}
+
+#[test]
+fn captures_external_command_output_in_order() {
+    let (status, stdout, _) = run("echo one; printf 'two\\n'; echo three; sh -c 'echo four'");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "one\ntwo\nthree\nfour\n");
+}
+
+#[test]
+fn returns_the_last_status() {
+    assert_eq!(run("true").0, 0);
+    assert_eq!(run("false").0, 1);
+    assert_eq!(run("false; true").0, 0);
+    assert_eq!(run("sh -c 'exit 7'").0, 7);
+    assert_eq!(run("! true").0, 1);
+}
+
+#[test]
+fn reports_errors_on_stderr() {
+    let (status, stdout, stderr) = run("no-such-command-here");
+    assert_eq!(status, 127);
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "shellob: no-such-command-here: command not found\n");
+
+    let (status, _, stderr) = run("echo (");
+    assert_eq!(status, 2);
+    assert!(stderr.contains("syntax error"), "{:?}", stderr);
+
+    let (status, _, stderr) = run("ls /no/such/dir");
+    assert_ne!(status, 0);
+    assert!(stderr.contains("/no/such/dir"), "{:?}", stderr);
+}
+
+#[test]
+fn redirects_within_captured_streams() {
+    let (_, stdout, stderr) = run("ls /no/such/dir 2>&1 | wc -l");
+    assert_eq!(stdout.trim(), "1");
+    assert_eq!(stderr, "");
+
+    let (_, stdout, stderr) = run("{ echo out; echo err >&2; } 2>/dev/null");
+    assert_eq!((stdout.as_str(), stderr.as_str()), ("out\n", ""));
+
+    let (_, stdout, stderr) = run("echo moved >&2");
+    assert_eq!((stdout.as_str(), stderr.as_str()), ("", "moved\n"));
+}
+
+#[test]
+fn runs_pipelines_and_substitutions() {
+    let (_, stdout, _) = run("echo $(echo inner) | tr a-z A-Z");
+    assert_eq!(stdout, "INNER\n");
+    let (_, stdout, _) = run("(echo sub; echo shell) | wc -l");
+    assert_eq!(stdout.trim(), "2");
+    let (_, stdout, _) = run("cat <<EOF\nbody\nline\nEOF\ncat <(echo process)");
+    assert_eq!(stdout, "body\nline\nprocess\n");
+}
+
+#[test]
+fn expands_here_documents_unless_the_delimiter_is_quoted() {
+    let body = "$x $(echo sub) `echo back` \\$x \"q\" 'q' \\a\n";
+    let (_, stdout, _) = run(&format!("x=val\ncat <<EOF\n{body}EOF"));
+    assert_eq!(stdout, "val sub back $x \"q\" 'q' \\a\n");
+    for delimiter in ["'EOF'", "\"EOF\"", "\\EOF", "E'O'F"] {
+        let (_, stdout, _) = run(&format!("x=val\ncat <<{delimiter}\n{body}EOF"));
+        assert_eq!(stdout, body, "{delimiter}");
+    }
+}
+
+#[test]
+fn keeps_state_between_evals() {
+    let stdout = Output::default();
+    let mut shell = Shell::new();
+    shell.set_stdout(stdout.clone());
+    assert!(shell.eval("greeting=hi; set -- a b c").success());
+    assert!(shell.eval("echo $greeting $# $2").success());
+    shell.eval("false");
+    shell.eval("echo $?");
+    assert_eq!(stdout.text(), "hi 3 b\n1\n");
+}
+
+#[test]
+fn shells_in_threads_keep_their_output_apart() {
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            thread::spawn(move || {
+                run(&format!("echo {i}; sh -c 'echo {i}'; echo {i} | cat"))
+            })
+        })
+        .collect();
+    for (i, thread) in threads.into_iter().enumerate() {
+        let (status, stdout, _) = thread.join().unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(stdout, format!("{i}\n{i}\n{i}\n"));
+    }
+}
+
+#[test]
+fn streams_large_stderr_without_hanging() {
+    // Far more than a pipe buffer holds, with nothing on stdout until
+    // it's all been written
+    let script = "sh -c 'head -c 4000000 /dev/zero | tr \"\\0\" x >&2; echo done'";
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || sender.send(run(script)));
+    let (status, stdout, stderr) = receiver.recv_timeout(Duration::from_secs(30)).expect("eval hung");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "done\n");
+    assert_eq!(stderr.len(), 4_000_000);
+    assert!(stderr.bytes().all(|b| b == b'x'));
+}
+
+#[test]
+fn joins_and_splits_positional_parameters_with_ifs() {
+    let script = [
+        "set -- 'a b' 'c:d' e",
+        "IFS=:",
+        "echo \"[$*]\"",
+        "printf '<%s>' $*; echo",
+        "printf '<%s>' $@; echo",
+        "printf '<%s>' \"$@\"; echo",
+        "x=$*; echo \"$x\"",
+        "IFS=",
+        "echo \"[$*]\"",
+        "printf '<%s>' $*; echo",
+    ];
+    let (_, stdout, _) = run(&script.join("\n"));
+    let expected = [
+        "[a b:c:d:e]",
+        "<a b><c><d><e>",
+        "<a b><c><d><e>",
+        "<a b><c:d><e>",
+        "a b:c:d:e",
+        "[a bc:de]",
+        "<a b><c:d><e>",
+    ];
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+
+    // Unset, IFS is space, tab and newline
+    let (_, stdout, _) = run("set -- 'a  b' c\nprintf '<%s>' $@; echo \"[$*]\"");
+    assert_eq!(stdout, "<a><b><c>[a  b c]\n");
+}
+
+#[test]
+fn records_pipeline_statuses() {
+    let (status, stdout, _) = run("sh -c 'exit 3' | false | true; echo \"$? $PIPESTATUS\"\necho | false\necho $PIPESTATUS");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "0 3 1 0\n0 1\n");
+
+    // A lone command, builtin or not, has a status of its own
+    let (_, stdout, _) = run("false | false; true; echo $PIPESTATUS; sh -c 'exit 4'; echo $PIPESTATUS");
+    assert_eq!(stdout, "0\n4\n");
+}
+
+#[test]
+fn pipefail_takes_the_last_failure() {
+    assert_eq!(run("sh -c 'exit 3' | false | true").0, 0);
+    assert_eq!(run("set -o pipefail; sh -c 'exit 3' | false | true").0, 1);
+    assert_eq!(run("set -o pipefail; sh -c 'exit 3' | true").0, 3);
+    assert_eq!(run("set -o pipefail; true | true").0, 0);
+    assert_eq!(run("set -o pipefail; ! false | true").0, 0);
+    assert_eq!(run("set -o pipefail; set +o pipefail; false | true").0, 0);
+}
+
+#[test]
+fn keeps_empty_quoted_words() {
+    let (_, stdout, _) = run("printf '<%s>' a \"\" b ''; echo");
+    assert_eq!(stdout, "<a><><b><>\n");
+    let (_, stdout, _) = run("set -- a '' b; echo $#; printf '<%s>' \"$@\"; echo");
+    assert_eq!(stdout, "3\n<a><><b>\n");
+
+    // Unless there's nothing in the quotes but a `$@` with no parameters
+    let (_, stdout, _) = run("printf '<%s>' x \"$@\" y; echo");
+    assert_eq!(stdout, "<x><y>\n");
+}
+
+#[test]
+fn splits_fields_around_empty_quoted_strings() {
+    let script = [
+        "empty=",
+        "printf '<%s>' \"\" \"$empty\" $empty; echo",
+        "b=' x y '",
+        "printf '<%s>' \"$empty\"$b; echo",
+        "printf '<%s>' $b\"\"; echo",
+        "printf '<%s>' \"a\"$b; echo",
+        "IFS=:",
+        "v='a::b:'",
+        "printf '<%s>' $v \"$v\"; echo",
+        "printf '<%s>' \"\"$v; echo",
+    ];
+    let (_, stdout, _) = run(&script.join("\n"));
+    let expected = [
+        "<><>",
+        "<><x><y>",
+        "<x><y><>",
+        "<a><x><y>",
+        "<a><><b><a::b:>",
+        "<a><><b>",
+    ];
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn substitutes_output_without_a_subshell() {
+    let script = [
+        "x=1",
+        "out=${ x=2; echo captured; echo; }",
+        "echo \"[$out] $x $?\"",
+        "echo \"${ printf 'a  b';}\" ${ printf 'c  d\\n'; }",
+        "status=${ false; }; echo $?",
+        "set -- a; count=${ set -- b c; echo $#; }; echo $count $# $1",
+        "echo ${unset:-${ y=3; echo default; }} $y",
+    ];
+    let (_, stdout, _) = run(&script.join("\n"));
+    let expected = ["[captured] 2 0", "a  b c d", "1", "2 2 b", "default 3"];
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+
+    // Unlike a subshell
+    let (_, stdout, _) = run("x=1; out=$(x=2; echo captured); echo $out $x");
+    assert_eq!(stdout, "captured 1\n");
+}
+
+#[test]
+fn counts_seconds_from_when_seconds_was_set() {
+    let (_, stdout, _) = run("echo $SECONDS; sleep 1; echo $SECONDS; SECONDS=100; echo $SECONDS; sleep 1; echo ${SECONDS}");
+    let seconds: Vec<u64> = stdout.lines().map(|line| line.parse().unwrap()).collect();
+    assert_eq!(seconds.len(), 4, "{:?}", stdout);
+    assert_eq!(seconds[0], 0);
+    assert!((1..100).contains(&seconds[1]), "{:?}", seconds);
+    assert_eq!(seconds[2], 100);
+    assert!(seconds[3] > 100, "{:?}", seconds);
+}
+
+#[test]
+fn draws_random_numbers_that_repeat_for_a_seed() {
+    let (_, stdout, _) = run("echo $RANDOM $RANDOM $RANDOM $RANDOM\nRANDOM=42; echo $RANDOM $RANDOM\nRANDOM=42; echo $RANDOM $RANDOM");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{:?}", stdout);
+    let numbers: Vec<u32> = lines[0].split(' ').map(|number| number.parse().unwrap()).collect();
+    assert!(numbers.iter().all(|number| *number <= 32767), "{:?}", numbers);
+    assert!(numbers.windows(2).any(|pair| pair[0] != pair[1]), "{:?}", numbers);
+    assert_eq!(lines[1], lines[2]);
+}
+
+#[test]
+fn numbers_lines_from_the_start_of_the_script() {
+    let (_, stdout, _) = run("echo $LINENO\n\necho a \\\nb\necho $LINENO; echo ${LINENO}");
+    assert_eq!(stdout, "1\na b\n5\n5\n");
+}
+
+#[test]
+fn waits_for_a_job_slot_past_maxjobs() {
+    let seconds = |script: &str| {
+        let (_, stdout, _) = run(script);
+        stdout.lines().last().unwrap().parse::<u64>().unwrap()
+    };
+    assert_eq!(seconds("sleep 1 & sleep 1 & sleep 1 & echo $SECONDS"), 0);
+    // The third job only starts once one of the first two is done
+    assert!(seconds("MAXJOBS=2; sleep 1 & sleep 1 & sleep 1 & echo $SECONDS") >= 1);
+}
+
+#[test]
+fn gives_prefix_assignments_only_to_the_command() {
+    let (_, stdout, _) = run("greeting=hi sh -c 'echo $greeting'; echo \"[$greeting]\"");
+    assert_eq!(stdout, "hi\n[]\n");
+    let (_, stdout, _) = run("export greeting=hi; greeting=bye sh -c 'echo $greeting'; sh -c 'echo $greeting'");
+    assert_eq!(stdout, "bye\nhi\n");
+}
+
+#[test]
+fn keeps_directory_changes_in_subshells() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("eval").join("subshell");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("real")).unwrap();
+    symlink("real", dir.join("link")).unwrap();
+    let link = dir.join("link");
+
+    // Commands run from the subshell get its logical directory, and
+    // redirections are relative to it
+    let script = format!("pwd; (cd {}; pwd; sh -c 'echo $PWD'; echo hi >out); pwd", link.display());
+    let (status, stdout, _) = run(&script);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(status, 0);
+    assert_eq!(lines.len(), 4, "{:?}", stdout);
+    assert_eq!(lines[1..3], [link.to_str().unwrap(); 2]);
+    assert_eq!(lines[0], lines[3]);
+    assert_eq!(fs::read_to_string(dir.join("real").join("out")).unwrap(), "hi\n");
+}
+
+#[test]
+fn runs_the_case_arms_that_match() {
+    let (_, stdout, _) = run("x=apple; case $x in a*) echo a;; *) echo other;; esac");
+    assert_eq!(stdout, "a\n");
+    // `;&` runs the next arm whatever its patterns, `;;&` tries them
+    let (_, stdout, _) = run("case apple in b*|ap*) echo ap;& c) echo c;; *) echo no;; esac");
+    assert_eq!(stdout, "ap\nc\n");
+    let (_, stdout, _) = run("case apple in *p*) echo p;;& b*) echo b;;& *e) echo e;;& *) echo any; esac");
+    assert_eq!(stdout, "p\ne\nany\n");
+
+    // Quoted pattern characters only match themselves, unlike those of
+    // an unquoted expansion
+    let (_, stdout, _) = run("p='a*'; case abc in \"$p\") echo quoted;; $p) echo pattern;; esac; case '*' in \\*) echo star;; esac");
+    assert_eq!(stdout, "pattern\nstar\n");
+
+    let (_, stdout, _) = run("false; case x in y) echo y;; esac; echo $?; case x in x) false;; esac; echo $?");
+    assert_eq!(stdout, "0\n1\n");
+}
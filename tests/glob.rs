@@ -0,0 +1,46 @@
+//! Expanding patterns against names with spaces, newlines and other
+//! characters the shell would otherwise split or take as options.
+
+mod common;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use common::run;
+
+/// A directory of its own for `test`, holding a file for each of `names`.
+fn fixture(test: &str, names: &[&str]) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("glob").join(test);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for name in names {
+        fs::write(dir.join(name), "").unwrap();
+    }
+    dir
+}
+
+#[test]
+fn keeps_each_name_one_word() {
+    let dir = fixture("words", &["a b", "new\nline", "-dash", "plain"]);
+    let (_, stdout, _) = run(&format!("(cd {}; printf '[%s]' *)", dir.display()));
+    assert_eq!(stdout, "[-dash][a b][new\nline][plain]");
+    let (_, stdout, _) = run(&format!("printf '[%s]' {}/*' '*", dir.display()));
+    assert_eq!(stdout, format!("[{}/a b]", dir.display()));
+}
+
+#[test]
+fn matches_dotfiles_only_with_a_dot() {
+    let dir = fixture("dotfiles", &[".hidden", "shown"]);
+    let (_, stdout, _) = run(&format!("(cd {}; echo *; echo .*; echo ?hidden)", dir.display()));
+    assert_eq!(stdout, "shown\n.hidden\n?hidden\n");
+}
+
+#[test]
+fn skips_names_that_are_not_utf8() {
+    let dir = fixture("utf8", &["fine"]);
+    fs::write(dir.join(OsStr::from_bytes(b"bad\xff")), "").unwrap();
+    let (_, stdout, _) = run(&format!("(cd {}; echo *)", dir.display()));
+    assert_eq!(stdout, "fine\n");
+}
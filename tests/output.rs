@@ -0,0 +1,65 @@
+//! Running scripts with the `shellob` binary, whose stdout is buffered,
+//! and checking that what it writes still comes out in order.
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Runs `script` as a script file with stdout and stderr going to the
+/// same file, returning what ended up in it.
+fn run_script(name: &str, script: &str) -> String {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("output");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.sh", name));
+    fs::write(&path, script).unwrap();
+    let log = File::create(dir.join(format!("{}.log", name))).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_shellob"))
+        .arg(&path)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone().unwrap())
+        .stderr(log)
+        .status()
+        .unwrap();
+    assert!(status.code().is_some());
+    fs::read_to_string(dir.join(format!("{}.log", name))).unwrap()
+}
+
+#[test]
+fn interleaves_builtins_and_external_commands() {
+    let script = "echo 1\nprintf '2\\n'\necho 3\nsh -c 'echo 4'\necho 5\n{ echo 6; cat; } </dev/null | cat\n\
+                  echo 7\n(echo 8)\necho $(echo 9)\necho 10";
+    let expected: Vec<String> = (1..=10).map(|n| n.to_string()).collect();
+    assert_eq!(run_script("interleaved", script).lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn keeps_stdout_in_order_with_stderr() {
+    let script = "echo 1\nno-such-command-here\necho 2\nls /no/such/dir\necho 3 >&2\necho 4\nexit 3";
+    let output = run_script("stderr", script);
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines.len(), 6, "{:?}", output);
+    assert_eq!(lines[0], "1");
+    assert!(lines[1].contains("command not found"), "{:?}", output);
+    assert_eq!(lines[2], "2");
+    assert!(lines[3].contains("/no/such/dir"), "{:?}", output);
+    assert_eq!(&lines[4..], ["3", "4"]);
+}
+
+#[test]
+fn flushes_many_lines_at_exit() {
+    let script = "set -- x\n".to_string() + &"echo $1\n".repeat(20_000);
+    let output = run_script("many", &script);
+    assert_eq!(output.len(), 40_000);
+}
+
+#[test]
+fn prints_the_version_and_features() {
+    let output = Command::new(env!("CARGO_BIN_EXE_shellob")).arg("--version").output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert!(lines.next().unwrap().starts_with(&format!("shellob {} (", env!("CARGO_PKG_VERSION"))));
+    let features = if cfg!(feature = "git-prompt") { "+git-prompt" } else { "-git-prompt" };
+    assert_eq!(lines.next(), Some(&*format!("features: {}", features)));
+    assert_eq!(lines.next(), None);
+    assert_eq!(run_script("version", "version\nshellob"), stdout.repeat(2));
+}
@@ -0,0 +1,130 @@
+//! SHELLOB_PATHCHECK and commands or directories found through relative
+//! or world-writable PATH and CDPATH entries.
+
+mod common;
+
+use std::fs::{self, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use common::run;
+
+/// Relative entries resolve against the working directory, which is the
+/// process's and so shared by every test here.
+static CWD: Mutex<()> = Mutex::new(());
+
+/// A fresh directory for `test` with a `tool` command and a `sub`
+/// directory in each of `trusted`, `relative` and the world-writable
+/// `open`.
+fn fixture(test: &str) -> PathBuf {
+    let base = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("pathcheck").join(test);
+    let _ = fs::remove_dir_all(&base);
+    for name in ["trusted", "relative", "open"] {
+        let dir = base.join(name);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let tool = dir.join("tool");
+        fs::write(&tool, format!("#!/bin/sh\necho {}\n", name)).unwrap();
+        fs::set_permissions(&tool, Permissions::from_mode(0o755)).unwrap();
+    }
+    fs::set_permissions(base.join("open"), Permissions::from_mode(0o777)).unwrap();
+    base
+}
+
+/// Runs `script` in `base` with PATH, CDPATH and SHELLOB_PATHCHECK set.
+fn run_in(base: &Path, path: &str, cdpath: &str, check: &str, script: &str) -> (i32, String, String) {
+    let _cwd = CWD.lock().unwrap_or_else(|e| e.into_inner());
+    let base = base.display();
+    let path = path.replace("BASE", &base.to_string());
+    let cdpath = cdpath.replace("BASE", &base.to_string());
+    run(&format!(
+        "cd {base}\nPATH={path}\nCDPATH={cdpath}\nSHELLOB_PATHCHECK={check}\n{script}"
+    ))
+}
+
+#[test]
+fn unchecked_by_default() {
+    let base = fixture("unchecked");
+    let (status, stdout, stderr) = run_in(&base, "relative:BASE/trusted", "", "", "tool");
+    assert_eq!((status, stdout.as_str(), stderr.as_str()), (0, "relative\n", ""));
+}
+
+#[test]
+fn warns_about_relative_path_entries() {
+    let base = fixture("relative-warn");
+    let (status, stdout, stderr) = run_in(&base, "relative:BASE/trusted", "", "warn", "tool");
+    assert_eq!((status, stdout.as_str()), (0, "relative\n"));
+    assert_eq!(
+        stderr,
+        format!("shellob: {}/relative/tool: found via relative PATH entry 'relative'\n", base.display())
+    );
+}
+
+#[test]
+fn refuses_relative_path_entries_for_later_ones() {
+    let base = fixture("relative-refuse");
+    let (status, stdout, stderr) = run_in(&base, "relative:BASE/trusted", "", "refuse", "tool");
+    assert_eq!((status, stdout.as_str()), (0, "trusted\n"));
+    assert!(stderr.contains("found via relative PATH entry 'relative'"), "{:?}", stderr);
+
+    // The empty entry is the working directory, and so relative too
+    let (_, stdout, stderr) = run_in(&base, ":BASE/trusted", "", "refuse", "cd relative; tool");
+    assert_eq!(stdout, "trusted\n");
+    assert!(stderr.contains("found via relative PATH entry ''"), "{:?}", stderr);
+}
+
+#[test]
+fn checks_world_writable_path_entries() {
+    let base = fixture("open");
+    let (_, stdout, stderr) = run_in(&base, "BASE/open:BASE/trusted", "", "warn", "tool");
+    assert_eq!(stdout, "open\n");
+    assert!(stderr.contains("found via world-writable PATH entry"), "{:?}", stderr);
+
+    let (_, stdout, stderr) = run_in(&base, "BASE/open:BASE/trusted", "", "refuse", "tool");
+    assert_eq!(stdout, "trusted\n");
+    assert!(stderr.contains("found via world-writable PATH entry"), "{:?}", stderr);
+
+    // With nothing after it, a refused command isn't found at all
+    let (status, stdout, _) = run_in(&base, "BASE/open", "", "refuse", "tool");
+    assert_eq!((status, stdout.as_str()), (127, ""));
+}
+
+#[test]
+fn checks_cdpath_entries() {
+    let base = fixture("cdpath");
+    let b = base.display();
+    let cd = |cdpath, check, dir| run_in(&base, "/usr/bin:/bin", cdpath, check, &format!("cd {dir} >/dev/null; pwd"));
+
+    let (_, stdout, stderr) = cd("BASE/open:BASE/trusted", "warn", "sub");
+    assert_eq!(stdout, format!("{b}/open/sub\n"));
+    assert!(stderr.contains("found via world-writable CDPATH entry"), "{:?}", stderr);
+
+    let (_, stdout, _) = cd("BASE/open:BASE/trusted", "refuse", "sub");
+    assert_eq!(stdout, format!("{b}/trusted/sub\n"));
+
+    let (_, stdout, stderr) = cd("relative:BASE/trusted", "refuse", "sub");
+    assert_eq!(stdout, format!("{b}/trusted/sub\n"));
+    assert!(stderr.contains("found via relative CDPATH entry 'relative'"), "{:?}", stderr);
+
+    // The working directory itself is where cd looks anyway
+    let (_, stdout, stderr) = cd(":BASE/trusted", "refuse", "open");
+    assert_eq!((stdout, stderr), (format!("{b}/open\n"), String::new()));
+}
+
+#[test]
+fn runs_paths_without_searching() {
+    let base = fixture("direct");
+    let (status, stdout, stderr) = run_in(&base, "", "", "refuse", "cd trusted; ./tool; /bin/echo absolute");
+    assert_eq!((status, stdout.as_str(), stderr.as_str()), (0, "trusted\nabsolute\n", ""));
+
+    // Not even an untrusted PATH entry is blamed for them
+    let (_, stdout, stderr) = run_in(&base, "BASE/open:relative:/bin", "", "warn", "open/tool | cat; relative/tool");
+    assert_eq!((stdout.as_str(), stderr.as_str()), ("open\nrelative\n", ""));
+
+    let (status, stdout, stderr) = run_in(&base, "relative", "", "refuse", "type relative/tool; hash ./trusted/tool");
+    assert_eq!((status, stdout.as_str(), stderr.as_str()), (0, "relative/tool is relative/tool\n", ""));
+
+    let (status, _, stderr) = run_in(&base, "BASE/trusted", "", "", "./tool");
+    assert_eq!(status, 127);
+    assert!(stderr.starts_with("shellob: ./tool: No such file or directory"), "{:?}", stderr);
+}
@@ -0,0 +1,86 @@
+//! `source` and `.`, and how `return` and `exit` in a sourced file end
+//! it.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::run;
+
+/// Writes `script` to a file named `name` for a test to source.
+fn script(name: &str, script: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("source");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, script).unwrap();
+    path
+}
+
+#[test]
+fn runs_the_file_in_this_shell() {
+    let path = script("sets.sh", "greeting=hello\ncd /\n");
+    let (status, stdout, _) = run(&format!("source {}; echo $greeting; pwd", path.display()));
+    assert_eq!((status, stdout.as_str()), (0, "hello\n/\n"));
+
+    let (_, stdout, _) = run(&format!(". {}; echo $greeting", path.display()));
+    assert_eq!(stdout, "hello\n");
+}
+
+#[test]
+fn return_stops_only_the_file() {
+    let path = script("returns.sh", "echo before\nreturn 3\necho after\n");
+    let (status, stdout, _) = run(&format!("source {}; echo \"status $?\"", path.display()));
+    assert_eq!((status, stdout.as_str()), (0, "before\nstatus 3\n"));
+
+    // Without a status, the last command's
+    let path = script("returns-last.sh", "false || return\necho after\n");
+    let (_, stdout, _) = run(&format!(". {}; echo $?", path.display()));
+    assert_eq!(stdout, "1\n");
+}
+
+#[test]
+fn exit_stops_the_shell() {
+    let path = script("exits.sh", "echo before\nexit 3\necho after\n");
+    let (status, stdout, _) = run(&format!("source {}\necho still running", path.display()));
+    assert_eq!((status, stdout.as_str()), (3, "before\n"));
+
+    // Nested, from inside a list
+    let outer = script("outer.sh", &format!("source {} && echo and\necho outer", path.display()));
+    let (status, stdout, _) = run(&format!("source {}; echo top", outer.display()));
+    assert_eq!((status, stdout.as_str()), (3, "before\n"));
+
+    // And the process running it
+    let main = script("main.sh", &format!(". {}\necho still running", path.display()));
+    let output = Command::new(env!("CARGO_BIN_EXE_shellob")).arg(&main).output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(output.stdout, b"before\n");
+}
+
+#[test]
+fn sets_positional_parameters_while_it_runs() {
+    let path = script("args.sh", "echo $# $1 $2\n");
+    let (_, stdout, _) = run(&format!("set -- x\n. {} a b\necho $# $1", path.display()));
+    assert_eq!(stdout, "2 a b\n1 x\n");
+}
+
+#[test]
+fn reports_misuse() {
+    let (status, _, stderr) = run("return 1");
+    assert_eq!(status, 1);
+    assert_eq!(stderr, "shellob: return: can only `return' from a sourced file\n");
+
+    let (status, _, stderr) = run("source /no/such/file");
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("shellob: /no/such/file: "), "{:?}", stderr);
+
+    assert_eq!(run("source").0, 2);
+}
+
+#[test]
+fn numbers_lines_from_the_start_of_the_file() {
+    let path = script("lineno.sh", "\necho $LINENO\n");
+    let (_, stdout, _) = run(&format!("echo $LINENO\nsource {}\necho $LINENO", path.display()));
+    assert_eq!(stdout, "1\n2\n3\n");
+}
@@ -0,0 +1,48 @@
+//! Which startup files the `shellob` binary runs, and the options that
+//! leave them out.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Runs the shell with `options` and a home directory with a
+/// `.shellobrc` and a `.profile` in it, returning which of them set
+/// their variable.
+fn startup(options: &[&str]) -> String {
+    // A home of its own, so tests running at once don't write the same
+    // files
+    let home = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("startup").join(options.concat());
+    fs::create_dir_all(&home).unwrap();
+    fs::write(home.join(".shellobrc"), "from_rc=rc\n").unwrap();
+    fs::write(home.join(".profile"), "from_profile=profile\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shellob"))
+        .args(options)
+        .env("HOME", &home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"echo \"ran [$from_rc $from_profile]\"\n").unwrap();
+    let stdout = String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap();
+    // Past the prompt, if one was shown
+    let ran = stdout.split("ran [").nth(1).unwrap_or_else(|| panic!("{:?}", stdout));
+    ran[..ran.find(']').unwrap()].trim().to_string()
+}
+
+#[test]
+fn runs_the_rc_file_when_interactive() {
+    assert_eq!(startup(&["-i"]), "rc");
+    assert_eq!(startup(&["-i", "--norc"]), "");
+    // Nor is it run for commands that come from a pipe
+    assert_eq!(startup(&[]), "");
+}
+
+#[test]
+fn runs_the_profile_when_logging_in() {
+    assert_eq!(startup(&["-l"]), "profile");
+    assert_eq!(startup(&["--login", "--noprofile"]), "");
+    assert_eq!(startup(&["-il"]), "rc profile");
+    assert_eq!(startup(&["-il", "--norc", "--noprofile"]), "");
+}
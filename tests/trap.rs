@@ -0,0 +1,60 @@
+//! `trap`, run in the `shellob` binary since it changes how the whole
+//! process handles signals.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Runs `script` as a script file named `name`, returning its status,
+/// stdout and stderr.
+fn run_script(name: &str, script: &str) -> (i32, String, String) {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("trap");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.sh", name));
+    fs::write(&path, script).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_shellob")).arg(&path).output().unwrap();
+    let text = |bytes: Vec<u8>| String::from_utf8(bytes).unwrap();
+    (output.status.code().unwrap(), text(output.stdout), text(output.stderr))
+}
+
+#[test]
+fn prints_traps_as_the_commands_that_set_them() {
+    let traps = "trap -- 'echo \"it'\\''s\" done' EXIT\ntrap -- 'echo usr' SIGUSR1\ntrap -- '' SIGTERM\n";
+    let set = "trap \"echo \\\"it's\\\" done\" EXIT\ntrap 'echo usr' usr1\ntrap '' 15\ntrap -p\n";
+    let (_, stdout, _) = run_script("print", set);
+    assert_eq!(stdout, format!("{}it's done\n", traps));
+
+    // Run again, they set the same traps
+    let (_, stdout, _) = run_script("reprint", &format!("{}trap -p; trap - EXIT", traps));
+    assert_eq!(stdout, traps);
+
+    let (status, stdout, stderr) = run_script("named", "trap 'echo usr' USR1 USR2\ntrap -p SIGUSR2 bogus");
+    assert_eq!((status, stdout.as_str()), (1, "trap -- 'echo usr' SIGUSR2\n"));
+    assert_eq!(stderr, "shellob: trap: bogus: invalid signal specification\n");
+}
+
+#[test]
+fn runs_traps_when_signals_arrive() {
+    // `$?` is left for the commands after the trap
+    let script = "trap 'echo caught; false' USR1\nkill -USR1 $$; echo $?\ntrap - USR1\ntrap -p\ntrap '' USR1\nkill -USR1 $$; echo ignored";
+    let (status, stdout, _) = run_script("signals", script);
+    assert_eq!((status, stdout.as_str()), (0, "caught\n0\nignored\n"));
+}
+
+#[test]
+fn runs_the_exit_trap_as_the_shell_exits() {
+    let (status, stdout, _) = run_script("exit", "trap 'echo bye $?' EXIT\n(trap 'echo sub' EXIT)\nexit 3");
+    assert_eq!((status, stdout.as_str()), (3, "sub\nbye 3\n"));
+    // An `exit` in the trap has the last word
+    let (status, stdout, _) = run_script("exit-status", "trap 'echo bye; exit 4' 0\necho hi");
+    assert_eq!((status, stdout.as_str()), (4, "hi\nbye\n"));
+}
+
+#[test]
+fn lists_signals() {
+    let (_, stdout, _) = run_script("list", "trap -l");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 31);
+    assert_eq!(lines[..2], [" 1) SIGHUP", " 2) SIGINT"]);
+    assert_eq!(lines[14], "15) SIGTERM");
+}